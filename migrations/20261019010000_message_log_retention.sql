@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE log_retention (
+    guild_id bigint PRIMARY KEY,
+    days int NOT NULL
+);
+
+CREATE INDEX log_messages_author_id_idx ON log_messages (author_id);
+CREATE INDEX log_messages_creation_timestamp_idx ON log_messages (creation_timestamp);
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE attachment_archive_guilds (
+    guild_id bigint PRIMARY KEY
+);
+
+CREATE TABLE archived_attachments (
+    id serial PRIMARY KEY,
+    message_id bigint NOT NULL,
+    guild_id bigint NOT NULL,
+    author_id bigint NOT NULL,
+    filename text NOT NULL,
+    hash text NOT NULL,
+    size bigint NOT NULL,
+    archived_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX archived_attachments_message_id_idx ON archived_attachments (message_id);
+CREATE INDEX archived_attachments_hash_idx ON archived_attachments (hash);
+CREATE INDEX archived_attachments_author_id_idx ON archived_attachments (author_id);
@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE log_messages ADD COLUMN deleted_timestamp timestamptz;
+
+-- array_to_string() is only marked as stable, but it's immutable for text arrays,
+-- so it's wrapped to be usable on an index.
+CREATE FUNCTION log_messages_search_vector(content text, content_history text[]) RETURNS tsvector AS $$
+    SELECT to_tsvector('simple', coalesce(content, '') || ' ' || coalesce(array_to_string(content_history, ' '), ''))
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE INDEX log_messages_search_idx ON log_messages USING GIN (log_messages_search_vector(content, content_history));
+CREATE INDEX log_messages_guild_id_idx ON log_messages (guild_id, creation_timestamp);
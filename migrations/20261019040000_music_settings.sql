@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE music_settings (
+    guild_id bigint PRIMARY KEY,
+    stay_connected bool NOT NULL DEFAULT false
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE music_settings ADD COLUMN volume int NOT NULL DEFAULT 100;
+ALTER TABLE music_settings ADD COLUMN equalizer float8[];
+ALTER TABLE music_settings ADD COLUMN filter text;
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE playlists (
+    id serial PRIMARY KEY,
+    owner_id bigint NOT NULL,
+    guild_playlist bool NOT NULL,
+    name text NOT NULL,
+    tracks text[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX playlists_owner_name_idx ON playlists (owner_id, guild_playlist, lower(name));
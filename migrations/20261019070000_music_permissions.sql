@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE music_settings
+    ADD COLUMN dj_role bigint,
+    ADD COLUMN vote_ratio int NOT NULL DEFAULT 50,
+    ADD COLUMN max_user_tracks int,
+    ADD COLUMN max_track_length int;
@@ -0,0 +1,29 @@
+-- Add migration script here
+CREATE TABLE osu_tracked_players (
+    osu_id bigint NOT NULL,
+    mode int NOT NULL,
+    osu_username text NOT NULL,
+    global_rank bigint,
+    pp double precision NOT NULL DEFAULT 0,
+    -- The players are checked in batches, the ones checked the longest ago first.
+    last_checked timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (osu_id, mode)
+);
+
+CREATE TABLE osu_tracked_channels (
+    guild_id bigint NOT NULL,
+    channel_id bigint NOT NULL,
+    osu_id bigint NOT NULL,
+    mode int NOT NULL,
+    PRIMARY KEY (guild_id, osu_id, mode),
+    FOREIGN KEY (osu_id, mode) REFERENCES osu_tracked_players ON DELETE CASCADE
+);
+
+-- The top plays that were already seen, so they are only announced once.
+CREATE TABLE osu_tracked_scores (
+    osu_id bigint NOT NULL,
+    mode int NOT NULL,
+    score_id bigint NOT NULL,
+    PRIMARY KEY (mode, score_id),
+    FOREIGN KEY (osu_id, mode) REFERENCES osu_tracked_players ON DELETE CASCADE
+);
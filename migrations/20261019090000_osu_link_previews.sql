@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Guilds and channels where the osu! link previews were toggled, the channels take priority.
+CREATE TABLE osu_link_previews (
+    target_id bigint PRIMARY KEY NOT NULL,
+    is_guild bool NOT NULL,
+    enabled bool NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- The daily rank and pp of the linked osu! users, for the history graphs.
+CREATE TABLE osu_user_snapshots (
+    osu_id bigint NOT NULL,
+    mode int NOT NULL,
+    day date NOT NULL DEFAULT CURRENT_DATE,
+    global_rank bigint,
+    -- Without pp for the users that were not found, so they are only requested once a day.
+    pp double precision,
+    PRIMARY KEY (osu_id, mode, day)
+);
@@ -1,6 +1,13 @@
 use crate::{
-    global_data::*, notifications::Post, utils::booru, utils::checks::*,
-    utils::logging::LoggingEvents, MASTER_GROUP,
+    global_data::*,
+    logging::attachments::guild_archives_attachments,
+    notifications::Post,
+    utils::basic_functions::string_to_seconds,
+    utils::booru,
+    utils::checks::*,
+    utils::logging::{guild_logging_channel, LoggingChannels, LoggingEvents, LOGGING_EVENT_NAMES},
+    utils::osu_preview::osu_previews_enabled,
+    MASTER_GROUP,
 };
 
 use std::time::Duration;
@@ -11,12 +18,15 @@ use futures::TryStreamExt;
 use reqwest::Url;
 
 use regex::Regex;
+use uuid::Uuid;
 
 use serenity::{
     framework::standard::{macros::command, Args, CommandResult, Delimiter},
     model::channel::Channel,
-    model::channel::{Message, ReactionType},
+    model::channel::{GuildChannel, Message, ReactionType},
     model::id::RoleId,
+    model::interactions::message_component::*,
+    model::interactions::InteractionResponseType,
     model::webhook::Webhook,
     prelude::Context,
     utils::{content_safe, ContentSafeOptions},
@@ -307,10 +317,12 @@ async fn booru(ctx: &Context, msg: &Message, raw_args: Args) -> CommandResult {
 /// Configurable aspects:
 /// `toggle_annoy`: Toggles the annoying features on or off.
 /// `notifications`: Configure the notifications for YandeRe posts or Twitch livestreams.
+/// `logging`: Configure the guild events that get logged to the channel.
+/// `osu_previews`: Toggles the previews of the osu! links sent on the channel.
 #[command]
 #[required_permissions(MANAGE_CHANNELS)]
 #[only_in("guilds")]
-#[sub_commands(toggle_annoy, notifications, logging)]
+#[sub_commands(toggle_annoy, notifications, logging, channel_osu_previews)]
 #[aliases(chan)]
 async fn channel(_ctx: &Context, _message: &Message, _args: Args) -> CommandResult {
     Ok(())
@@ -1062,11 +1074,31 @@ async fn toggle_annoy(ctx: &Context, msg: &Message, _args: Args) -> CommandResul
 /// `disable_command`: Disables a command.
 /// `enable_command`: Enables a disabled command.
 /// `toggle_anti_spam`: Enables or Disables antispam.
+/// `log_retention`: Sets for how many days logged messages are kept.
+/// `archive_attachments`: Toggles archiving the attachments of logged messages.
+/// `dj_role`: Sets the role that can control the music.
+/// `vote_ratio`: Sets the percentage of listeners that need to vote to skip or stop.
+/// `queue_limit`: Sets how many tracks each user can queue.
+/// `max_track_length`: Sets the max length of the queued tracks.
+/// `osu_previews`: Toggles the previews of the osu! links sent on the guild.
 #[command]
 #[required_permissions(MANAGE_GUILD)]
 #[only_in("guilds")]
 #[aliases(server)]
-#[sub_commands(prefix, mute_role, disable_command, enable_command, toggle_anti_spam)]
+#[sub_commands(
+    prefix,
+    mute_role,
+    disable_command,
+    enable_command,
+    toggle_anti_spam,
+    log_retention,
+    archive_attachments,
+    dj_role,
+    vote_ratio,
+    queue_limit,
+    max_track_length,
+    guild_osu_previews
+)]
 async fn guild(_ctx: &Context, _msg: &Message, _args: Args) -> CommandResult {
     Ok(())
 }
@@ -1337,55 +1369,640 @@ async fn toggle_anti_spam(ctx: &Context, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-/// WIP: Configures logging for the channel.
+/// Sets for how many days the messages stored for logging are kept on this guild.
+/// Older messages get deleted periodically, and their edits and deletions will no longer be logged.
 ///
-/// Usage: `configure channel logging 134217727`
+/// By default messages are kept forever.
+///
+/// Usage:
+/// `config guild log_retention 30`
+/// `config guild log_retention off`
 #[command]
-#[aliases("logs")]
 #[min_args(1)]
-async fn logging(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let digits = args.single::<u64>()?;
+#[aliases(logretention, retention, log_retention_days)]
+async fn log_retention(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
 
-    let channel = msg.channel(ctx).await.unwrap();
+    let guild_id = msg.guild_id.unwrap().0 as i64;
 
-    if let Channel::Guild(channel) = channel {
-        let hook = match channel
-            .create_webhook_with_avatar(
-                ctx,
-                "Robo Arc - Logging",
-                ctx.cache.current_user().await.face().as_str(),
-            )
-            .await
-        {
-            Err(why) => {
-                msg.reply(ctx, format!("Could not create a webhook, please provide the bot access to manage webhooks in this channel.\n{}", why)).await?;
+    let days = match args.single::<String>()?.to_lowercase().as_str() {
+        "off" | "forever" | "disable" => None,
+        x => match x.parse::<i32>() {
+            Ok(days) if days > 0 => Some(days),
+            _ => {
+                msg.reply(ctx, "Please, provide a valid number of days, or `off`.")
+                    .await?;
                 return Ok(());
             }
-            Ok(x) => x,
-        };
+        },
+    };
 
-        let pool = {
-            let data_read = ctx.data.read().await;
-            data_read.get::<DatabasePool>().unwrap().clone()
-        };
+    if let Some(days) = days {
+        sqlx::query!(
+            "INSERT INTO log_retention (guild_id, days) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET days = $2",
+            guild_id,
+            days
+        )
+        .execute(&pool)
+        .await?;
+
+        msg.reply(
+            ctx,
+            format!("Logged messages will now be kept for {} days.", days),
+        )
+        .await?;
+    } else {
+        sqlx::query!("DELETE FROM log_retention WHERE guild_id = $1", guild_id)
+            .execute(&pool)
+            .await?;
 
+        msg.reply(ctx, "Logged messages will now be kept forever.")
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Toggles archiving the attachments of the messages sent on this guild.
+///
+/// Discord attachment links stop working shortly after a message is deleted, so with this
+/// enabled, the attachments get stored by the bot and are included when logging a deleted message.
+/// Archived attachments are deleted after a while, and big files are not archived.
+#[command]
+#[aliases(archiveattachments, archive, attachment_archive)]
+async fn archive_attachments(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let guild_id = msg.guild_id.unwrap().0;
+
+    if guild_archives_attachments(&pool, guild_id).await {
         sqlx::query!(
-            "INSERT INTO logging_channels (guild_id, webhook_url, bitwise) VALUES ($1, $2, $3)",
-            msg.guild_id.unwrap().0 as i64,
-            hook.url().unwrap(),
-            digits as i64
+            "DELETE FROM attachment_archive_guilds WHERE guild_id = $1",
+            guild_id as i64
         )
         .execute(&pool)
         .await?;
 
-        let events = LoggingEvents::from_bits_truncate(digits);
+        msg.reply(ctx, "Attachments will no longer be archived.")
+            .await?;
+    } else {
+        sqlx::query!(
+            "INSERT INTO attachment_archive_guilds (guild_id) VALUES ($1)",
+            guild_id as i64
+        )
+        .execute(&pool)
+        .await?;
+
+        msg.reply(ctx, "Attachments will now be archived for message logs.")
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Sets the DJ role of the server, or `off` to let everyone control the music.
+///
+/// DJs, members with the `Manage Server` permission and whoever requested the current track can
+/// control the playback freely, while other listeners need to vote to skip or stop.
+///
+/// Usage:
+/// `config guild dj_role @DJ`
+/// `config guild dj_role off`
+#[command]
+#[min_args(1)]
+#[aliases(djrole, dj)]
+async fn dj_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let role = if args.current().map(|i| i.to_lowercase()) == Some("off".to_string()) {
+        None
+    } else if let Ok(x) = args.single::<RoleId>() {
+        Some(x.0 as i64)
+    } else {
         msg.reply(
             ctx,
-            format!("Successfully added logging for this events:\n{:?}", events),
+            "An invalid role was provided, please mention the role or post it's id.",
         )
         .await?;
+        return Ok(());
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    sqlx::query!(
+        "INSERT INTO music_settings (guild_id, dj_role) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET dj_role = $2",
+        msg.guild_id.unwrap().0 as i64,
+        role
+    )
+    .execute(&pool)
+    .await?;
+
+    msg.react(ctx, '👍').await?;
+
+    Ok(())
+}
+
+/// Sets the percentage of listeners that need to vote to skip or stop the music.
+///
+/// Usage: `config guild vote_ratio 50`
+#[command]
+#[num_args(1)]
+#[aliases(voteratio, vote_skip, voteskip)]
+async fn vote_ratio(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let ratio = match args
+        .single::<String>()?
+        .trim_end_matches('%')
+        .parse::<i32>()
+    {
+        Ok(x) if (1..=100).contains(&x) => x,
+        _ => {
+            msg.reply(ctx, "Please, provide a percentage from 1 to 100.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    sqlx::query!(
+        "INSERT INTO music_settings (guild_id, vote_ratio) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET vote_ratio = $2",
+        msg.guild_id.unwrap().0 as i64,
+        ratio
+    )
+    .execute(&pool)
+    .await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "{}% of the listeners will now need to vote to skip or stop.",
+            ratio
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sets how many tracks each user can have on the queue, or `off` for no limit.
+/// DJs are not limited.
+///
+/// Usage: `config guild queue_limit 10`
+#[command]
+#[num_args(1)]
+#[aliases(queuelimit, max_queue)]
+async fn queue_limit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let limit = match args.single::<String>()?.to_lowercase().as_str() {
+        "off" | "none" | "disable" => None,
+        x => match x.parse::<i32>() {
+            Ok(limit) if limit > 0 => Some(limit),
+            _ => {
+                msg.reply(ctx, "Please, provide a valid number of tracks, or `off`.")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    sqlx::query!(
+        "INSERT INTO music_settings (guild_id, max_user_tracks) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET max_user_tracks = $2",
+        msg.guild_id.unwrap().0 as i64,
+        limit
+    )
+    .execute(&pool)
+    .await?;
+
+    msg.react(ctx, '👍').await?;
+
+    Ok(())
+}
+
+/// Sets the max length of the tracks that can be queued, or `off` for no limit.
+/// DJs are not limited.
+///
+/// Usage:
+/// `config guild max_track_length 10m`
+/// `config guild max_track_length 1h 30m`
+#[command]
+#[min_args(1)]
+#[aliases(maxtracklength, max_length, maxlength)]
+async fn max_track_length(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let length = match args.message().to_lowercase().as_str() {
+        "off" | "none" | "disable" => None,
+        _ => match string_to_seconds(args.message()) {
+            0 => {
+                msg.reply(
+                    ctx,
+                    "Please, provide a valid length, like `10m` or `1h 30m`, or `off`.",
+                )
+                .await?;
+                return Ok(());
+            }
+            seconds => Some(seconds.min(i32::MAX as u64) as i32),
+        },
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    sqlx::query!(
+        "INSERT INTO music_settings (guild_id, max_track_length) VALUES ($1, $2) ON CONFLICT (guild_id) DO UPDATE SET max_track_length = $2",
+        msg.guild_id.unwrap().0 as i64,
+        length
+    )
+    .execute(&pool)
+    .await?;
+
+    msg.react(ctx, '👍').await?;
+
+    Ok(())
+}
+
+/// Obtains a logging webhook for the channel, reusing the currently configured one if it
+/// already belongs to this channel.
+async fn logging_webhook(
+    ctx: &Context,
+    msg: &Message,
+    channel: &GuildChannel,
+    current: Option<&LoggingChannels>,
+) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(current) = current {
+        let mut split = current.webhook_url.split('/');
+        let id = split
+            .nth(5)
+            .unwrap_or("0")
+            .parse::<u64>()
+            .unwrap_or_default();
+        let token = split.next().unwrap_or_default();
+
+        if let Ok(hook) = ctx.http.get_webhook_with_token(id, token).await {
+            if hook.channel_id == channel.id {
+                return Ok(Some(current.webhook_url.to_string()));
+            }
+        }
+    }
+
+    match channel
+        .create_webhook_with_avatar(
+            ctx,
+            "Robo Arc - Logging",
+            ctx.cache.current_user().await.face().as_str(),
+        )
+        .await
+    {
+        Err(why) => {
+            msg.reply(ctx, format!("Could not create a webhook, please provide the bot access to manage webhooks in this channel.\n{}", why)).await?;
+            Ok(None)
+        }
+        Ok(x) => Ok(Some(x.url()?)),
+    }
+}
+
+/// Shows a select menu with all the loggable events, with the currently enabled ones selected.
+///
+/// Returns the selected events, or None if the menu timed out or was cancelled.
+async fn logging_menu(
+    ctx: &Context,
+    msg: &Message,
+    current: LoggingEvents,
+) -> Result<Option<LoggingEvents>, Box<dyn std::error::Error + Send + Sync>> {
+    let uuid_select = Uuid::new_v4().to_string();
+    let uuid_save = Uuid::new_v4().to_string();
+    let uuid_cancel = Uuid::new_v4().to_string();
+
+    let mut events = current;
+
+    let mut message = msg
+        .channel_id
+        .send_message(ctx, |m| m.content("Loading logging configuration..."))
+        .await?;
+
+    let result = loop {
+        message
+            .edit(ctx, |m| {
+                m.content(format!("<@{}>", msg.author.id));
+                m.embed(|e| {
+                    e.title("Logging Configuration");
+                    e.description(if events.is_empty() {
+                        "No events are being logged.".to_string()
+                    } else {
+                        format!(
+                            "Events logged on this channel:\n`{}`",
+                            events.names().join("`, `")
+                        )
+                    });
+                    e.footer(|f| f.text("Select the events to log and press Save."))
+                });
+                m.components(|c| {
+                    c.create_action_row(|ar| {
+                        ar.create_select_menu(|sm| {
+                            sm.placeholder("Events to log...");
+                            sm.min_values(0);
+                            sm.max_values(LOGGING_EVENT_NAMES.len() as u64);
+                            sm.custom_id(&uuid_select);
+
+                            sm.options(|o| {
+                                for (name, event) in LOGGING_EVENT_NAMES.iter() {
+                                    o.create_option(|o| {
+                                        o.label(name);
+                                        o.value(name);
+                                        o.default_selection(events.contains(*event));
+                                        o
+                                    });
+                                }
+                                o
+                            });
+                            sm
+                        });
+                        ar
+                    });
+                    c.create_action_row(|ar| {
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Success);
+                            b.label("Save");
+                            b.emoji(ReactionType::Unicode("✅".to_string()));
+                            b.custom_id(&uuid_save)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Danger);
+                            b.label("Cancel");
+                            b.emoji(ReactionType::Unicode("❌".to_string()));
+                            b.custom_id(&uuid_cancel)
+                        });
+                        ar
+                    });
+                    c
+                });
+                m
+            })
+            .await?;
+
+        let mov_uuid_select = uuid_select.clone();
+        let mov_uuid_save = uuid_save.clone();
+        let mov_uuid_cancel = uuid_cancel.clone();
+
+        let mci = message
+            .await_component_interaction(ctx)
+            .author_id(msg.author.id.0)
+            .timeout(Duration::from_secs(120))
+            .filter(move |mci| match mci.data.component_type {
+                ComponentType::SelectMenu => mci.data.custom_id == mov_uuid_select,
+                ComponentType::Button => {
+                    mci.data.custom_id == mov_uuid_save || mci.data.custom_id == mov_uuid_cancel
+                }
+                _ => false,
+            })
+            .await;
+
+        if let Some(mci) = mci {
+            mci.create_interaction_response(ctx, |ir| {
+                ir.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await?;
+
+            if mci.data.custom_id == uuid_select {
+                events = mci
+                    .data
+                    .values
+                    .iter()
+                    .filter_map(|i| LoggingEvents::from_event_name(i))
+                    .collect();
+            } else if mci.data.custom_id == uuid_save {
+                break Some(events);
+            } else {
+                break None;
+            }
+        } else {
+            break None;
+        }
+    };
+
+    message
+        .edit(ctx, |m| {
+            m.content(format!("<@{}>: Done.", msg.author.id));
+            m.components(|c| c)
+        })
+        .await?;
+
+    Ok(result)
+}
+
+/// Configures logging for the channel.
+///
+/// Events can be given by category: `messages`, `members`, `roles`, `channels`, `reactions`,
+/// `voice`, `guild` or `all`; or by their name, like `message_delete`.
+/// Prefixing with `+` or `-` adds or removes the events from the current configuration,
+/// otherwise the configuration gets replaced.
+///
+/// Calling it without arguments shows an interactive configurator.
+///
+/// Usage:
+/// `configure channel logging`
+/// `configure channel logging messages members`
+/// `configure channel logging +roles -reaction_add`
+/// `configure channel logging off`
+#[command]
+#[aliases("logs")]
+async fn logging(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let channel = if let Channel::Guild(channel) = msg.channel(ctx).await.unwrap() {
+        channel
     } else {
         msg.reply(ctx, "Invalid Channel Type").await?;
+        return Ok(());
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let guild_id = msg.guild_id.unwrap();
+    let current = guild_logging_channel(&pool, guild_id).await;
+    let current_events = current
+        .as_ref()
+        .map(|i| LoggingEvents::from_bits_truncate(i.bitwise as u64))
+        .unwrap_or_else(LoggingEvents::empty);
+
+    let events = if args.is_empty() {
+        if let Some(x) = logging_menu(ctx, msg, current_events).await? {
+            x
+        } else {
+            return Ok(());
+        }
+    } else if ["off", "disable", "none"].contains(&args.message().to_lowercase().as_str()) {
+        sqlx::query!(
+            "DELETE FROM logging_channels WHERE guild_id = $1",
+            guild_id.0 as i64
+        )
+        .execute(&pool)
+        .await?;
+
+        msg.reply(ctx, "Successfully disabled logging on this guild.")
+            .await?;
+        return Ok(());
+    } else {
+        let mut events = LoggingEvents::empty();
+
+        for (index, arg) in args.raw().enumerate() {
+            // Backwards compatibility with the raw bitmask.
+            if let Ok(digits) = arg.parse::<u64>() {
+                events |= LoggingEvents::from_bits_truncate(digits);
+                continue;
+            }
+
+            let (toggle, name) = match arg.chars().next() {
+                Some('+') => (Some(true), &arg[1..]),
+                Some('-') => (Some(false), &arg[1..]),
+                _ => (None, arg),
+            };
+
+            if index == 0 && toggle.is_some() {
+                events = current_events;
+            }
+
+            let named = if let Some(x) = LoggingEvents::from_name(name) {
+                x
+            } else {
+                msg.reply(ctx, format!("Unknown event or category: `{}`\nThe available categories are `messages`, `members`, `roles`, `channels`, `reactions`, `voice`, `guild` and `all`", name.replace('`', ""))).await?;
+                return Ok(());
+            };
+
+            events.set(named, toggle.unwrap_or(true));
+        }
+
+        events
+    };
+
+    let webhook_url = if let Some(x) = logging_webhook(ctx, msg, &channel, current.as_ref()).await?
+    {
+        x
+    } else {
+        return Ok(());
+    };
+
+    sqlx::query!(
+        "INSERT INTO logging_channels (guild_id, webhook_url, bitwise) VALUES ($1, $2, $3) ON CONFLICT (guild_id) DO UPDATE SET webhook_url = $2, bitwise = $3",
+        guild_id.0 as i64,
+        &webhook_url,
+        events.bits() as i64
+    )
+    .execute(&pool)
+    .await?;
+
+    if events.is_empty() {
+        msg.reply(
+            ctx,
+            "Logging is configured on this channel, but no events are being logged.",
+        )
+        .await?;
+    } else {
+        msg.reply(
+            ctx,
+            format!(
+                "Successfully configured logging for this events:\n`{}`",
+                events.names().join("`, `")
+            ),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Toggles the previews of the osu! beatmap, beatmapset, user and score links sent on this guild.
+/// They are enabled by default, and the channels can be toggled separately with
+/// `config channel osu_previews`.
+#[command("osu_previews")]
+#[aliases(osupreviews, osu_preview, osu_links)]
+async fn guild_osu_previews(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let guild_id = msg.guild_id.unwrap().0 as i64;
+
+    let enabled = sqlx::query!(
+        "SELECT enabled FROM osu_link_previews WHERE target_id = $1",
+        guild_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .map(|i| i.enabled)
+    .unwrap_or(true);
+
+    sqlx::query!(
+        "INSERT INTO osu_link_previews (target_id, is_guild, enabled) VALUES ($1, $2, $3) ON CONFLICT (target_id) DO UPDATE SET enabled = $3",
+        guild_id,
+        true,
+        !enabled
+    )
+    .execute(&pool)
+    .await?;
+
+    if enabled {
+        msg.reply(ctx, "The osu! links sent on this guild will no longer have previews, except on the channels that enabled them.").await?;
+    } else {
+        msg.reply(
+            ctx,
+            "The osu! links sent on this guild will now have previews.",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Toggles the previews of the osu! beatmap, beatmapset, user and score links sent on this channel,
+/// over the setting of the guild.
+#[command("osu_previews")]
+#[aliases(osupreviews, osu_preview, osu_links)]
+async fn channel_osu_previews(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let enabled = osu_previews_enabled(&pool, msg.guild_id.unwrap().0, msg.channel_id.0).await;
+
+    sqlx::query!(
+        "INSERT INTO osu_link_previews (target_id, is_guild, enabled) VALUES ($1, $2, $3) ON CONFLICT (target_id) DO UPDATE SET enabled = $3",
+        msg.channel_id.0 as i64,
+        false,
+        !enabled
+    )
+    .execute(&pool)
+    .await?;
+
+    if enabled {
+        msg.reply(
+            ctx,
+            "The osu! links sent on this channel will no longer have previews.",
+        )
+        .await?;
+    } else {
+        msg.reply(
+            ctx,
+            "The osu! links sent on this channel will now have previews.",
+        )
+        .await?;
     }
 
     Ok(())
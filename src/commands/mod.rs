@@ -9,5 +9,8 @@ pub mod moderation;
 pub mod music;
 pub mod new_osu;
 pub mod osu;
+pub mod osu_tracking;
+pub mod playlist;
+pub mod privacy;
 pub mod sankaku;
 pub mod serenity_docs;
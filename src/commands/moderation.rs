@@ -2,14 +2,22 @@ use crate::global_data::DatabasePool;
 use crate::utils::basic_functions::string_to_seconds;
 use crate::utils::checks::BOT_HAS_MANAGE_ROLES_CHECK;
 
-use std::time::Duration;
+use std::{borrow::Cow, time::Duration};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{App, AppSettings, Arg};
+use uuid::Uuid;
 
 use serenity::{
+    builder::CreateEmbed,
     framework::standard::{macros::command, Args, CommandResult, Delimiter},
+    http::AttachmentType,
     model::{
-        channel::Message,
-        guild::Member,
-        id::{MessageId, UserId},
+        channel::{Message, ReactionType},
+        guild::{Guild, Member},
+        id::{ChannelId, MessageId, UserId},
+        interactions::message_component::*,
+        interactions::InteractionResponseType,
     },
     prelude::Context,
 };
@@ -543,3 +551,400 @@ async fn permanent_ban(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
 
     Ok(())
 }
+
+// How many messages are shown on each page of the `history` command.
+const HISTORY_PAGE_SIZE: usize = 10;
+// The maximum amount of messages a single `history` search returns.
+const HISTORY_MAX_RESULTS: i64 = 1000;
+
+struct HistoryEntry {
+    id: i64,
+    channel_id: i64,
+    author_id: i64,
+    content: Option<String>,
+    content_history: Option<Vec<String>>,
+    creation_timestamp: DateTime<Utc>,
+    edited_timestamp: Option<DateTime<Utc>>,
+    deleted_timestamp: Option<DateTime<Utc>>,
+}
+
+fn parse_history_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc))
+}
+
+fn history_embed(guild_id: u64, entries: &[HistoryEntry], index: usize, max: usize) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+
+    embed.title("Message History");
+    if entries.len() as i64 == HISTORY_MAX_RESULTS {
+        embed.description(format!(
+            "Showing the newest {} messages found.",
+            entries.len()
+        ));
+    } else {
+        embed.description(format!("{} messages found.", entries.len()));
+    }
+
+    for entry in entries
+        .iter()
+        .skip(index * HISTORY_PAGE_SIZE)
+        .take(HISTORY_PAGE_SIZE)
+    {
+        let mut status = String::new();
+        if entry.deleted_timestamp.is_some() {
+            status.push_str(" | 🗑️ Deleted");
+        }
+        if entry.edited_timestamp.is_some() {
+            status.push_str(" | ✏️ Edited");
+        }
+
+        let content = match entry.content.as_deref() {
+            Some(x) if !x.is_empty() => {
+                if x.chars().count() > 200 {
+                    format!("{}...", x.chars().take(200).collect::<String>())
+                } else {
+                    x.to_string()
+                }
+            }
+            _ => "*No content*".to_string(),
+        };
+
+        let location = if entry.deleted_timestamp.is_some() {
+            format!("<@{}> in <#{}>", entry.author_id, entry.channel_id)
+        } else {
+            format!(
+                "<@{}> in <#{}> - [Jump](https://discord.com/channels/{}/{}/{})",
+                entry.author_id, entry.channel_id, guild_id, entry.channel_id, entry.id
+            )
+        };
+
+        embed.field(
+            format!(
+                "{}{}",
+                entry.creation_timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                status
+            ),
+            format!("{}\n{}", location, content),
+            false,
+        );
+    }
+
+    embed.footer(|f| f.text(format!("Page {}/{}", index + 1, max + 1)));
+
+    embed
+}
+
+fn history_file(guild: &Guild, entries: &[HistoryEntry]) -> String {
+    let mut file = String::new();
+
+    for entry in entries {
+        let channel_name = guild
+            .channels
+            .get(&ChannelId(entry.channel_id as u64))
+            .map(|c| format!("#{}", c.name))
+            .unwrap_or_else(|| entry.channel_id.to_string());
+        let author_name = guild
+            .members
+            .get(&UserId(entry.author_id as u64))
+            .map(|m| format!("{} ({})", m.user.tag(), entry.author_id))
+            .unwrap_or_else(|| entry.author_id.to_string());
+
+        file.push_str(&format!(
+            "[{}] {} in {} (message {})",
+            entry.creation_timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            author_name,
+            channel_name,
+            entry.id
+        ));
+        if let Some(edited) = entry.edited_timestamp {
+            file.push_str(&format!(
+                " [edited {}]",
+                edited.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+        if let Some(deleted) = entry.deleted_timestamp {
+            file.push_str(&format!(
+                " [deleted {}]",
+                deleted.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+        file.push('\n');
+
+        file.push_str(entry.content.as_deref().unwrap_or_default());
+        file.push('\n');
+
+        if let Some(history) = &entry.content_history {
+            if !history.is_empty() {
+                file.push_str("Previous versions:\n");
+                for old_content in history {
+                    file.push_str(&format!("  - {}\n", old_content));
+                }
+            }
+        }
+
+        file.push('\n');
+    }
+
+    file
+}
+
+/// Searches the logged messages of this guild.
+/// Only the messages sent while logging was enabled, on channels you can read, are searched.
+///
+/// Filters:
+/// `--author @user` or `-a`: Messages sent by this user.
+/// `--channel #channel` or `-c`: Messages sent on this channel.
+/// `--text "some words"` or `-t`: Messages containing these words, including their previous versions. Supports `or` and `-excluded` words.
+/// `--after 2021-05-01`: Messages sent on or after this date.
+/// `--before 2021-06-01`: Messages sent before this date.
+/// `--deleted` or `-d`: Only deleted messages.
+/// `--edited` or `-e`: Only edited messages.
+/// `--export` or `-x`: Sends the results as a text file.
+///
+/// Usage:
+/// `history -a @user --deleted`
+/// `history -c #general -t "free nitro" --after 2021-05-01`
+/// `history --author 135423120268984330 --export`
+#[command]
+#[required_permissions(MANAGE_MESSAGES)]
+#[only_in("guilds")]
+#[aliases(message_history, search_history)]
+async fn history(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let matches = match App::new("history")
+        .setting(AppSettings::NoBinaryName)
+        .arg(
+            Arg::with_name("author")
+                .long("author")
+                .short("a")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("channel")
+                .long("channel")
+                .short("c")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .short("t")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("after").long("after").takes_value(true))
+        .arg(Arg::with_name("before").long("before").takes_value(true))
+        .arg(Arg::with_name("deleted").long("deleted").short("d"))
+        .arg(Arg::with_name("edited").long("edited").short("e"))
+        .arg(Arg::with_name("export").long("export").short("x"))
+        .get_matches_from_safe(args.raw_quoted())
+    {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, format!("```{}```", why.message)).await?;
+            return Ok(());
+        }
+    };
+
+    let author_id = if let Some(author) = matches.value_of("author") {
+        if let Ok(x) = author.parse::<UserId>() {
+            Some(x.0 as i64)
+        } else {
+            match parse_member(ctx, msg, author.to_string()).await {
+                Ok(m) => Some(m.user.id.0 as i64),
+                Err(why) => {
+                    msg.reply(ctx, why).await?;
+                    return Ok(());
+                }
+            }
+        }
+    } else {
+        None
+    };
+
+    let channel_id = if let Some(channel) = matches.value_of("channel") {
+        if let Ok(x) = channel.parse::<ChannelId>() {
+            Some(x.0 as i64)
+        } else {
+            msg.reply(ctx, "Invalid channel provided.").await?;
+            return Ok(());
+        }
+    } else {
+        None
+    };
+
+    let after = if let Some(date) = matches.value_of("after") {
+        if let Some(x) = parse_history_date(date) {
+            Some(x)
+        } else {
+            msg.reply(ctx, "Invalid date provided, the format is `YYYY-MM-DD`.")
+                .await?;
+            return Ok(());
+        }
+    } else {
+        None
+    };
+
+    let before = if let Some(date) = matches.value_of("before") {
+        if let Some(x) = parse_history_date(date) {
+            Some(x)
+        } else {
+            msg.reply(ctx, "Invalid date provided, the format is `YYYY-MM-DD`.")
+                .await?;
+            return Ok(());
+        }
+    } else {
+        None
+    };
+
+    let guild = msg.guild(ctx).await.unwrap();
+
+    // The logs shouldn't allow reading messages of channels the moderator can't see.
+    let mut readable_channels = Vec::new();
+    for channel in guild.channels.values() {
+        if let Ok(permissions) = channel.permissions_for_user(ctx, msg.author.id).await {
+            if permissions.read_messages() && permissions.read_message_history() {
+                readable_channels.push(channel.id.0 as i64);
+            }
+        }
+    }
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let entries = sqlx::query_as!(
+        HistoryEntry,
+        "SELECT id, channel_id, author_id, content, content_history, creation_timestamp, edited_timestamp, deleted_timestamp FROM log_messages WHERE guild_id = $1 AND channel_id = ANY($2) AND ($3::bigint IS NULL OR author_id = $3) AND ($4::bigint IS NULL OR channel_id = $4) AND ($5::text IS NULL OR log_messages_search_vector(content, content_history) @@ websearch_to_tsquery('simple', $5)) AND ($6::timestamptz IS NULL OR creation_timestamp >= $6) AND ($7::timestamptz IS NULL OR creation_timestamp < $7) AND (NOT $8 OR deleted_timestamp IS NOT NULL) AND (NOT $9 OR edited_timestamp IS NOT NULL) ORDER BY creation_timestamp DESC LIMIT $10",
+        guild.id.0 as i64,
+        &readable_channels,
+        author_id,
+        channel_id,
+        matches.value_of("text"),
+        after,
+        before,
+        matches.is_present("deleted"),
+        matches.is_present("edited"),
+        HISTORY_MAX_RESULTS,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    if entries.is_empty() {
+        msg.reply(ctx, "No messages were found.").await?;
+        return Ok(());
+    }
+
+    let export = |entries: &[HistoryEntry]| AttachmentType::Bytes {
+        data: Cow::from(history_file(&guild, entries).into_bytes()),
+        filename: format!("history_{}.txt", guild.id.0),
+    };
+
+    if matches.is_present("export") {
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.content(format!("{} messages found.", entries.len()));
+                m.add_file(export(&entries))
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let uuid_prev = Uuid::new_v4().to_string();
+    let uuid_next = Uuid::new_v4().to_string();
+    let uuid_export = Uuid::new_v4().to_string();
+    let uuid_done = Uuid::new_v4().to_string();
+
+    let max = (entries.len() - 1) / HISTORY_PAGE_SIZE;
+    let mut index = 0;
+
+    let mut message = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.set_embed(history_embed(guild.id.0, &entries, index, max));
+            m.reference_message(msg)
+        })
+        .await?;
+
+    loop {
+        message
+            .edit(ctx, |m| {
+                m.set_embeds(vec![history_embed(guild.id.0, &entries, index, max)]);
+                m.components(|c| {
+                    c.create_action_row(|ar| {
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Newer");
+                            b.emoji(ReactionType::Unicode("⬅️".to_string()));
+                            b.disabled(index == 0);
+                            b.custom_id(&uuid_prev)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Older");
+                            b.emoji(ReactionType::Unicode("➡️".to_string()));
+                            b.disabled(index == max);
+                            b.custom_id(&uuid_next)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Primary);
+                            b.label("Export");
+                            b.emoji(ReactionType::Unicode("📁".to_string()));
+                            b.custom_id(&uuid_export)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Success);
+                            b.label("Done!");
+                            b.emoji(ReactionType::Unicode("✅".to_string()));
+                            b.custom_id(&uuid_done)
+                        });
+                        ar
+                    })
+                })
+            })
+            .await?;
+
+        let mov_uuid_prev = uuid_prev.clone();
+        let mov_uuid_next = uuid_next.clone();
+        let mov_uuid_export = uuid_export.clone();
+        let mov_uuid_done = uuid_done.clone();
+
+        let mci = message
+            .await_component_interaction(ctx)
+            .author_id(msg.author.id.0)
+            .timeout(Duration::from_secs(120))
+            .filter(move |mci| {
+                matches!(mci.data.component_type, ComponentType::Button)
+                    && (mci.data.custom_id == mov_uuid_prev
+                        || mci.data.custom_id == mov_uuid_next
+                        || mci.data.custom_id == mov_uuid_export
+                        || mci.data.custom_id == mov_uuid_done)
+            })
+            .await;
+
+        if let Some(mci) = mci {
+            mci.create_interaction_response(ctx, |ir| {
+                ir.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await?;
+
+            if mci.data.custom_id == uuid_prev {
+                index = index.saturating_sub(1);
+            } else if mci.data.custom_id == uuid_next {
+                index = (index + 1).min(max);
+            } else if mci.data.custom_id == uuid_export {
+                msg.channel_id
+                    .send_message(ctx, |m| m.add_file(export(&entries)))
+                    .await?;
+            } else {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    message.edit(ctx, |m| m.components(|c| c)).await?;
+
+    Ok(())
+}
@@ -1,15 +1,34 @@
-use crate::global_data::Lavalink;
+use crate::global_data::{CachePool, DatabasePool, Lavalink, MusicStates};
+use crate::utils::checks::DJ_CHECK;
+use crate::utils::music::{
+    format_duration, leave_voice, queueable_tracks, save_guild_queue, vote, AudioSettings,
+    AutoLeave, FilterPreset, LeaveReason, LoopMode, MusicPermissions, MusicStateMap, Vote,
+    VoteKind, FILTER_PRESETS,
+};
 
-use std::time::Duration;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
 use serenity::{
+    builder::CreateEmbed,
     framework::standard::{macros::command, Args, CommandResult},
-    model::{channel::Message, misc::Mentionable},
+    model::{
+        channel::{Message, ReactionType},
+        id::{GuildId, UserId},
+        interactions::message_component::*,
+        interactions::InteractionResponseType,
+        misc::Mentionable,
+    },
     prelude::Context,
+    utils::parse_username,
 };
 
-use lavalink_rs::model::Band;
+use lavalink_rs::{
+    model::{Band, Track, TrackQueue},
+    LavalinkClient,
+};
 use tokio::process::Command;
+use uuid::Uuid;
 
 use regex::Regex;
 
@@ -19,6 +38,11 @@ use failure::Fail;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
+// How many tracks are shown on each page of the queue.
+const QUEUE_PAGE_SIZE: usize = 10;
+// How many results are shown to pick from when searching.
+const SEARCH_RESULTS: usize = 10;
+
 #[derive(Debug, Fail)]
 #[fail(display = "Not in a voice channel.")]
 struct JoinError;
@@ -49,8 +73,21 @@ pub async fn _join(ctx: &Context, msg: &Message) -> Result<String, Error> {
 
     match handler {
         Ok(connection_info) => {
-            let data = ctx.data.read().await;
-            let lava_client = data.get::<Lavalink>().unwrap();
+            // Prefer the nodes close to the voice channel.
+            let region = guild
+                .channels
+                .get(&connect_to)
+                .and_then(|channel| channel.rtc_region.clone())
+                .unwrap_or_else(|| guild.region.clone());
+
+            let lava_client = {
+                let data_read = ctx.data.read().await;
+                data_read
+                    .get::<Lavalink>()
+                    .unwrap()
+                    .assign(guild_id.0, Some(&region))
+                    .await
+            };
             lava_client.create_session(&connection_info).await?;
 
             Ok(connect_to.mention().to_string())
@@ -78,10 +115,15 @@ async fn join(ctx: &Context, msg: &Message) -> CommandResult {
 /// Shuffles the order of the current queue.
 #[command]
 #[aliases(randomize)]
+#[checks("dj")]
 async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     if let Some(mut node) = lava_client.nodes().await.get_mut(&msg.guild_id.unwrap().0) {
@@ -101,22 +143,94 @@ async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
         msg.react(ctx, '✅').await?;
     };
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
+/// Lets listeners that can't control the playback vote for it instead.
+/// Returns if the action can go ahead.
+async fn vote_for(ctx: &Context, msg: &Message, kind: VoteKind) -> Result<bool, Error> {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let permissions = MusicPermissions::load(&pool, msg.guild_id.unwrap().0).await;
+
+    if permissions.can_control(ctx, msg).await {
+        return Ok(true);
+    }
+
+    let action = match kind {
+        VoteKind::Skip => "skip",
+        VoteKind::Stop => "stop",
+    };
+
+    match vote(ctx, msg, kind, permissions.vote_ratio).await {
+        Vote::Passed => Ok(true),
+        Vote::NotListening => {
+            msg.reply(
+                ctx,
+                format!(
+                    "You need to be listening on my voice channel to vote to {}.",
+                    action
+                ),
+            )
+            .await?;
+            Ok(false)
+        }
+        Vote::Pending { votes, required } => {
+            msg.reply(
+                ctx,
+                format!("Voted to {} ({}/{} votes).", action, votes, required),
+            )
+            .await?;
+            Ok(false)
+        }
+    }
+}
+
 /// Skips the current song being played.
+/// If there's a DJ role, other listeners need to vote to skip.
 ///
 /// NOTE: will not skip if there's no more songs in the queue.
 /// Use `stop` or `pause` instad.
 #[command]
 #[aliases(next)]
 async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
+    if !vote_for(ctx, msg, VoteKind::Skip).await? {
+        return Ok(());
+    }
+
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     if let Some(track) = lava_client.skip(msg.guild_id.unwrap()).await {
+        let loop_mode = {
+            let data_read = ctx.data.read().await;
+            let music_states = data_read.get::<MusicStates>().unwrap().read().await;
+            music_states
+                .get(&msg.guild_id.unwrap().0)
+                .map(|i| i.loop_mode)
+                .unwrap_or_default()
+        };
+
+        // Skipped tracks stay on the queue when the whole queue is being looped.
+        if loop_mode == LoopMode::Queue {
+            let mut play = lava_client.play(msg.guild_id.unwrap(), track.track.clone());
+            if let Some(requester) = track.requester {
+                play = play.requester(requester);
+            }
+            play.queue().await?;
+        }
+
         let track_info = track.track.info.as_ref().unwrap();
         msg.channel_id
             .send_message(ctx, |m| {
@@ -157,54 +271,571 @@ async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
         msg.channel_id.say(ctx, "Nothing to skip.").await?;
     }
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
-/// Displays the current song queue.
+/// The queue of a guild, the first track being the one currently playing,
+/// with the loop mode and the position of the current track.
+async fn queue_state(
+    lava_client: &LavalinkClient,
+    music_states: &MusicStateMap,
+    guild_id: u64,
+) -> (Vec<TrackQueue>, LoopMode, u64) {
+    let (queue, is_paused) = if let Some(node) = lava_client.nodes().await.get(&guild_id) {
+        (node.queue.clone(), node.is_paused)
+    } else {
+        (vec![], false)
+    };
+
+    let music_states = music_states.read().await;
+    let state = music_states.get(&guild_id);
+
+    (
+        queue,
+        state.map(|i| i.loop_mode).unwrap_or_default(),
+        state
+            .and_then(|i| i.current_position(is_paused))
+            .unwrap_or(0),
+    )
+}
+
+/// The amount of pages of a queue, excluding the current track.
+fn queue_pages(queue_len: usize) -> usize {
+    queue_len.saturating_sub(2) / QUEUE_PAGE_SIZE
+}
+
+fn queue_track_line(track: &TrackQueue) -> String {
+    let (title, uri, length) = if let Some(info) = track.track.info.as_ref() {
+        (
+            info.title.replace("@", "@\u{200B}").replace("]", "\\]"),
+            info.uri.as_str(),
+            format_duration(info.length),
+        )
+    } else {
+        ("Unknown track".to_string(), "", "?".to_string())
+    };
+
+    let requester = track
+        .requester
+        .map(|i| format!(" - <@{}>", i.0))
+        .unwrap_or_default();
+
+    if uri.is_empty() {
+        format!("{} `{}`{}", title, length, requester)
+    } else {
+        format!("[{}]({}) `{}`{}", title, uri, length, requester)
+    }
+}
+
+fn queue_embed(
+    queue: &[TrackQueue],
+    loop_mode: LoopMode,
+    position: u64,
+    index: usize,
+) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed.title("Queue");
+
+    let mut description = String::new();
+
+    if let Some(current) = queue.first() {
+        let length = current.track.info.as_ref().map(|i| i.length).unwrap_or(0);
+
+        description.push_str(&format!(
+            "**Now playing:** {} `{}`\n\n",
+            queue_track_line(current),
+            format_duration(position.min(length))
+        ));
+    }
+
+    if queue.len() > 1 {
+        for (number, track) in queue
+            .iter()
+            .enumerate()
+            .skip(1 + index * QUEUE_PAGE_SIZE)
+            .take(QUEUE_PAGE_SIZE)
+        {
+            description.push_str(&format!("**{}.** {}\n", number, queue_track_line(track)));
+        }
+    } else {
+        description.push_str("The queue is empty.");
+    }
+
+    embed.description(description);
+
+    let current_left = queue
+        .first()
+        .and_then(|i| i.track.info.as_ref())
+        .map(|i| i.length.saturating_sub(position))
+        .unwrap_or(0);
+    let total = queue
+        .iter()
+        .skip(1)
+        .filter_map(|i| i.track.info.as_ref())
+        .map(|i| i.length)
+        .sum::<u64>()
+        + current_left;
+
+    embed.footer(|f| {
+        f.text(format!(
+            "Page {}/{} | {} tracks | {} left | Loop: {}",
+            index + 1,
+            queue_pages(queue.len()) + 1,
+            queue.len().saturating_sub(1),
+            format_duration(total),
+            loop_mode
+        ))
+    });
+
+    embed
+}
+
+/// Displays the current song queue, with who requested each track.
 #[command]
 #[aliases(que, q)]
 async fn queue(ctx: &Context, msg: &Message) -> CommandResult {
-    let lava_client = {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (lava_client, music_states) = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
-    };
-
-    if let Some(node) = lava_client.nodes().await.get_mut(&msg.guild_id.unwrap().0) {
-        if node.queue.len() > 1 {
-            let mut queue = String::from("```st\n");
-            for (index, track) in node.queue.iter().skip(1).take(10).enumerate() {
-                queue += &format!(
-                    "{}: {}\n",
-                    index + 1,
-                    track.track.info.as_ref().unwrap().title
-                );
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<MusicStates>().unwrap().clone(),
+        )
+    };
+
+    let (queue, loop_mode, position) = queue_state(&lava_client, &music_states, guild_id).await;
+
+    if queue.is_empty() {
+        msg.channel_id.say(ctx, "The queue is empty.").await?;
+        return Ok(());
+    }
+
+    let uuid_prev = Uuid::new_v4().to_string();
+    let uuid_next = Uuid::new_v4().to_string();
+    let uuid_jump = Uuid::new_v4().to_string();
+    let uuid_done = Uuid::new_v4().to_string();
+
+    let mut index = 0;
+
+    let mut message = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.set_embed(queue_embed(&queue, loop_mode, position, index));
+            m.reference_message(msg)
+        })
+        .await?;
+
+    loop {
+        // The queue may change while it's being looked at, so it's updated on every page.
+        let (queue, loop_mode, position) = queue_state(&lava_client, &music_states, guild_id).await;
+        let max = queue_pages(queue.len());
+        index = index.min(max);
+
+        message
+            .edit(ctx, |m| {
+                m.set_embeds(vec![queue_embed(&queue, loop_mode, position, index)]);
+                m.components(|c| {
+                    c.create_action_row(|ar| {
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Previous");
+                            b.emoji(ReactionType::Unicode("⬅️".to_string()));
+                            b.disabled(index == 0);
+                            b.custom_id(&uuid_prev)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Next");
+                            b.emoji(ReactionType::Unicode("➡️".to_string()));
+                            b.disabled(index == max);
+                            b.custom_id(&uuid_next)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Primary);
+                            b.label("Jump");
+                            b.emoji(ReactionType::Unicode("🔢".to_string()));
+                            b.disabled(max == 0);
+                            b.custom_id(&uuid_jump)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Success);
+                            b.label("Done!");
+                            b.emoji(ReactionType::Unicode("✅".to_string()));
+                            b.custom_id(&uuid_done)
+                        });
+                        ar
+                    })
+                })
+            })
+            .await?;
+
+        let mov_uuid_prev = uuid_prev.clone();
+        let mov_uuid_next = uuid_next.clone();
+        let mov_uuid_jump = uuid_jump.clone();
+        let mov_uuid_done = uuid_done.clone();
+
+        let mci = message
+            .await_component_interaction(ctx)
+            .author_id(msg.author.id.0)
+            .timeout(Duration::from_secs(120))
+            .filter(move |mci| {
+                matches!(mci.data.component_type, ComponentType::Button)
+                    && (mci.data.custom_id == mov_uuid_prev
+                        || mci.data.custom_id == mov_uuid_next
+                        || mci.data.custom_id == mov_uuid_jump
+                        || mci.data.custom_id == mov_uuid_done)
+            })
+            .await;
+
+        if let Some(mci) = mci {
+            mci.create_interaction_response(ctx, |ir| {
+                ir.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await?;
+
+            if mci.data.custom_id == uuid_prev {
+                index = index.saturating_sub(1);
+            } else if mci.data.custom_id == uuid_next {
+                index = (index + 1).min(max);
+            } else if mci.data.custom_id == uuid_jump {
+                let prompt = msg
+                    .channel_id
+                    .say(
+                        ctx,
+                        format!("Send the page you want to jump to (1-{}).", max + 1),
+                    )
+                    .await?;
+
+                if let Some(reply) = msg
+                    .author
+                    .await_reply(ctx)
+                    .channel_id(msg.channel_id)
+                    .timeout(Duration::from_secs(30))
+                    .await
+                {
+                    if let Ok(page) = reply.content.trim().parse::<usize>() {
+                        index = page.saturating_sub(1).min(max);
+                    }
+
+                    let _ = reply.delete(ctx).await;
+                }
+
+                let _ = prompt.delete(ctx).await;
+            } else {
+                break;
             }
+        } else {
+            break;
+        }
+    }
+
+    message.edit(ctx, |m| m.components(|c| c)).await?;
+
+    Ok(())
+}
+
+/// Sets what happens to the tracks once they finish playing.
+///
+/// - `track`: Repeats the current track.
+/// - `queue`: Adds every finished or skipped track back to the end of the queue.
+/// - `off`: Removes the tracks once they finish.
+///
+/// Without arguments, the current loop mode is shown.
+///
+/// Usage: `loop track`
+#[command]
+#[aliases("loop", repeat, loopmode)]
+async fn loop_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let music_states = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<MusicStates>().unwrap().clone()
+    };
+
+    if args.is_empty() {
+        let loop_mode = music_states
+            .read()
+            .await
+            .get(&guild_id)
+            .map(|i| i.loop_mode)
+            .unwrap_or_default();
+
+        msg.reply(ctx, format!("The current loop mode is `{}`", loop_mode))
+            .await?;
+
+        return Ok(());
+    }
+
+    let loop_mode = if let Some(x) = LoopMode::from_name(&args.single::<String>()?) {
+        x
+    } else {
+        msg.reply(
+            ctx,
+            "Invalid loop mode, choose from `track`, `queue` or `off`.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    music_states
+        .write()
+        .await
+        .entry(guild_id)
+        .or_default()
+        .loop_mode = loop_mode;
+
+    msg.reply(ctx, format!("Loop mode set to `{}`", loop_mode))
+        .await?;
+
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
+    Ok(())
+}
+
+/// Removes tracks from the queue.
+///
+/// Accepts the number of a track, a range of them, or a user, to remove all the tracks they requested.
+/// If there's a DJ role, other listeners can only remove their own tracks.
+///
+/// Usage:
+/// `remove 3`
+/// `remove 3-7`
+/// `remove @user`
+#[command]
+#[min_args(1)]
+#[aliases(rem, rm)]
+async fn remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+    let arg = args.rest().trim();
+
+    let lava_client = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
+    };
+
+    let can_control = {
+        let pool = {
+            let data_read = ctx.data.read().await;
+            data_read.get::<DatabasePool>().unwrap().clone()
+        };
+
+        MusicPermissions::load(&pool, guild_id)
+            .await
+            .can_control(ctx, msg)
+            .await
+    };
+
+    let user_id = parse_username(arg).map(UserId);
+
+    if let Some(user_id) = user_id {
+        if user_id != msg.author.id && !can_control {
+            msg.reply(ctx, "You can only remove the tracks you requested.")
+                .await?;
+            return Ok(());
+        }
+
+        let removed = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+            let before = node.queue.len();
+            let mut index = 0;
+            // The current track is kept, it can be skipped instead.
+            node.queue.retain(|i| {
+                index += 1;
+                index == 1 || i.requester.map(|r| r.0) != Some(user_id.0)
+            });
+            before - node.queue.len()
+        } else {
+            0
+        };
+
+        msg.reply(
+            ctx,
+            format!("Removed {} tracks requested by <@{}>", removed, user_id.0),
+        )
+        .await?;
 
-            if node.queue.len() > 10 {
-                queue += &format!("... {}", node.queue.len() - 1);
+        save_guild_queue(ctx, guild_id).await;
+
+        return Ok(());
+    }
+
+    let mut bounds = arg.splitn(2, '-').map(|i| i.trim().parse::<usize>());
+
+    let range = match (bounds.next(), bounds.next()) {
+        (Some(Ok(start)), None) => Some(start..=start),
+        (Some(Ok(start)), Some(Ok(end))) if start <= end => Some(start..=end),
+        _ => None,
+    };
+
+    let range = match range {
+        Some(x) if *x.start() != 0 => x,
+        _ => {
+            msg.reply(
+                ctx,
+                "Please specify a valid queue index number, range or user.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut not_owned = false;
+
+    let removed = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+        if *range.end() < node.queue.len() {
+            not_owned = !can_control
+                && node.queue[range.clone()]
+                    .iter()
+                    .any(|i| i.requester.map(|r| r.0) != Some(msg.author.id.0));
+
+            if not_owned {
+                vec![]
+            } else {
+                node.queue.drain(range).collect::<Vec<_>>()
             }
+        } else {
+            vec![]
+        }
+    } else {
+        vec![]
+    };
 
-            queue += "\n```";
+    if not_owned {
+        msg.reply(ctx, "You can only remove the tracks you requested.")
+            .await?;
+        return Ok(());
+    }
 
-            queue = queue.replace("@", "@\u{200B}");
+    if removed.is_empty() {
+        msg.channel_id
+            .say(ctx, "There's no such track on the queue.")
+            .await?;
+        return Ok(());
+    }
 
-            msg.channel_id.say(ctx, &queue).await?;
+    save_guild_queue(ctx, guild_id).await;
+
+    if removed.len() > 1 {
+        msg.reply(
+            ctx,
+            format!("Removed {} tracks from the queue.", removed.len()),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let track_info = removed[0].track.info.as_ref().unwrap();
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content("Removed:");
+            m.embed(|e| {
+                e.title(&track_info.title);
+                e.thumbnail(format!(
+                    "https://i.ytimg.com/vi/{}/default.jpg",
+                    &track_info.identifier
+                ));
+                e.url(&track_info.uri);
+                e.field("Uploader", &track_info.author, true);
+                e.field("Length", format_duration(track_info.length), true);
+                if let Some(requester) = removed[0].requester {
+                    e.field("Requested by", format!("<@{}>", requester.0), true);
+                }
+                e
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Moves a track of the queue to another position.
+///
+/// Usage: `move 7 1`
+#[command]
+#[num_args(2)]
+#[aliases("move", mv)]
+#[checks("dj")]
+async fn move_track(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (from, to) = match (args.single::<usize>(), args.single::<usize>()) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => {
+            msg.reply(ctx, "Please specify two valid queue index numbers.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let lava_client = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
+    };
+
+    let moved = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+        let len = node.queue.len();
+
+        if from != 0 && to != 0 && from < len && to < len {
+            let track = node.queue.remove(from);
+            let title = track.track.info.as_ref().map(|i| i.title.clone());
+            node.queue.insert(to, track);
+            Some(title.unwrap_or_else(|| "Unknown track".to_string()))
         } else {
-            msg.channel_id.say(ctx, "The queue is empty.").await?;
+            None
         }
+    } else {
+        None
     };
 
+    if let Some(title) = moved {
+        save_guild_queue(ctx, guild_id).await;
+
+        msg.reply(
+            ctx,
+            format!(
+                "Moved `{}` to position {}",
+                title.replace("@", "@\u{200B}"),
+                to
+            ),
+        )
+        .await?;
+    } else {
+        msg.reply(ctx, "There's no such track on the queue.")
+            .await?;
+    }
+
     Ok(())
 }
 
-/// Removes the queue item with that index.
+/// Swaps the position of two tracks of the queue.
+///
+/// Usage: `swap 2 5`
 #[command]
-#[aliases(rem, rm)]
-async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let index = match args.single::<usize>() {
-        Ok(x) => x,
-        Err(_) => {
-            msg.reply(ctx, "Please specify a valid queue index number.")
+#[num_args(2)]
+#[checks("dj")]
+async fn swap(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (first, second) = match (args.single::<usize>(), args.single::<usize>()) {
+        (Ok(first), Ok(second)) => (first, second),
+        _ => {
+            msg.reply(ctx, "Please specify two valid queue index numbers.")
                 .await?;
             return Ok(());
         }
@@ -212,48 +843,155 @@ async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
-    if let Some(mut node) = lava_client.nodes().await.get_mut(&msg.guild_id.unwrap().0) {
-        if index < node.queue.len() && index != 0 {
-            let track = node.queue.remove(index);
-            let track_info = track.track.info.as_ref().unwrap();
+    let swapped = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+        let len = node.queue.len();
 
-            msg.channel_id
-                .send_message(ctx, |m| {
-                    m.content("Skipped:");
-                    m.embed(|e| {
-                        e.title(&track_info.title);
-                        e.thumbnail(format!(
-                            "https://i.ytimg.com/vi/{}/default.jpg",
-                            &track_info.identifier
-                        ));
-                        e.url(&track_info.uri);
-                        e.footer(|f| f.text("Submited by unknown".to_string()));
-                        e.field("Uploader", &track_info.author, true);
-                        e.field(
-                            "Length",
-                            format!("{}:{}", track_info.length / 1000 % 3600 / 60, {
-                                let x = track_info.length / 1000 % 3600 % 60;
-                                if x < 10 {
-                                    format!("0{}", x)
-                                } else {
-                                    x.to_string()
-                                }
-                            }),
-                            true,
-                        );
-                        e
-                    })
-                })
+        if first != 0 && second != 0 && first < len && second < len {
+            node.queue.swap(first, second);
+            true
+        } else {
+            false
+        }
+    } else {
+        false
+    };
+
+    if swapped {
+        save_guild_queue(ctx, guild_id).await;
+        msg.react(ctx, '✅').await?;
+    } else {
+        msg.reply(ctx, "There's no such track on the queue.")
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Skips to a track of the queue, removing the ones before it.
+///
+/// When the whole queue is being looped, the skipped tracks are moved to the end of the queue instead.
+///
+/// Usage: `skipto 5`
+#[command]
+#[num_args(1)]
+#[aliases(skip_to, jump)]
+#[checks("dj")]
+async fn skipto(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let target = match args.single::<usize>() {
+        Ok(x) if x != 0 => x,
+        _ => {
+            msg.reply(ctx, "Please specify a valid queue index number.")
                 .await?;
+            return Ok(());
+        }
+    };
+
+    let (lava_client, music_states) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<MusicStates>().unwrap().clone(),
+        )
+    };
+
+    let loop_mode = music_states
+        .read()
+        .await
+        .get(&guild_id)
+        .map(|i| i.loop_mode)
+        .unwrap_or_default();
+
+    let valid = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+        if target < node.queue.len() {
+            let skipped = node.queue.drain(1..target).collect::<Vec<_>>();
+
+            if loop_mode == LoopMode::Queue {
+                node.queue.extend(skipped);
+            }
+
+            true
+        } else {
+            false
+        }
+    } else {
+        false
+    };
+
+    if !valid {
+        msg.reply(ctx, "There's no such track on the queue.")
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(track) = lava_client.skip(GuildId(guild_id)).await {
+        if loop_mode == LoopMode::Queue {
+            let mut play = lava_client.play(GuildId(guild_id), track.track);
+            if let Some(requester) = track.requester {
+                play = play.requester(requester);
+            }
+            play.queue().await?;
+        }
+    }
+
+    save_guild_queue(ctx, guild_id).await;
+
+    msg.react(ctx, '✅').await?;
+
+    Ok(())
+}
 
-            return Ok(());
-        }
-    }
+/// Removes the tracks that are on the queue more than once.
+#[command]
+#[aliases(dedup, remove_duplicates)]
+#[checks("dj")]
+async fn dedupe(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let lava_client = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
+    };
+
+    let removed = if let Some(mut node) = lava_client.nodes().await.get_mut(&guild_id) {
+        let before = node.queue.len();
+        let mut seen = HashSet::new();
+
+        node.queue.retain(|i| {
+            let key = i
+                .track
+                .info
+                .as_ref()
+                .map(|info| info.uri.clone())
+                .unwrap_or_else(|| i.track.track.clone());
+            seen.insert(key)
+        });
+
+        before - node.queue.len()
+    } else {
+        0
+    };
 
-    msg.channel_id.say(ctx, "Nothing to skip.").await?;
+    save_guild_queue(ctx, guild_id).await;
+
+    msg.reply(ctx, format!("Removed {} duplicated tracks.", removed))
+        .await?;
 
     Ok(())
 }
@@ -261,10 +999,15 @@ async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 /// Clears the current queue.
 #[command]
 #[aliases(cque, clearqueue, clearque, cqueue)]
+#[checks("dj")]
 async fn clear_queue(ctx: &Context, msg: &Message) -> CommandResult {
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     if let Some(mut node) = lava_client.nodes().await.get_mut(&msg.guild_id.unwrap().0) {
@@ -279,6 +1022,8 @@ async fn clear_queue(ctx: &Context, msg: &Message) -> CommandResult {
         }
     };
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
@@ -288,7 +1033,20 @@ async fn clear_queue(ctx: &Context, msg: &Message) -> CommandResult {
 async fn now_playing(ctx: &Context, msg: &Message) -> CommandResult {
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
+    };
+
+    let state = {
+        let data_read = ctx.data.read().await;
+        let music_states = data_read.get::<MusicStates>().unwrap().read().await;
+        music_states
+            .get(&msg.guild_id.unwrap().0)
+            .cloned()
+            .unwrap_or_default()
     };
 
     if let Some(node) = lava_client.nodes().await.get(&msg.guild_id.unwrap().0) {
@@ -301,6 +1059,11 @@ async fn now_playing(ctx: &Context, msg: &Message) -> CommandResult {
             };
 
             let track_info = x.track.info.as_ref().unwrap();
+            let position = state
+                .current_position(node.is_paused)
+                .unwrap_or(track_info.position)
+                .min(track_info.length);
+
             msg.channel_id
                 .send_message(ctx, |m| {
                     m.content("Now playing:");
@@ -316,28 +1079,13 @@ async fn now_playing(ctx: &Context, msg: &Message) -> CommandResult {
                         e.field(
                             "Length",
                             format!(
-                                "{}:{} - {}:{}",
-                                track_info.position / 1000 % 3600 / 60,
-                                {
-                                    let x = track_info.position / 1000 % 3600 % 60;
-                                    if x < 10 {
-                                        format!("0{}", x)
-                                    } else {
-                                        x.to_string()
-                                    }
-                                },
-                                track_info.length / 1000 % 3600 / 60,
-                                {
-                                    let x = track_info.length / 1000 % 3600 % 60;
-                                    if x < 10 {
-                                        format!("0{}", x)
-                                    } else {
-                                        x.to_string()
-                                    }
-                                }
+                                "{} - {}",
+                                format_duration(position),
+                                format_duration(track_info.length)
                             ),
                             true,
                         );
+                        e.field("Loop", state.loop_mode, true);
                         e
                     })
                 })
@@ -360,6 +1108,7 @@ async fn now_playing(ctx: &Context, msg: &Message) -> CommandResult {
 #[command]
 #[min_args(1)]
 #[aliases(jump_to, jumpto, scrub)]
+#[checks("dj")]
 async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let num = if let Ok(x) = args.single::<u64>() {
         x
@@ -371,66 +1120,106 @@ async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     lava_client
         .seek(msg.guild_id.unwrap(), Duration::from_secs(num))
         .await?;
 
+    {
+        let data_read = ctx.data.read().await;
+        let mut music_states = data_read.get::<MusicStates>().unwrap().write().await;
+        if let Some(state) = music_states.get_mut(&msg.guild_id.unwrap().0) {
+            state.position = Some((num * 1000, Instant::now()));
+        }
+    }
+
     msg.react(ctx, '✅').await?;
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
 /// Stops the current player.
+/// If there's a DJ role, other listeners need to vote to stop.
 #[command]
 async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    if !vote_for(ctx, msg, VoteKind::Stop).await? {
+        return Ok(());
+    }
+
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     lava_client.stop(msg.guild_id.unwrap()).await?;
 
     msg.react(ctx, '✅').await?;
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
 /// Pauses the current player.
 #[command]
+#[checks("dj")]
 async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     lava_client.set_pause(msg.guild_id.unwrap(), true).await?;
 
     msg.react(ctx, '✅').await?;
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
 /// Resumes the current player.
 #[command]
 #[aliases(unpause)]
+#[checks("dj")]
 async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
     let lava_client = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
     };
 
     lava_client.set_pause(msg.guild_id.unwrap(), false).await?;
 
     msg.react(ctx, '✅').await?;
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
     Ok(())
 }
 
 /// Disconnects me from the voice channel if im in one.
 #[command]
+#[checks("dj")]
 async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
     let guild = msg.guild(&ctx.cache).await.unwrap();
     let guild_id = guild.id;
@@ -439,42 +1228,354 @@ async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
     let has_handler = manager.get(guild_id).is_some();
 
     if has_handler {
-        if let Err(e) = manager.remove(guild_id).await {
+        let (lavalink, music_states, redis_pool) = {
+            let data_read = ctx.data.read().await;
+            (
+                data_read.get::<Lavalink>().unwrap().clone(),
+                data_read.get::<MusicStates>().unwrap().clone(),
+                data_read.get::<CachePool>().unwrap().clone(),
+            )
+        };
+
+        if let Err(e) =
+            leave_voice(&manager, &lavalink, &music_states, &redis_pool, guild_id.0).await
+        {
             msg.channel_id
                 .say(&ctx.http, format!("Failed: {:?}", e))
                 .await?;
         }
 
-        let lava_client = {
-            let data_read = ctx.data.read().await;
-            data_read.get::<Lavalink>().unwrap().clone()
-        };
+        msg.react(ctx, '✅').await?;
+    } else {
+        msg.reply(ctx, "Not in a voice channel").await?;
+    }
 
-        lava_client.destroy(guild_id).await?;
-        lava_client.nodes().await.remove(&guild_id.0);
+    Ok(())
+}
 
-        let loops = lava_client.loops().await;
+/// Toggles 24/7 mode, where i stay on the voice channel even if nobody is listening or the queue ends.
+#[command]
+#[aliases("24/7", "247", twenty_four_seven, stay_connected)]
+#[required_permissions(MANAGE_GUILD)]
+async fn stay(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
 
-        loops.remove(&guild_id.0);
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
 
-        msg.react(ctx, '✅').await?;
+    let stay_connected = sqlx::query!(
+        "INSERT INTO music_settings (guild_id, stay_connected) VALUES ($1, true) ON CONFLICT (guild_id) DO UPDATE SET stay_connected = NOT music_settings.stay_connected RETURNING stay_connected",
+        guild_id as i64
+    )
+    .fetch_one(&pool)
+    .await?
+    .stay_connected;
+
+    if stay_connected {
+        msg.reply(ctx, "24/7 mode enabled, i will stay on the voice channel.")
+            .await?;
     } else {
-        msg.reply(ctx, "Not in a voice channel").await?;
+        // Start counting again if the bot is already alone or idle.
+        let auto_leave = AutoLeave::from_context(ctx).await;
+        {
+            let mut music_states = auto_leave.music_states.write().await;
+            if let Some(state) = music_states.get_mut(&guild_id) {
+                if state.alone_since.is_some() {
+                    state.alone_since = Some(Instant::now());
+                    auto_leave.schedule(guild_id, LeaveReason::Alone);
+                }
+                if state.idle_since.is_some() {
+                    state.idle_since = Some(Instant::now());
+                    auto_leave.schedule(guild_id, LeaveReason::Idle);
+                }
+            }
+        }
+
+        msg.reply(
+            ctx,
+            "24/7 mode disabled, i will leave the voice channel when nobody is listening.",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Searches for tracks, the `yt:` and `sc:` prefixes search on YouTube or SoundCloud,
+/// otherwise it's searched on YouTube unless it's a url.
+async fn search_tracks(lava_client: &LavalinkClient, query: &str) -> Result<Vec<Track>, Error> {
+    let res = if let Some(x) = query.strip_prefix("yt:") {
+        lava_client
+            .search_tracks(format!("ytsearch:{}", x.trim()))
+            .await?
+    } else if let Some(x) = query.strip_prefix("sc:") {
+        lava_client
+            .search_tracks(format!("scsearch:{}", x.trim()))
+            .await?
+    } else {
+        lava_client.auto_search_tracks(query).await?
+    };
+
+    Ok(res.tracks)
+}
+
+/// Adds a track to the queue and lets the channel know about it.
+async fn add_to_queue(
+    ctx: &Context,
+    msg: &Message,
+    lava_client: &LavalinkClient,
+    guild_id: GuildId,
+    track: Track,
+) -> CommandResult {
+    let track = match queueable_tracks(ctx, msg, vec![track]).await {
+        (tracks, None) => tracks.into_iter().next().unwrap(),
+        (_, Some(limits)) => {
+            msg.reply(
+                ctx,
+                format!("You can't queue that track, this server allows {}.", limits),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let data_read = ctx.data.read().await;
+        let mut music_states = data_read.get::<MusicStates>().unwrap().write().await;
+        music_states
+            .entry(guild_id.0)
+            .or_default()
+            .set_request_channel(&track.track, msg.channel_id.0);
+    }
+
+    lava_client
+        .play(guild_id, track.clone())
+        .requester(msg.author.id)
+        .queue()
+        .await?;
+
+    let track_info = track.info.as_ref().unwrap();
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content("Added to queue:");
+            m.embed(|e| {
+                e.title(&track_info.title);
+                e.thumbnail(format!(
+                    "https://i.ytimg.com/vi/{}/default.jpg",
+                    track_info.identifier
+                ));
+                e.url(&track_info.uri);
+                e.footer(|f| f.text(format!("Submited by {}", &msg.author.name)));
+                e.field("Uploader", &track_info.author, true);
+                e.field("Length", format_duration(track_info.length), true);
+                e
+            })
+        })
+        .await?;
+
+    save_guild_queue(ctx, guild_id.0).await;
+
+    Ok(())
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        format!("{}...", text.chars().take(max - 3).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Shows the results of a search, and adds the one picked from the menu to the queue.
+async fn pick_track(ctx: &Context, msg: &Message, query: &str) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+
+    let manager = songbird::get(ctx).await.unwrap().clone();
+
+    if manager.get(guild_id).is_none() {
+        msg.channel_id.say(ctx, "Please, connect the bot to the voice channel you are currently on first with the `join` command.").await?;
+        return Ok(());
+    }
+
+    let lava_client = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Lavalink>()
+            .unwrap()
+            .client(msg.guild_id.unwrap().0)
+            .await
+    };
+
+    let mut tracks = search_tracks(&lava_client, query).await?;
+    tracks.retain(|i| i.info.is_some());
+    tracks.truncate(SEARCH_RESULTS);
+
+    if tracks.is_empty() {
+        msg.channel_id
+            .say(ctx, "Could not find any video of the search query.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut results = String::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let info = track.info.as_ref().unwrap();
+        results.push_str(&format!(
+            "**{}.** [{}]({}) `{}` - {}\n",
+            index + 1,
+            info.title.replace("@", "@\u{200B}").replace("]", "\\]"),
+            info.uri,
+            format_duration(info.length),
+            info.author.replace("@", "@\u{200B}"),
+        ));
+    }
+
+    let uuid_select = Uuid::new_v4().to_string();
+    let uuid_cancel = Uuid::new_v4().to_string();
+
+    let mut message = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Search results");
+                e.description(&results);
+                e.footer(|f| f.text("Pick a track from the menu below."))
+            });
+            m.components(|c| {
+                c.create_action_row(|ar| {
+                    ar.create_select_menu(|sm| {
+                        sm.placeholder("Pick a track...");
+                        sm.min_values(1);
+                        sm.max_values(1);
+                        sm.custom_id(&uuid_select);
+
+                        sm.options(|o| {
+                            for (index, track) in tracks.iter().enumerate() {
+                                let info = track.info.as_ref().unwrap();
+                                o.create_option(|o| {
+                                    o.label(truncate(
+                                        &format!("{}. {}", index + 1, info.title),
+                                        100,
+                                    ));
+                                    o.value(index.to_string());
+                                    o.description(truncate(
+                                        &format!(
+                                            "{} | {}",
+                                            format_duration(info.length),
+                                            info.author
+                                        ),
+                                        100,
+                                    ))
+                                });
+                            }
+                            o
+                        })
+                    })
+                });
+                c.create_action_row(|ar| {
+                    ar.create_button(|b| {
+                        b.style(ButtonStyle::Danger);
+                        b.label("Cancel");
+                        b.emoji(ReactionType::Unicode("❌".to_string()));
+                        b.custom_id(&uuid_cancel)
+                    })
+                })
+            });
+            m.reference_message(msg)
+        })
+        .await?;
+
+    let mov_uuid_select = uuid_select.clone();
+    let mov_uuid_cancel = uuid_cancel.clone();
+
+    let mci = message
+        .await_component_interaction(ctx)
+        .author_id(msg.author.id.0)
+        .timeout(Duration::from_secs(60))
+        .filter(move |mci| match mci.data.component_type {
+            ComponentType::SelectMenu => mci.data.custom_id == mov_uuid_select,
+            ComponentType::Button => mci.data.custom_id == mov_uuid_cancel,
+            _ => false,
+        })
+        .await;
+
+    let picked = if let Some(mci) = mci {
+        mci.create_interaction_response(ctx, |ir| {
+            ir.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+        mci.data
+            .values
+            .first()
+            .and_then(|i| i.parse::<usize>().ok())
+            .and_then(|i| tracks.get(i).cloned())
+    } else {
+        None
+    };
+
+    message.edit(ctx, |m| m.components(|c| c)).await?;
+
+    if let Some(track) = picked {
+        add_to_queue(ctx, msg, &lava_client, guild_id, track).await?;
+    } else {
+        message
+            .edit(ctx, |m| {
+                m.embed(|e| {
+                    e.title("Search results");
+                    e.description("Nothing was picked.")
+                })
+            })
+            .await?;
     }
 
     Ok(())
 }
 
+/// Searches for a song, and lets you pick which result to add to the queue.
+///
+/// Prefix the search with `yt:` or `sc:` to search on YouTube or SoundCloud.
+///
+/// Usage: `search starmachine2000`
+/// or `search sc:lofi`
+#[command]
+#[min_args(1)]
+#[aliases(find)]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    pick_track(ctx, msg, args.message().trim()).await
+}
+
 /// Adds a song to the queue.
 ///
+/// Prefix the search with `yt:` or `sc:` to search on YouTube or SoundCloud.
+/// Add `--pick` to choose from the search results, like the `search` command.
+///
 /// Usage: `play starmachine2000`
 /// or `play https://www.youtube.com/watch?v=dQw4w9WgXcQ`
+/// or `play sc:lofi --pick`
 #[command]
 #[min_args(1)]
 #[aliases(p)]
 async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mut embeded = false;
-    let mut query = args.message().to_string();
+    let mut pick = false;
+    let mut query = args
+        .message()
+        .split(' ')
+        .filter(|i| {
+            let is_flag = *i == "--pick";
+            pick |= is_flag;
+            !is_flag
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if pick {
+        return pick_track(ctx, msg, query.trim()).await;
+    }
 
     if query.starts_with('<') && query.ends_with('>') {
         embeded = true;
@@ -519,7 +1620,11 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if let Some(_handler_lock) = manager.get(guild_id) {
         let lava_client = {
             let data_read = ctx.data.read().await;
-            data_read.get::<Lavalink>().unwrap().clone()
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await
         };
 
         let mut iter = 0;
@@ -527,18 +1632,29 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 
         let query_information = loop {
             iter += 1;
-            let res = lava_client.auto_search_tracks(&query).await?;
+            let res = search_tracks(&lava_client, &query).await?;
 
-            if res.tracks.is_empty() {
+            if res.is_empty() {
                 if iter == 5 {
                     if !already_checked {
                         already_checked = true;
 
-                        let output: std::process::Output = Command::new("youtube-dl")
+                        let output = match Command::new("youtube-dl")
                             .arg("-g")
                             .arg(&query)
                             .output()
-                            .await?;
+                            .await
+                        {
+                            Ok(x) => x,
+                            Err(why) if why.kind() == std::io::ErrorKind::NotFound => {
+                                warn!("youtube-dl is not installed, unable to search further.");
+                                msg.channel_id
+                                    .say(ctx, "Could not find any video of the search query, and `youtube-dl` is not installed to look any further.")
+                                    .await?;
+                                return Ok(());
+                            }
+                            Err(why) => return Err(why.into()),
+                        };
 
                         if !output.stdout.is_empty() {
                             let stdout = String::from_utf8(output.stdout)?;
@@ -558,63 +1674,16 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                     return Ok(());
                 }
             } else {
-                if query.starts_with("http") && res.tracks.len() > 1 {
+                if query.starts_with("http") && res.len() > 1 {
                     msg.channel_id.say(ctx, "If you would like to play the entire playlist, use `play_playlist` instead.").await?;
                 }
                 break res;
             }
         };
 
-        lava_client
-            .play(guild_id, query_information.tracks[0].clone())
-            .requester(msg.author.id)
-            .queue()
-            .await?;
+        let track = query_information.into_iter().next().unwrap();
 
-        msg.channel_id
-            .send_message(ctx, |m| {
-                m.content("Added to queue:");
-                m.embed(|e| {
-                    e.title(&query_information.tracks[0].info.as_ref().unwrap().title);
-                    e.thumbnail(format!(
-                        "https://i.ytimg.com/vi/{}/default.jpg",
-                        query_information.tracks[0]
-                            .info
-                            .as_ref()
-                            .unwrap()
-                            .identifier
-                    ));
-                    e.url(&query_information.tracks[0].info.as_ref().unwrap().uri);
-                    e.footer(|f| f.text(format!("Submited by {}", &msg.author.name)));
-                    e.field(
-                        "Uploader",
-                        &query_information.tracks[0].info.as_ref().unwrap().author,
-                        true,
-                    );
-                    e.field(
-                        "Length",
-                        format!(
-                            "{}:{}",
-                            query_information.tracks[0].info.as_ref().unwrap().length / 1000 % 3600
-                                / 60,
-                            {
-                                let x = query_information.tracks[0].info.as_ref().unwrap().length
-                                    / 1000
-                                    % 3600
-                                    % 60;
-                                if x < 10 {
-                                    format!("0{}", x)
-                                } else {
-                                    x.to_string()
-                                }
-                            }
-                        ),
-                        true,
-                    );
-                    e
-                })
-            })
-            .await?;
+        add_to_queue(ctx, msg, &lava_client, guild_id, track).await?;
     } else {
         msg.channel_id.say(ctx, "Please, connect the bot to the voice channel you are currently on first with the `join` command.").await?;
     }
@@ -630,9 +1699,10 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 /// Adds an entire playlist to the queue.
 ///
 /// Usage: `playlist https://www.youtube.com/playlist?list=PLTktV6LgA75yif8RR7yUiSttZD7GKtl_5`
+/// To use saved playlists, see the `playlist` group.
 #[command]
 #[min_args(1)]
-#[aliases(playlist, playplaylist, play_list, pl, playl, plist)]
+#[aliases(playplaylist, play_list, playl, plist)]
 async fn play_playlist(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mut embeded = false;
     let mut query = args.message().to_string();
@@ -680,7 +1750,11 @@ async fn play_playlist(ctx: &Context, msg: &Message, args: Args) -> CommandResul
     if let Some(_handler_lock) = manager.get(guild_id) {
         let lava_client = {
             let data_read = ctx.data.read().await;
-            data_read.get::<Lavalink>().unwrap().clone()
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await
         };
 
         let mut iter = 0;
@@ -700,7 +1774,30 @@ async fn play_playlist(ctx: &Context, msg: &Message, args: Args) -> CommandResul
             }
         };
 
-        for track in query_information.tracks {
+        let (tracks, limits) = queueable_tracks(ctx, msg, query_information.tracks).await;
+
+        if let Some(limits) = limits {
+            msg.reply(
+                ctx,
+                format!(
+                    "Only {} tracks of the playlist were queued, this server allows {}.",
+                    tracks.len(),
+                    limits
+                ),
+            )
+            .await?;
+        }
+
+        {
+            let data_read = ctx.data.read().await;
+            let mut music_states = data_read.get::<MusicStates>().unwrap().write().await;
+            let state = music_states.entry(guild_id.0).or_default();
+            for track in &tracks {
+                state.set_request_channel(&track.track, msg.channel_id.0);
+            }
+        }
+
+        for track in tracks {
             lava_client
                 .play(guild_id, track.clone())
                 .requester(msg.author.id)
@@ -727,6 +1824,136 @@ async fn play_playlist(ctx: &Context, msg: &Message, args: Args) -> CommandResul
         let _ = m.delete(ctx).await;
     }
 
+    save_guild_queue(ctx, msg.guild_id.unwrap().0).await;
+
+    Ok(())
+}
+
+/// Sets the volume of the player, from 0 to 1000, 100 being the default.
+/// The volume is kept for the next time i join.
+///
+/// Without arguments, the current volume is shown.
+///
+/// Usage: `volume 50`
+#[command]
+#[aliases(vol, v)]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (lava_client, pool) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
+    };
+
+    let mut settings = AudioSettings::load(&pool, guild_id).await;
+
+    if args.is_empty() {
+        msg.reply(ctx, format!("The current volume is `{}`", settings.volume))
+            .await?;
+        return Ok(());
+    }
+
+    settings.volume = match args.single::<u16>() {
+        Ok(x) if x <= 1000 => x,
+        _ => {
+            msg.reply(ctx, "Please, provide a volume between 0 and 1000.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    settings.save(&pool, guild_id).await?;
+    lava_client
+        .volume(msg.guild_id.unwrap(), settings.volume)
+        .await?;
+
+    msg.reply(ctx, format!("Volume set to `{}`", settings.volume))
+        .await?;
+
+    Ok(())
+}
+
+/// Applies an audio filter preset to the player, which is kept for the next time i join.
+///
+/// Note: It may take a few seconds for the filter to take effect.
+///
+/// Choose from: `nightcore`, `vaporwave`, `bassboost`, `8d`, `karaoke`, `tremolo`, `vibrato` or `off`.
+/// Without arguments, the current volume, filter and equalizer are shown.
+///
+/// Usage: `filters nightcore`
+#[command]
+#[aliases(filter, fx)]
+async fn filters(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (lava_client, pool) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
+    };
+
+    let mut settings = AudioSettings::load(&pool, guild_id).await;
+
+    if args.is_empty() {
+        let mut equalizer = "```\nband | gain\n".to_string();
+        for (band, gain) in settings.equalizer.iter().enumerate() {
+            equalizer.push_str(&format!(" {:02}  |  {}\n", band, gain));
+        }
+        equalizer.push_str("```");
+
+        msg.channel_id
+            .send_message(ctx, |m| {
+                m.embed(|e| {
+                    e.title("Audio Settings");
+                    e.field("Volume", settings.volume, true);
+                    e.field("Filter", settings.filter, true);
+                    e.field(
+                        "Available Filters",
+                        FILTER_PRESETS
+                            .iter()
+                            .map(|i| format!("`{}`", i))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        false,
+                    );
+                    e.field("Equalizer", equalizer, false)
+                })
+            })
+            .await?;
+
+        return Ok(());
+    }
+
+    settings.filter = if let Some(x) = FilterPreset::from_name(args.rest()) {
+        x
+    } else {
+        msg.reply(
+            ctx,
+            "Invalid filter, use the command without arguments to see the available filters.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    settings.save(&pool, guild_id).await?;
+    settings.apply(&lava_client, guild_id).await?;
+
+    msg.reply(ctx, format!("Filter set to `{}`", settings.filter))
+        .await?;
+
     Ok(())
 }
 
@@ -739,9 +1966,18 @@ async fn play_playlist(ctx: &Context, msg: &Message, args: Args) -> CommandResul
 #[aliases(eq, equalizer)]
 #[min_args(1)]
 async fn equalize(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let lava_client = {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (lava_client, pool) = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
     };
 
     let eq = match args.single::<String>()?.to_lowercase().as_str() {
@@ -751,7 +1987,11 @@ async fn equalize(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
         _ => lavalink_rs::EQ_BASE,
     };
 
-    lava_client.equalize_all(msg.guild_id.unwrap(), eq).await?;
+    let mut settings = AudioSettings::load(&pool, guild_id).await;
+    settings.equalizer = eq;
+
+    settings.save(&pool, guild_id).await?;
+    settings.apply(&lava_client, guild_id).await?;
     msg.react(ctx, '✅').await?;
 
     Ok(())
@@ -780,9 +2020,18 @@ async fn equalize(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
 #[aliases(eqb, equalizeband, eqband, eq_band, eq_b, equalize_b)]
 #[min_args(2)]
 async fn equalize_band(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let lava_client = {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (lava_client, pool) = {
         let data_read = ctx.data.read().await;
-        data_read.get::<Lavalink>().unwrap().clone()
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
     };
 
     let arguments = args.message();
@@ -817,9 +2066,13 @@ async fn equalize_band(ctx: &Context, msg: &Message, args: Args) -> CommandResul
 
         text.push_str("```");
 
-        lava_client
-            .equalize_dynamic(msg.guild_id.unwrap(), bands)
-            .await?;
+        let mut settings = AudioSettings::load(&pool, guild_id).await;
+        for i in &bands {
+            settings.equalizer[i.band as usize] = i.gain;
+        }
+
+        settings.save(&pool, guild_id).await?;
+        settings.apply(&lava_client, guild_id).await?;
 
         msg.reply(ctx, text).await?;
     }
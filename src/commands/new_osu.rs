@@ -1,8 +1,8 @@
+use crate::global_data::DatabasePool;
 use crate::utils::osu_model::*;
-//use crate::utils::osu::*;
-use crate::commands::osu::progress_math;
-use crate::global_data::OsuHttpClient;
+use crate::utils::osu::{mode_accuracy, mode_hits, mode_progress, set_channel_beatmap, take_mode_flag, PpCalculation};
 use crate::utils::basic_functions::capitalize_first;
+use crate::utils::osu_api::{Error, OsuApi, ScoreKind};
 
 use std::time::Duration;
 
@@ -26,9 +26,10 @@ use serenity::{
 /// Note: if you are not getting your recent scores, it's because your nick or username in discord doesn't match the osu! username, if that's the case, call the command with the username afterwards.
 ///
 /// `new_recent vicky5124`
+/// `new_recent vicky5124 -m taiko`
 #[command]
 #[aliases(nrc, newrc, newrececnt, new_rc, n_rc, nrs, newrs, new_rs, n_rs)]
-async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+async fn new_recent(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     let mut message = msg.reply(ctx, "Loading recent scores...").await?;
     let mut content = String::new();
     let mut content_swapped = false;
@@ -40,27 +41,37 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
 
     let mut to_keep = 0;
 
-    let raw_user = if let Ok(x) = args.single_quoted() {
+    let (mode, raw_user) = take_mode_flag(args.message());
+
+    // Without `-m`, the mode configured with `osuc` is used.
+    let mode = if let Some(x) = mode {
         x
     } else {
-        msg.member(ctx).await?.display_name().into_owned()
+        let pool = {
+            let data_read = ctx.data.read().await;
+            data_read.get::<DatabasePool>().unwrap().clone()
+        };
+
+        let author_id = msg.author.id.0 as i64;
+        let stored_mode = sqlx::query!("SELECT mode FROM osu_user WHERE discord_id = $1", author_id)
+            .fetch_optional(&pool)
+            .await?
+            .and_then(|row| row.mode);
+
+        stored_mode.map(GameMode::from_id).unwrap_or_default()
     };
 
-    let client_lock = {
-        let data_read = ctx.data.read().await;
-        data_read.get::<OsuHttpClient>().unwrap().clone()
+    let raw_user = if raw_user.is_empty() {
+        msg.member(ctx).await?.display_name().into_owned()
+    } else {
+        raw_user.trim_matches('"').to_string()
     };
 
+    let api = OsuApi::from_context(ctx).await;
+
     let user = {
-        let user_data = client_lock
-            .read()
-            .await
-            .get(&format!("https://osu.ppy.sh/api/v2/users/{}", &raw_user))
-            .send()
-            .await?
-            .json::<OsuUser>()
-            .await;
-        if let Ok(u) = user_data {
+        let user_data = api.user_by_name(&raw_user, Some(mode)).await;
+        if let Ok(Some(u)) = user_data {
             u.id
         } else {
             message
@@ -72,16 +83,8 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
         }
     };
 
-    let res_recent_data = client_lock
-        .read()
-        .await
-        .get(&format!(
-            "https://osu.ppy.sh/api/v2/users/{}/scores/recent?mode=osu&include_fails=1&limit=50",
-            &user
-        ))
-        .send()
-        .await?
-        .json::<Recent>()
+    let res_recent_data = api
+        .user_scores(user, ScoreKind::Recent, Some(mode), 50)
         .await;
 
     let recent_data = if let Ok(x) = res_recent_data {
@@ -110,8 +113,8 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
         .iter()
         .chunks(3)
         .into_iter()
-        .map(|i| i.map(|i| i.to_owned()).collect::<Vec<&RecentElement>>())
-        .collect::<Vec<Vec<&RecentElement>>>();
+        .map(|i| i.map(|i| i.to_owned()).collect::<Vec<&Score>>())
+        .collect::<Vec<Vec<&Score>>>();
 
     let max = chunks.len() - 1;
     let mut index = 0;
@@ -121,111 +124,7 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
 
     loop {
         for (idx, data) in chunks[index].iter().enumerate() {
-            let beatmap_file = client_lock
-                .read()
-                .await
-                .get(&format!("https://osu.ppy.sh/web/maps/{}", &data.beatmap.id))
-                .send()
-                .await?
-                .text()
-                .await?;
-
-            let map = if let Ok(x) = Map::parse(beatmap_file.as_bytes()) { x } else { continue }; // TODO: Self::from_str()
-            let mods = Mods::from_strs(&data.mods);
-            let difficulty = Difficulty::calc(&map, mods);
-
-            let map_statistics = MapStatistics::new(
-                data.beatmap.ar,
-                data.beatmap.accuracy,
-                data.beatmap.cs,
-                data.beatmap.drain,
-            )
-            .with_mods(mods);
-
-            let accuracy = Accuracy {
-                n300: data.statistics.count_300 as i32,
-                n100: data.statistics.count_100 as i32,
-                n50: data.statistics.count_50 as i32,
-                misses: data.statistics.count_miss as i32,
-            }; // TODO: Self::new()
-
-            let accuracy_fc = Accuracy {
-                n300: (data.statistics.count_300 + data.statistics.count_miss) as i32,
-                n100: data.statistics.count_100 as i32,
-                n50: data.statistics.count_50 as i32,
-                misses: 0,
-            }; // TODO: Self::new()
-
-            let progress: f32 = progress_math(
-                data.beatmap.count_circles as f32,
-                data.beatmap.count_sliders as f32,
-                data.beatmap.count_spinners as f32,
-                data.statistics.count_300 as f32,
-                data.statistics.count_100 as f32,
-                data.statistics.count_50 as f32,
-                data.statistics.count_miss as f32,
-            );
-
-            trace!("Calculating v1 for {}", data.id);
-
-            let pp_v1 = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                Some(data.max_combo as u32),
-                mods,
-                accuracy,
-                1,
-                None,
-            );
-
-            trace!("Calculating v2 for {}", data.id);
-
-            let pp_v2 = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                Some(data.max_combo as u32),
-                mods,
-                accuracy,
-                2,
-                None,
-            );
-
-            trace!("Calculating v1 fc for {}", data.id);
-
-            let pp_v1_fc = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                None,
-                mods,
-                accuracy_fc,
-                1,
-                None,
-            );
-
-            trace!("Calculating v2 fc for {}", data.id);
-
-            let pp_v2_fc = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                None,
-                mods,
-                accuracy_fc,
-                2,
-                None,
-            );
-
-            debug!(
-                "{}: {} {} {} {}",
-                data.id, pp_v1.total, pp_v2.total, pp_v1_fc.total, pp_v2_fc.total
-            );
+            let summary = if let Some(x) = summarize_score(&api, mode, data).await? { x } else { continue };
 
             let mut embed = CreateEmbed::default();
 
@@ -293,12 +192,9 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
                 });
                 f.text({
                     format!(
-                        "{:.2}pp SV1 | {:.2}pp SV2 | {:.2}pp SV1 FC | {:.2}pp SV2 FC\nProgress: {:.2}% | {} | Played",
-                        pp_v1.total,
-                        pp_v2.total,
-                        pp_v1_fc.total,
-                        pp_v2_fc.total,
-                        progress,
+                        "{}\n{}{} | Played",
+                        summary.pp,
+                        summary.progress_text(),
                         capitalize_first(&data.beatmapset.status),
                     )
                 })
@@ -306,28 +202,20 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
             embed.timestamp(&data.created_at);
             embed.description(
                 format!(
-                    "__Mapped by **[{}](https://osu.ppy.sh/users/{})**__ | Difficulty **{}** {}\n**{:.2}\\*** ({:.2}\\* Aim | {:.2}\\* Speed)** {}**\nAR {:.1} | OD {:.1} | CS {:.1} | HP {:.1}\n**{}** ┇ **x{}** / {} {}\n**{:.2}%** ┇ {} - {} - {} - ~~{}~~",
+                    "__Mapped by **[{}](https://osu.ppy.sh/users/{})**__ | Difficulty **{}** {}\n{}** {}**\n{}\n**{}** ┇ **x{}** / {} {}\n**{:.2}%** ┇ {}",
                     data.beatmapset.creator,
                     data.beatmapset.user_id,
                     data.beatmap.version,
                     if data.beatmapset.nsfw { "*Explicit*" } else { "" },
-                    difficulty.total,
-                    difficulty.aim,
-                    difficulty.speed,
+                    summary.difficulty,
                     data.mods.join(", "),
-                    difficulty.stats.ar,
-                    difficulty.stats.od,
-                    difficulty.stats.cs,
-                    difficulty.stats.hp,
+                    summary.stats,
                     data.score.to_formatted_string(&Locale::en),
                     data.max_combo,
-                    map.max_combo,
+                    summary.max_combo,
                     if data.perfect { "**FC**" } else { "" },
-                    accuracy.value() * 100.0,
-                    data.statistics.count_300,
-                    data.statistics.count_100,
-                    data.statistics.count_50,
-                    data.statistics.count_miss,
+                    summary.accuracy,
+                    mode_hits(mode, &data.statistics),
                 )
             );
 
@@ -522,14 +410,11 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
 
     let data = &chunks[index][to_keep];
 
-    let beatmap_file = client_lock
-        .read()
-        .await
-        .get(&format!("https://osu.ppy.sh/web/maps/{}", &data.beatmap.id))
-        .send()
+    let summary = summarize_score(&api, mode, data)
         .await?
-        .text()
-        .await?;
+        .ok_or("Unable to read the beatmap file.")?;
+
+    set_channel_beatmap(ctx, msg.channel_id, data.beatmap.id).await;
 
     message.edit(ctx, |m| {
         m.content(format!("`{}`", data.beatmap.id));
@@ -550,7 +435,7 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
                 if let Some(id) = data.best_id {
                     ar.create_button(|b| {
                         b.style(ButtonStyle::Link);
-                        b.url(format!("https://osu.ppy.sh/scores/osu/{}", id));
+                        b.url(format!("https://osu.ppy.sh/scores/{}/{}", mode.name(), id));
                         b.label("Share Score!")
                     });
                 }
@@ -559,90 +444,6 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
             })
         });
         m.embed(|embed| {
-            let map = Map::parse(beatmap_file.as_bytes()).unwrap(); // TODO: Self::from_str()
-            let mods = Mods::from_strs(&data.mods);
-            let difficulty = Difficulty::calc(&map, mods);
-
-            let map_statistics = MapStatistics::new(
-                data.beatmap.ar,
-                data.beatmap.accuracy,
-                data.beatmap.cs,
-                data.beatmap.drain,
-            )
-            .with_mods(mods);
-
-            let accuracy = Accuracy {
-                n300: data.statistics.count_300 as i32,
-                n100: data.statistics.count_100 as i32,
-                n50: data.statistics.count_50 as i32,
-                misses: data.statistics.count_miss as i32,
-            }; // TODO: Self::new()
-
-            let accuracy_fc = Accuracy {
-                n300: (data.statistics.count_300 + data.statistics.count_miss) as i32,
-                n100: data.statistics.count_100 as i32,
-                n50: data.statistics.count_50 as i32,
-                misses: 0,
-            }; // TODO: Self::new()
-
-            let progress: f32 = progress_math(
-                data.beatmap.count_circles as f32,
-                data.beatmap.count_sliders as f32,
-                data.beatmap.count_spinners as f32,
-                data.statistics.count_300 as f32,
-                data.statistics.count_100 as f32,
-                data.statistics.count_50 as f32,
-                data.statistics.count_miss as f32,
-            );
-
-            let pp_v1 = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                Some(data.max_combo as u32),
-                mods,
-                accuracy,
-                1,
-                None,
-            );
-
-            let pp_v2 = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                Some(data.max_combo as u32),
-                mods,
-                accuracy,
-                2,
-                None,
-            );
-
-            let pp_v1_fc = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                None,
-                mods,
-                accuracy_fc,
-                1,
-                None,
-            );
-
-            let pp_v2_fc = PpV2::pp(
-                &map,
-                &map_statistics,
-                difficulty.aim,
-                difficulty.speed,
-                None,
-                mods,
-                accuracy_fc,
-                2,
-                None,
-            );
-
             embed.title({
                 if data.beatmapset.artist != data.beatmapset.artist_unicode
                     && data.beatmapset.title != data.beatmapset.title_unicode
@@ -696,12 +497,9 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
                 });
                 f.text({
                     format!(
-                        "{:.2}pp SV1 | {:.2}pp SV2 | {:.2}pp SV1 FC | {:.2}pp SV2 FC\nProgress: {:.2}% | {} | Played",
-                        pp_v1.total,
-                        pp_v2.total,
-                        pp_v1_fc.total,
-                        pp_v2_fc.total,
-                        progress,
+                        "{}\n{}{} | Played",
+                        summary.pp,
+                        summary.progress_text(),
                         capitalize_first(&data.beatmapset.status),
                     )
                 })
@@ -709,28 +507,20 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
             embed.timestamp(&data.created_at);
             embed.description(
                 format!(
-                    "__Mapped by **[{}](https://osu.ppy.sh/users/{})**__ | Difficulty **{}** {}\n**{:.2}\\*** ({:.2}\\* Aim | {:.2}\\* Speed)** {}**\nAR {:.1} | OD {:.1} | CS {:.1} | HP {:.1}\n**{}** ┇ **x{}** / {} {}\n**{:.2}%** ┇ {} - {} - {} - ~~{}~~\nScoreable: {} ┇ {} ❤️\nLast updated: <t:{}:F>",
+                    "__Mapped by **[{}](https://osu.ppy.sh/users/{})**__ | Difficulty **{}** {}\n{}** {}**\n{}\n**{}** ┇ **x{}** / {} {}\n**{:.2}%** ┇ {}\nScoreable: {} ┇ {} ❤️\nLast updated: <t:{}:F>",
                     data.beatmapset.creator,
                     data.beatmapset.user_id,
                     data.beatmap.version,
                     if data.beatmapset.nsfw { "*Explicit*" } else { "" },
-                    difficulty.total,
-                    difficulty.aim,
-                    difficulty.speed,
+                    summary.difficulty,
                     data.mods.join(", "),
-                    difficulty.stats.ar,
-                    difficulty.stats.od,
-                    difficulty.stats.cs,
-                    difficulty.stats.hp,
+                    summary.stats,
                     data.score.to_formatted_string(&Locale::en),
                     data.max_combo,
-                    map.max_combo,
+                    summary.max_combo,
                     if data.perfect { "**FC**" } else { "" },
-                    accuracy.value() * 100.0,
-                    data.statistics.count_300,
-                    data.statistics.count_100,
-                    data.statistics.count_50,
-                    data.statistics.count_miss,
+                    summary.accuracy,
+                    mode_hits(mode, &data.statistics),
                     if data.beatmap.is_scoreable { "✅" } else { "❌" },
                     data.beatmapset.favourite_count,
                     data.beatmap.last_updated.timestamp(),
@@ -755,3 +545,189 @@ async fn new_recent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
 
     Ok(())
 }
+
+// The difficulty, accuracy and pp of a score, calculated for the mode it was played on.
+struct ScoreSummary {
+    difficulty: String,
+    stats: String,
+    max_combo: u64,
+    accuracy: f64,
+    pp: String,
+    progress: Option<f64>,
+}
+
+impl ScoreSummary {
+    fn progress_text(&self) -> String {
+        self.progress
+            .map(|i| format!("Progress: {:.2}% | ", i))
+            .unwrap_or_default()
+    }
+}
+
+// Returns None if the beatmap of the score can't be read.
+async fn summarize_score(
+    api: &OsuApi,
+    mode: GameMode,
+    data: &Score,
+) -> Result<Option<ScoreSummary>, Error> {
+    let progress = mode_progress(mode, &data.beatmap, &data.statistics);
+
+    // The other modes use the difficulty attributes of the API, as osu_perf is osu!std only.
+    if mode != GameMode::Osu {
+        let attributes = if let Some(x) = api
+            .beatmap_attributes(data.beatmap.id, &data.mods, Some(mode))
+            .await?
+        {
+            x
+        } else {
+            return Ok(None);
+        };
+
+        let mut pp = PpCalculation::from_score(
+            mode,
+            &data.beatmap,
+            &attributes,
+            &data.mods,
+            &data.statistics,
+            data.max_combo,
+            data.score,
+        );
+        pp.progress = progress.unwrap_or(100.0);
+
+        let pp_total = pp.calculate();
+
+        // osu!mania pp only depend on the score, so there's no full combo pp.
+        let pp_text = if mode == GameMode::Mania {
+            format!("{:.2}pp", pp_total)
+        } else {
+            pp.score_great += pp.score_miss;
+            pp.score_miss = 0.0;
+            pp.score_max_combo = pp.map_max_combo;
+
+            format!("{:.2}pp | {:.2}pp FC", pp_total, pp.calculate())
+        };
+
+        return Ok(Some(ScoreSummary {
+            difficulty: format!(
+                "**{:.2}\\*** {}",
+                attributes.star_rating,
+                mode.display_name()
+            ),
+            stats: format!(
+                "OD {:.1} | CS {:.1} | HP {:.1}",
+                data.beatmap.accuracy, data.beatmap.cs, data.beatmap.drain
+            ),
+            max_combo: attributes.max_combo,
+            accuracy: mode_accuracy(mode, &data.statistics),
+            pp: pp_text,
+            progress,
+        }));
+    }
+
+    let beatmap_file = api.beatmap_file(data.beatmap.id).await?;
+
+    let map = if let Ok(x) = Map::parse(beatmap_file.as_bytes()) { x } else { return Ok(None) }; // TODO: Self::from_str()
+    let mods = Mods::from_strs(&data.mods);
+    let difficulty = Difficulty::calc(&map, mods);
+
+    let map_statistics = MapStatistics::new(
+        data.beatmap.ar,
+        data.beatmap.accuracy,
+        data.beatmap.cs,
+        data.beatmap.drain,
+    )
+    .with_mods(mods);
+
+    let accuracy = Accuracy {
+        n300: data.statistics.count_300 as i32,
+        n100: data.statistics.count_100 as i32,
+        n50: data.statistics.count_50 as i32,
+        misses: data.statistics.count_miss as i32,
+    }; // TODO: Self::new()
+
+    let accuracy_fc = Accuracy {
+        n300: (data.statistics.count_300 + data.statistics.count_miss) as i32,
+        n100: data.statistics.count_100 as i32,
+        n50: data.statistics.count_50 as i32,
+        misses: 0,
+    }; // TODO: Self::new()
+
+    trace!("Calculating v1 for {}", data.id);
+
+    let pp_v1 = PpV2::pp(
+        &map,
+        &map_statistics,
+        difficulty.aim,
+        difficulty.speed,
+        Some(data.max_combo as u32),
+        mods,
+        accuracy,
+        1,
+        None,
+    );
+
+    trace!("Calculating v2 for {}", data.id);
+
+    let pp_v2 = PpV2::pp(
+        &map,
+        &map_statistics,
+        difficulty.aim,
+        difficulty.speed,
+        Some(data.max_combo as u32),
+        mods,
+        accuracy,
+        2,
+        None,
+    );
+
+    trace!("Calculating v1 fc for {}", data.id);
+
+    let pp_v1_fc = PpV2::pp(
+        &map,
+        &map_statistics,
+        difficulty.aim,
+        difficulty.speed,
+        None,
+        mods,
+        accuracy_fc,
+        1,
+        None,
+    );
+
+    trace!("Calculating v2 fc for {}", data.id);
+
+    let pp_v2_fc = PpV2::pp(
+        &map,
+        &map_statistics,
+        difficulty.aim,
+        difficulty.speed,
+        None,
+        mods,
+        accuracy_fc,
+        2,
+        None,
+    );
+
+    debug!(
+        "{}: {} {} {} {}",
+        data.id, pp_v1.total, pp_v2.total, pp_v1_fc.total, pp_v2_fc.total
+    );
+
+    Ok(Some(ScoreSummary {
+        difficulty: format!(
+            "**{:.2}\\*** ({:.2}\\* Aim | {:.2}\\* Speed)",
+            difficulty.total, difficulty.aim, difficulty.speed
+        ),
+        stats: format!(
+            "AR {:.1} | OD {:.1} | CS {:.1} | HP {:.1}",
+            difficulty.stats.ar, difficulty.stats.od, difficulty.stats.cs, difficulty.stats.hp
+        ),
+        max_combo: map.max_combo as u64,
+        accuracy: accuracy.value() * 100.0,
+        pp: format!(
+            "{:.2}pp SV1 | {:.2}pp SV2 | {:.2}pp SV1 FC | {:.2}pp SV2 FC",
+            pp_v1.total, pp_v2.total, pp_v1_fc.total, pp_v2_fc.total
+        ),
+        progress,
+    }))
+}
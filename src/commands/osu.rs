@@ -1,17 +1,26 @@
 //! This is the file containing all the osu! related commands.
 
 use crate::{
-    global_data::{DatabasePool, Tokens},
-    utils::basic_functions::{pacman, seconds_to_days},
+    global_data::DatabasePool,
+    utils::basic_functions::{capitalize_first, pacman, seconds_to_days},
+    utils::music::format_duration,
     utils::osu::*,
+    utils::osu_api::{Error, OsuApi, ScoreKind},
+    utils::osu_images::{cached_avatar, take_card_flag, CardTheme, ProfileCard},
+    utils::osu_model::{BeatmapScore, BeatmapWithSet, GameMode, Score, Statistics, UserExtended},
+    utils::osu_preview::{is_osu_replay, send_replay_summary, MAX_REPLAY_SIZE},
     MY_HELP, OSU_GROUP,
 };
 
 use serenity::{
     builder::CreateEmbed,
     framework::standard::{macros::command, Args, CommandResult, Delimiter},
-    http::Http,
-    model::channel::{Message, ReactionType},
+    http::{AttachmentType, Http},
+    model::{
+        channel::{Message, ReactionType},
+        interactions::message_component::*,
+        interactions::InteractionResponseType,
+    },
     prelude::Context,
     utils::Colour,
 };
@@ -19,151 +28,28 @@ use serenity::{
 //use futures::TryStreamExt;
 //use futures::stream::StreamExt;
 
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use std::{borrow::Cow, cmp::Ordering, collections::HashSet, sync::Arc, time::Duration};
 
 // Used to format the numbers on the embeds.
 use num_format::{Locale, ToFormattedString};
 
+use clap::{App, Arg};
+use osu_perf::{Accuracy, Difficulty, Map, MapStatistics, Mods, PpV2};
 use reqwest::Url;
-use serde::Deserialize;
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
 
-use clap::{App, Arg};
+// How many scores are shown on each page of the leaderboard.
+const LEADERBOARD_PAGE_SIZE: usize = 10;
+// Every member on the leaderboard costs an API request, so big guilds only check some of them.
+const MAX_LEADERBOARD_MEMBERS: usize = 25;
 
 #[derive(Default, Debug)]
 struct OsuData {
     id: i32,
     username: String,
     pp: bool,
-}
-
-// JSON Structure of the osu! user API request.
-#[derive(Deserialize, Debug)]
-struct OsuUserData {
-    user_id: String,
-    username: String,
-    join_date: String,
-    country: String,
-    count300: Option<String>,
-    count100: Option<String>,
-    count50: Option<String>,
-    playcount: Option<String>,
-    ranked_score: Option<String>,
-    total_score: Option<String>,
-    pp_rank: Option<String>,
-    level: Option<String>,
-    pp_raw: Option<String>,
-    accuracy: Option<String>,
-    count_rank_ss: Option<String>,
-    count_rank_ssh: Option<String>,
-    count_rank_s: Option<String>,
-    count_rank_sh: Option<String>,
-    count_rank_a: Option<String>,
-    total_seconds_played: Option<String>,
-    pp_country_rank: Option<String>,
-}
-
-// JSON Structure of the osu! scores API request.
-#[derive(Deserialize, Debug)]
-struct OsuScores {
-    score_id: String,
-    score: String,
-    username: String,
-    maxcombo: String,
-    count50: String,
-    count100: String,
-    count300: String,
-    countmiss: String,
-    countkatu: String,
-    countgeki: String,
-    perfect: String,
-    enabled_mods: String,
-    user_id: String,
-    date: String,
-    rank: String,
-    pp: Option<String>,
-    replay_available: String,
-}
-
-// JSON Structure of the osu! user recent plays API request.
-#[derive(Deserialize, Debug, Clone)]
-struct OsuUserRecentData {
-    beatmap_id: String,
-    score: String,
-    maxcombo: String,
-    count50: String,
-    count100: String,
-    count300: String,
-    countmiss: String,
-    countkatu: String,
-    countgeki: String,
-    perfect: String,
-    enabled_mods: String,
-    user_id: String,
-    date: String,
-    rank: String,
-}
-
-// JSON Structure of the osu! beatmap API request.
-#[derive(Deserialize, Debug)]
-struct OsuBeatmapData {
-    approved: String,
-    submit_date: String,
-    approved_date: Option<String>,
-    last_update: String,
-    artist: String,
-    beatmap_id: String,
-    beatmapset_id: String,
-    bpm: String,
-    creator: String,
-    creator_id: String,
-    difficultyrating: String,
-    diff_aim: String,
-    diff_speed: String,
-    diff_size: String,
-    diff_overall: String,
-    diff_approach: String,
-    diff_drain: String,
-    hit_length: String,
-    source: String,
-    genre_id: String,
-    language_id: String,
-    title: String,
-    total_length: String,
-    version: String,
-    file_md5: String,
-    mode: String,
-    tags: String,
-    favourite_count: String,
-    rating: String,
-    playcount: String,
-    passcount: String,
-    count_normal: String,
-    count_slider: String,
-    count_spinner: String,
-    max_combo: String,
-    download_unavailable: String,
-    audio_unavailable: String,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct OsuUserBest {
-    beatmap_id: String,
-    score_id: String,
-    score: String,
-    maxcombo: String,
-    count50: String,
-    count100: String,
-    count300: String,
-    countmiss: String,
-    countkatu: String,
-    countgeki: String,
-    perfect: String,
-    enabled_mods: String,
-    user_id: String,
-    date: String,
-    rank: String,
-    pp: String,
-    replay_available: String,
+    mode: GameMode,
 }
 
 // Data Structure of the data obtained on the database.
@@ -188,41 +74,15 @@ struct OsuUserRawDBData {
 struct OsuUserRawDBDataMinimal {
     osu_username: String, // String::new()
     pp: Option<bool>,
+    mode: Option<i32>,
 }
 
 // Centralized data, to be used for the events.
-#[derive(Default, Clone)]
+#[derive(Default)]
 pub struct EventData {
     user_db_data: Option<OsuUserDBData>,
-    user_recent_raw: Option<Vec<OsuUserRecentData>>,
-    osu_key: Option<String>,
-}
-
-// Calculates the accuracy % from the number of 300's 100's 50's and misses.
-async fn acc_math(score_300: f32, score_100: f32, score_50: f32, _miss: f32) -> f32 {
-    let mix = score_300 + score_100 + score_50 + _miss;
-
-    let pcount50 = score_50 / mix * (100.0 / 6.0);
-    let pcount100 = score_100 / mix * (100.0 / 3.0);
-    let pcount300 = score_300 / mix * 100.0;
-
-    let acc: f32 = pcount50 + pcount100 + pcount300;
-    acc
-}
-
-// Calculates the progress on the map with the number of notes hit over the number of notes the map has.
-pub fn progress_math(
-    count_normal: f32,
-    count_slider: f32,
-    count_spinner: f32,
-    score_300: f32,
-    score_100: f32,
-    score_50: f32,
-    _miss: f32,
-) -> f32 {
-    let all_the_things = count_normal + count_slider + count_spinner;
-    let everything = score_300 + score_100 + score_50 + _miss;
-    everything / all_the_things * 100.0
+    user_recent_raw: Option<Vec<Score>>,
+    api: Option<OsuApi>,
 }
 
 // Obtains the long named version of the mods
@@ -233,140 +93,16 @@ async fn _get_mods_long(value: u32) -> String {
     format!("{:?}", mods)
 }
 
-// Obtains the short named version of the mods
-async fn get_mods_short(value: u32) -> String {
-    use bitwhise_mods::ShortMods;
-
-    let mods = ShortMods::from_bits_truncate(value);
-    format!("{:?}", mods)
-}
-
-// This function simply calls the osu! api to get the id of the user from a username.
-async fn get_osu_id(
-    name: &str,
-    osu_key: &str,
-) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
-    let resp = get_osu_user(&name, osu_key).await?;
-
-    if !resp.is_empty() {
-        let id: i32 = resp[0].user_id.trim().parse()?;
-        Ok(id)
-    } else {
-        Ok(0)
-    }
-}
-
-// Requests to the api the user data
-async fn get_osu_user(
-    name: &str,
-    osu_key: &str,
-) -> Result<Vec<OsuUserData>, Box<dyn std::error::Error + Send + Sync>> {
-    let url = Url::parse_with_params(
-        "https://osu.ppy.sh/api/get_user",
-        &[("k", osu_key), ("u", name), ("type", "string")],
-    )?;
-    let resp = reqwest::get(url).await?.json::<Vec<OsuUserData>>().await?;
-    Ok(resp)
-}
-
-// Requests to the api the user data
-async fn get_osu_username(
-    id: &i32,
-    osu_key: &str,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let url = Url::parse_with_params(
-        "https://osu.ppy.sh/api/get_user",
-        &[("k", osu_key), ("u", &id.to_string()), ("type", "id")],
-    )?;
-    let resp = reqwest::get(url).await?.json::<Vec<OsuUserData>>().await?;
-
-    if let Some(x) = resp.get(0) {
-        Ok(x.username.to_string())
-    } else {
-        Ok(String::new())
-    }
-}
-
-// Requests to the api the scores of a map
-async fn get_osu_scores(
-    user_id: i32,
-    user_name: &str,
-    map_id: u64,
-    mode: i32,
-    osu_key: &str,
-) -> Result<Vec<OsuScores>, Box<dyn std::error::Error + Send + Sync>> {
-    let url = if user_id != 0 {
-        Url::parse_with_params(
-            "https://osu.ppy.sh/api/get_scores",
-            &[
-                ("k", osu_key),
-                ("u", &user_id.to_string()),
-                ("b", &map_id.to_string()),
-                ("m", &mode.to_string()),
-                ("type", "id"),
-            ],
-        )?
-    } else {
-        Url::parse_with_params(
-            "https://osu.ppy.sh/api/get_scores",
-            &[
-                ("k", osu_key),
-                ("u", user_name),
-                ("b", &map_id.to_string()),
-                ("m", &mode.to_string()),
-                ("type", "string"),
-            ],
-        )?
-    };
-
-    let resp = reqwest::get(url).await?.json::<Vec<OsuScores>>().await?;
-    Ok(resp)
-}
-
-// Requests to the api the recent plays of a user
-async fn get_osu_user_recent(
-    user_id: i32,
-    osu_key: &str,
-) -> Result<Vec<OsuUserRecentData>, Box<dyn std::error::Error + Send + Sync>> {
-    let url = format!(
-        "https://osu.ppy.sh/api/get_user_recent?k={}&u={}&type=id&limit=50",
-        osu_key, user_id
-    );
-    let resp = reqwest::get(&url)
-        .await?
-        .json::<Vec<OsuUserRecentData>>()
-        .await?;
-    Ok(resp)
-}
-
-// Requests to the api the data of a beatmap
-async fn get_osu_beatmap(
-    beatmap_id: &str,
-    osu_key: &str,
-) -> Result<Vec<OsuBeatmapData>, Box<dyn std::error::Error + Send + Sync>> {
-    let url = format!(
-        "https://osu.ppy.sh/api/get_beatmaps?k={}&b={}",
-        osu_key, beatmap_id
-    );
-    let resp = reqwest::get(&url)
-        .await?
-        .json::<Vec<OsuBeatmapData>>()
-        .await?;
-
-    Ok(resp)
+// Obtains the id of a user from the username, 0 if the user doesn't exist.
+async fn get_osu_id(api: &OsuApi, name: &str) -> Result<i32, Error> {
+    let user = api.user_by_name(name, None).await?;
+    Ok(user.map(|i| i.id as i32).unwrap_or(0))
 }
 
-// Requests to the api the top plays or best plays of a user (by pp weight).
-async fn get_osu_user_best(
-    user_id: &i32,
-    osu_key: &str,
-) -> Result<Vec<OsuUserBest>, Box<dyn std::error::Error + Send + Sync>> {
-    let url = format!(
-        "https://osu.ppy.sh/api/get_user_best?k={}&u={}&type=id&limit=100&m=0",
-        osu_key, user_id
-    );
-    let resp = reqwest::get(&url).await?.json::<Vec<OsuUserBest>>().await?;
-    Ok(resp)
+// Obtains the username of a user from the id, empty if the user doesn't exist.
+async fn get_osu_username(api: &OsuApi, id: i32) -> Result<String, Error> {
+    let user = api.user_by_id(id as u64, None).await?;
+    Ok(user.map(|i| i.username).unwrap_or_default())
 }
 
 // Builds the short version of the recent embed and edits the specified message with it.
@@ -378,35 +114,28 @@ async fn short_recent_builder(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let user_data = event_data.user_db_data.as_ref().unwrap();
     let user_recent_raw = event_data.user_recent_raw.as_ref().unwrap();
-    let osu_key = event_data.osu_key.as_ref().unwrap();
+    let api = event_data.api.as_ref().unwrap();
 
+    let mode = GameMode::from_id(user_data.mode.unwrap_or(0));
     let user_recent = &user_recent_raw[index];
-    let user_raw = get_osu_user(&user_data.name, &osu_key).await?;
-    let user = &user_raw[0];
+    let user = &user_recent.user;
+    let beatmap = &user_recent.beatmap;
+    let beatmapset = &user_recent.beatmapset;
 
-    let beatmap_raw = get_osu_beatmap(&user_recent.beatmap_id, &osu_key).await?;
-    let beatmap = &beatmap_raw[0];
+    // The star rating and strains change with the mods and the mode.
+    let attributes = api
+        .beatmap_attributes(beatmap.id, &user_recent.mods, Some(mode))
+        .await?
+        .ok_or("The beatmap of the score no longer exists.")?;
 
-    let accuracy = acc_math(
-        user_recent.count300.parse()?,
-        user_recent.count100.parse()?,
-        user_recent.count50.parse()?,
-        user_recent.countmiss.parse()?,
-    )
-    .await;
-
-    let progress: f32 = progress_math(
-        beatmap.count_normal.parse()?,
-        beatmap.count_slider.parse()?,
-        beatmap.count_spinner.parse()?,
-        user_recent.count300.parse()?,
-        user_recent.count100.parse()?,
-        user_recent.count50.parse()?,
-        user_recent.countmiss.parse()?,
-    );
+    let accuracy = mode_accuracy(mode, &user_recent.statistics);
+    let progress = mode_progress(mode, beatmap, &user_recent.statistics);
+    let progress_text = progress
+        .map(|i| format!(" ━ Progress: {:.2}%", i))
+        .unwrap_or_default();
 
     let attempts = index;
-    let mods: String = get_mods_short(user_recent.enabled_mods.parse()?).await;
+    let mods = get_mods_short(&user_recent.mods);
 
     let rating_url = if user_recent.rank == "F" {
         String::from("https://5124.mywire.org/HDD/Downloads/BoneF.png")
@@ -417,93 +146,103 @@ async fn short_recent_builder(
         )
     };
 
-    bot_msg.clone().edit(http.clone(), |m| { // say method doesn't work for the message builder.
-        m.content(format!("`{}`", beatmap.beatmap_id));
-        m.embed( |e| {
-            e.color(Colour::new({
-                let colour = user.user_id.parse().unwrap();
-                if colour > 16777215 {
-                    15227880
-                } else {
-                    colour
-                }
-            }));
-            e.title(format!("{} - {} [**{}**]\nby {}",
-                    beatmap.artist, beatmap.title, beatmap.version, beatmap.creator));
-            e.url(format!("https://osu.ppy.sh/b/{}", beatmap.beatmap_id));
-            e.description(format!("**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {} - {} - {} - {}\n Recent #{} ━ Progress: {:.2}%",
-                    user_recent.score.parse::<u32>().expect("NaN").to_formatted_string(&Locale::en), user_recent.maxcombo, beatmap.max_combo, accuracy, user_recent.count300, user_recent.count100, user_recent.count50, user_recent.countmiss, attempts + 1, progress));
-            e.timestamp(user_recent.date.clone());
-            e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", beatmap.beatmapset_id));
-            e.author( |a| {
-                a.name(&user.username);
-                a.url(format!("https://osu.ppy.sh/u/{}", user.user_id));
-                a.icon_url(format!("https://a.ppy.sh/{}", user.user_id));
-
-                a
-            });
-            if user_data.pp == Some(true) {
-                e.footer(|f| {
-                    let mut pp = PpCalculation::default();
-
-                    pp.score_mods = {
-                        let split = mods.split(" | ");
-                        split.map(|i| {
-                            if i != "NM" || i != "V2" {
-                                i.to_string()
-                            } else {
-                                String::new()
-                            }
-                        }).collect::<Vec<String>>()
-                    };
-                    pp.score_max_combo = user_recent.maxcombo.parse::<f64>().unwrap();
-                    pp.score_great = user_recent.count300.parse::<f64>().unwrap();
-                    pp.score_good = user_recent.count100.parse::<f64>().unwrap();
-                    pp.score_meh = user_recent.count50.parse::<f64>().unwrap();
-                    pp.score_miss = user_recent.countmiss.parse::<f64>().unwrap();
-
-                    pp.map_aim_strain = beatmap.diff_aim.parse::<f64>().unwrap();
-                    pp.map_speed_strain = beatmap.diff_speed.parse::<f64>().unwrap();
-
-                    pp.map_max_combo = beatmap.max_combo.parse::<f64>().unwrap();
-                    pp.map_ar = beatmap.diff_approach.parse::<f64>().unwrap();
-                    pp.map_od = beatmap.diff_overall.parse::<f64>().unwrap();
-
-                    pp.map_circles = beatmap.count_normal.parse::<f64>().unwrap();
-                    pp.map_sliders = beatmap.count_slider.parse::<f64>().unwrap();
-                    pp.map_spinners = beatmap.count_spinner.parse::<f64>().unwrap();
-
-                    pp.progress = progress as f64;
-
-                    let v1_pp = pp.calculate();
-                    pp.score_mods.push("V2".to_string());
-                    let v2_pp = pp.calculate();
-
-                    f.text(format!("{:.2}pp | {:.2} sv2 pp | {:.4}* | {}", v1_pp, v2_pp, beatmap.difficultyrating, mods));
-                    f.icon_url(&rating_url);
-
-                    f
-                });
-            } else {
-                e.footer(|f| {
-                    f.text(format!("{:.4}* | {}", beatmap.difficultyrating, mods));
-                    f.icon_url(&rating_url);
+    bot_msg
+        .clone()
+        .edit(http.clone(), |m| {
+            // say method doesn't work for the message builder.
+            m.content(format!("`{}`", beatmap.id));
+            m.embed(|e| {
+                e.color(Colour::new({
+                    let colour = user.id as u32;
+                    if colour > 16777215 {
+                        15227880
+                    } else {
+                        colour
+                    }
+                }));
+                e.title(format!(
+                    "{} - {} [**{}**]\nby {}",
+                    beatmapset.artist, beatmapset.title, beatmap.version, beatmapset.creator
+                ));
+                e.url(format!("https://osu.ppy.sh/b/{}", beatmap.id));
+                e.description(format!(
+                    "**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {}\n Recent #{}{}",
+                    user_recent.score.to_formatted_string(&Locale::en),
+                    user_recent.max_combo,
+                    attributes.max_combo,
+                    accuracy,
+                    mode_hits(mode, &user_recent.statistics),
+                    attempts + 1,
+                    progress_text
+                ));
+                e.timestamp(&user_recent.created_at);
+                e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", beatmapset.id));
+                e.author(|a| {
+                    a.name(&user.username);
+                    a.url(format!("https://osu.ppy.sh/u/{}", user.id));
+                    a.icon_url(&user.avatar_url);
 
-                    f
+                    a
                 });
-            }
+                if user_data.pp == Some(true) {
+                    e.footer(|f| {
+                        let mut pp = PpCalculation::from_score(
+                            mode,
+                            beatmap,
+                            &attributes,
+                            &user_recent.mods,
+                            &user_recent.statistics,
+                            user_recent.max_combo,
+                            user_recent.score,
+                        );
+
+                        pp.progress = progress.unwrap_or(100.0);
+
+                        let v1_pp = pp.calculate();
+
+                        // Only osu!std has a different pp calculation for score v2.
+                        if mode == GameMode::Osu {
+                            pp.score_mods.push("V2".to_string());
+                            let v2_pp = pp.calculate();
+
+                            f.text(format!(
+                                "{:.2}pp | {:.2} sv2 pp | {:.4}* | {}",
+                                v1_pp, v2_pp, attributes.star_rating, mods
+                            ));
+                        } else {
+                            f.text(format!(
+                                "{:.2}pp | {:.4}* | {} | {}",
+                                v1_pp,
+                                attributes.star_rating,
+                                mods,
+                                mode.display_name()
+                            ));
+                        }
+                        f.icon_url(&rating_url);
+
+                        f
+                    });
+                } else {
+                    e.footer(|f| {
+                        f.text(format!("{:.4}* | {}", attributes.star_rating, mods));
+                        f.icon_url(&rating_url);
+
+                        f
+                    });
+                }
 
-            e
-        });
+                e
+            });
 
-        m
-    }).await?;
+            m
+        })
+        .await?;
     Ok(())
 }
 
 /// Command to configure an osu! user for the bot to know about your prefferences.
 /// This supports various keyword parameters, this are:
-/// `mode=` To set your osu! gamemode.
+/// `mode=` or `-m` To set your osu! gamemode.
 /// `pp=` To show or not show any pp related features for your account.
 /// `short_recent=` To display the short version of the recent command with less information, but more cozy.
 ///
@@ -527,11 +266,7 @@ async fn short_recent_builder(
     "osu_set"
 )]
 async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult {
-    let osu_key = {
-        let data = ctx.data.read().await; // set inmutable global data.
-        let tokens = data.get::<Tokens>().unwrap().clone(); // get the tokens from the global data.
-        tokens.old_osu.to_string()
-    };
+    let api = OsuApi::from_context(ctx).await;
 
     let pool = {
         let data_read = ctx.data.read().await;
@@ -565,8 +300,17 @@ async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> Command
 
     // if there where arguments on the command (aka the user wants to modify a value)
     if !arguments.is_empty() {
+        // `-m taiko` works the same as `mode=taiko`
+        let (mode, rest) = take_mode_flag(arguments.message());
+        if let Some(x) = mode {
+            user_data.mode = Some(x.id());
+        }
+
         // Transforms the given arguments as a vector
-        let args = arguments.raw_quoted().collect::<Vec<&str>>();
+        let args = rest
+            .split(' ')
+            .filter(|i| !i.is_empty())
+            .collect::<Vec<&str>>();
 
         // iterates over all the arguments on the list
         for arg in args {
@@ -594,13 +338,7 @@ async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> Command
                 }
             } else if arg.starts_with("mode=") {
                 let x: &str = arg.split('=').nth(1).unwrap();
-                user_data.mode = match x {
-                    "0" | "std" | "standard" => Some(0),
-                    "1" | "taiko" => Some(1),
-                    "2" | "ctb" | "catch" => Some(2),
-                    "3" | "mania" => Some(3),
-                    _ => Some(0),
-                }
+                user_data.mode = Some(GameMode::from_name(x).unwrap_or_default().id());
 
                 // this triggers if the argument was not a keyword argument and adds the argument to
                 // the username adding a space.
@@ -648,7 +386,7 @@ async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> Command
     }
 
     // calls the get_osu_id function to get the id of the user.
-    user_data.osu_id = get_osu_id(&user_data.name, &osu_key).await?;
+    user_data.osu_id = get_osu_id(&api, &user_data.name).await?;
 
     // applies the default values in case of being not specified.
     user_data.pp = match &user_data.pp {
@@ -711,11 +449,16 @@ async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> Command
 /// You can use `osuc` to configure your osu! profile.
 ///
 /// Affected parameters for configuration:
+/// - Mode: The gamemode of the statistics, unless specified with `-m taiko|fruits|mania`.
 /// - PP: To know if the bot should display the PP stadistics.
 ///
+/// With `--card`, the profile is drawn as an image, with the `dark`, `light` or `pink` theme.
+///
 /// Usage:
 /// `osu_profile`
 /// `osu_profile -GN`
+/// `osu_profile -GN -m mania`
+/// `osu_profile -GN --card light`
 #[command]
 #[aliases(
     "oprofile",
@@ -729,19 +472,16 @@ async fn configure_osu(ctx: &Context, msg: &Message, arguments: Args) -> Command
     "osu"
 )]
 async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    // Obtains the osu! api key from the "global" data
-    let osu_key = {
-        let data = ctx.data.read().await; // set inmutable global data.
-        let tokens = data.get::<Tokens>().unwrap().clone(); // get the tokens from the global data.
-        tokens.old_osu.to_string()
-    };
+    let api = OsuApi::from_context(ctx).await;
 
     let pool = {
         let data_read = ctx.data.read().await;
         data_read.get::<DatabasePool>().unwrap().clone()
     };
 
-    let mut username = args.message().replace(" ", "_");
+    let (mut mode, username) = take_mode_flag(args.message());
+    let (card_theme, username) = take_card_flag(&username);
+    let mut username = username.replace(" ", "_");
     let author_id = *msg.author.id.as_u64() as i64;
 
     // get the author_id as a signed 64 bit int, because that's what the database asks for.
@@ -750,7 +490,7 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
         if username.is_empty() {
             sqlx::query_as!(
                 OsuUserRawDBDataMinimal,
-                "SELECT osu_username, pp FROM osu_user WHERE discord_id = $1",
+                "SELECT osu_username, pp, mode FROM osu_user WHERE discord_id = $1",
                 author_id
             )
             .fetch_optional(&pool)
@@ -758,7 +498,7 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
         } else {
             sqlx::query_as!(
                 OsuUserRawDBDataMinimal,
-                "SELECT osu_username, pp FROM osu_user WHERE osu_username = $1",
+                "SELECT osu_username, pp, mode FROM osu_user WHERE osu_username = $1",
                 username
             )
             .fetch_optional(&pool)
@@ -773,6 +513,9 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
         if username.is_empty() {
             username = row.osu_username;
         }
+        if mode.is_none() {
+            mode = row.mode.map(GameMode::from_id);
+        }
     } else if username.is_empty() {
         if let Ok(m) = msg.member(ctx).await {
             username = m.display_name().to_string();
@@ -781,10 +524,9 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
         }
     }
 
-    let resp = get_osu_user(&username, &osu_key).await?;
-
-    let user = if !resp.is_empty() {
-        &resp[0]
+    // Without a mode, the statistics are of the mode the user plays the most.
+    let user = if let Some(x) = api.user_by_name(&username, mode).await? {
+        x
     } else {
         msg.channel_id
             .say(
@@ -795,41 +537,61 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
         return Ok(());
     };
 
-    let country_url = format!("https://raw.githubusercontent.com/stevenrskelton/flag-icon/master/png/75/country-squared/{}.png", &user.country.to_lowercase());
+    if let Some(theme) = card_theme {
+        let mode = mode.unwrap_or(user.playmode);
+        return send_profile_card(ctx, msg, &api, &user, mode, pp, theme).await;
+    }
+
+    let country_url = format!("https://raw.githubusercontent.com/stevenrskelton/flag-icon/master/png/75/country-squared/{}.png", &user.country_code.to_lowercase());
 
-    if user.total_score.is_none() {
+    let stats = &user.statistics;
+    let author_name = format!(
+        "{} ({})",
+        user.username,
+        mode.unwrap_or(user.playmode).display_name()
+    );
+
+    if stats.play_count == 0 {
         msg.channel_id
             .send_message(ctx, |m| {
                 m.embed(|e| {
-                    e.timestamp(user.join_date.clone());
-                    e.thumbnail(format!("https://a.ppy.sh/{}", &user.user_id));
+                    e.timestamp(&user.join_date);
+                    e.thumbnail(&user.avatar_url);
                     e.author(|a| {
-                        a.name(&user.username);
-                        a.url(format!("https://osu.ppy.sh/u/{}", &user.user_id));
+                        a.name(&author_name);
+                        a.url(format!("https://osu.ppy.sh/u/{}", &user.id));
                         a.icon_url(country_url)
                     })
                 })
             })
             .await?;
     } else {
+        // The level with the progress to the next one as decimals, like 100.52
+        let level = format!("{}.{:02}", stats.level.current, stats.level.progress);
+        let play_time = stats.play_time.unwrap_or(0);
+        let format_rank = |rank: Option<u64>| {
+            rank.map(|i| i.to_formatted_string(&Locale::en))
+                .unwrap_or_else(|| "-".to_string())
+        };
+
         msg.channel_id
             .send_message(ctx, |m| {
                 m.embed(|e| {
                     e.color(Colour::new({
-                        let colour = user.user_id.parse().unwrap();
+                        let colour = user.id as u32;
                         if colour > 16777215 {
                             15227880
                         } else {
                             colour
                         }
                     }));
-                    e.timestamp(user.join_date.clone());
+                    e.timestamp(&user.join_date);
                     e.author(|a| {
-                        a.name(&user.username);
-                        a.url(format!("https://osu.ppy.sh/u/{}", &user.user_id));
+                        a.name(&author_name);
+                        a.url(format!("https://osu.ppy.sh/u/{}", &user.id));
                         a.icon_url(country_url)
                     });
-                    e.image(format!("https://a.ppy.sh/{}", &user.user_id));
+                    e.image(&user.avatar_url);
 
                     e.description({
                         let mut s = format!(
@@ -840,152 +602,46 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
                                 Played **{}** seconds or: **{:?}**
                                 **L{}** > Next level: `{}`
                                 ",
-                            &user
-                                .count300
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user
-                                .count100
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user
-                                .count50
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user.accuracy.as_ref().unwrap().parse::<f32>().expect("NAN"),
-                            &user
-                                .playcount
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user
-                                .total_score
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user
-                                .ranked_score
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u128>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            &user
-                                .total_seconds_played
-                                .as_ref()
-                                .unwrap()
-                                .parse::<u64>()
-                                .expect("NaN")
-                                .to_formatted_string(&Locale::en),
-                            seconds_to_days(
-                                user.total_seconds_played
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u64>()
-                                    .expect("NaN")
-                            ),
-                            user.level.as_ref().unwrap().parse::<f32>().expect("NaN") as u8,
-                            pacman(&user.level.as_ref().unwrap()),
+                            stats.count_300.to_formatted_string(&Locale::en),
+                            stats.count_100.to_formatted_string(&Locale::en),
+                            stats.count_50.to_formatted_string(&Locale::en),
+                            stats.hit_accuracy,
+                            stats.play_count.to_formatted_string(&Locale::en),
+                            stats.total_score.to_formatted_string(&Locale::en),
+                            stats.ranked_score.to_formatted_string(&Locale::en),
+                            play_time.to_formatted_string(&Locale::en),
+                            seconds_to_days(play_time),
+                            stats.level.current,
+                            pacman(&level),
                         );
                         if pp {
                             s += &format!(
                                 "Global: #**{}** | Country: #**{}**",
-                                &user
-                                    .pp_rank
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .pp_country_rank
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
+                                format_rank(stats.global_rank),
+                                format_rank(stats.country_rank),
                             );
                         }
                         s
                     });
                     e.footer(|f| {
+                        let grades = &stats.grade_counts;
+
                         if pp {
                             f.text(format!(
-                                "PP:{} | SSH:{} | SS:{} | SH:{} | S:{}",
-                                &user.pp_raw.as_ref().unwrap(),
-                                &user
-                                    .count_rank_ssh
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_ss
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_sh
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_s
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
+                                "PP:{:.2} | SSH:{} | SS:{} | SH:{} | S:{}",
+                                stats.pp,
+                                grades.ssh.to_formatted_string(&Locale::en),
+                                grades.ss.to_formatted_string(&Locale::en),
+                                grades.sh.to_formatted_string(&Locale::en),
+                                grades.s.to_formatted_string(&Locale::en),
                             ))
                         } else {
                             f.text(format!(
                                 "SSH:{} | SS:{} | SH:{} | S:{}",
-                                &user
-                                    .count_rank_ssh
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_ss
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_sh
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
-                                &user
-                                    .count_rank_s
-                                    .as_ref()
-                                    .unwrap()
-                                    .parse::<u128>()
-                                    .expect("NaN")
-                                    .to_formatted_string(&Locale::en),
+                                grades.ssh.to_formatted_string(&Locale::en),
+                                grades.ss.to_formatted_string(&Locale::en),
+                                grades.sh.to_formatted_string(&Locale::en),
+                                grades.s.to_formatted_string(&Locale::en),
                             ))
                         }
                     })
@@ -997,33 +653,121 @@ async fn osu_profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult
     Ok(())
 }
 
-/// Obtains your score on the specified beatmap id
-/// The number that's sent along with the recent command is the beatmap id of it.
+// Draws the profile of the user as an image, with their top plays.
+async fn send_profile_card(
+    ctx: &Context,
+    msg: &Message,
+    api: &OsuApi,
+    user: &UserExtended,
+    mode: GameMode,
+    pp: bool,
+    theme: CardTheme,
+) -> CommandResult {
+    let top_plays = api
+        .user_scores(user.id, ScoreKind::Best, Some(mode), 5)
+        .await?
+        .into_iter()
+        .map(|i| {
+            (
+                i.pp.unwrap_or_default(),
+                format!(
+                    "{} - {} [{}] +{}",
+                    i.beatmapset.artist,
+                    i.beatmapset.title,
+                    i.beatmap.version,
+                    get_mods_short(&i.mods)
+                ),
+            )
+        })
+        .collect();
+
+    // The card is still drawn without the avatar if it can't be downloaded.
+    let avatar = match cached_avatar(ctx, &user.avatar_url).await {
+        Ok(x) => Some(x),
+        Err(why) => {
+            warn!("Error downloading the avatar of {}: {}", user.username, why);
+            None
+        }
+    };
+
+    let stats = &user.statistics;
+    let grades = &stats.grade_counts;
+
+    let card = ProfileCard {
+        username: user.username.to_string(),
+        mode,
+        country_code: user.country_code.to_string(),
+        global_rank: stats.global_rank.filter(|_| pp),
+        country_rank: stats.country_rank.filter(|_| pp),
+        pp: Some(stats.pp).filter(|_| pp),
+        accuracy: stats.hit_accuracy,
+        play_count: stats.play_count,
+        grades: [grades.ssh, grades.ss, grades.sh, grades.s, grades.a],
+        level: stats.level.current,
+        level_progress: stats.level.progress,
+        top_plays,
+        avatar,
+        theme,
+    };
+
+    let bytes = spawn_blocking(move || card.draw()).await??;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.add_file(AttachmentType::Bytes {
+                data: Cow::from(bytes),
+                filename: "profile.png".to_string(),
+            });
+            m.embed(|e| {
+                e.author(|a| {
+                    a.name(format!("{} ({})", user.username, mode.display_name()));
+                    a.url(format!("https://osu.ppy.sh/u/{}", user.id))
+                });
+                e.image("attachment://profile.png")
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Obtains your score on the specified beatmap id or link.
+/// Without a beatmap, the last beatmap sent on the channel is used, like the one of `recent`.
 ///
 /// You can use `osuc` to configure your osu! profile.
 ///
 /// Affected parameters for configuration:
-/// - Mode: To specify the gamemode the score was on.
+/// - Mode: To specify the gamemode the score was on, unless specified with `-m taiko|fruits|mania`.
 /// - PP: To know if the bot should display the PP stadistics.
 ///
-/// Usage: `score 124217`
+/// Usage:
+/// `compare`
+/// `score 124217`
+/// `score 124217 -m taiko`
 #[command]
 #[aliases("compare", "scr")]
 async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let bmap_id = match args.parse::<u64>() {
-        Err(_) => {
-            msg.reply(ctx, "An invalid id was provided").await?;
+    let (mode_flag, rest) = take_mode_flag(args.message());
+
+    let bmap_id = if rest.is_empty() {
+        if let Some(x) = last_channel_beatmap(ctx, msg.channel_id).await {
+            x
+        } else {
+            msg.reply(
+                ctx,
+                "No beatmap was found on this channel, please specify one.",
+            )
+            .await?;
             return Ok(());
         }
-        Ok(x) => x,
+    } else if let Some(x) = parse_beatmap_id(&rest) {
+        x
+    } else {
+        msg.reply(ctx, "An invalid id was provided").await?;
+        return Ok(());
     };
 
-    // Obtains the osu! api key from the "global" data
-    let osu_key = {
-        let data = ctx.data.read().await; // set inmutable global data.
-        let tokens = data.get::<Tokens>().unwrap().clone(); // get the tokens from the global data.
-        tokens.old_osu.to_string()
-    };
+    let api = OsuApi::from_context(ctx).await;
 
     let pool = {
         let data_read = ctx.data.read().await;
@@ -1057,10 +801,21 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         username = msg.author.name.to_string();
     }
 
-    let score = get_osu_scores(osu_id, &username, bmap_id, mode, &osu_key).await?;
+    let mode = mode_flag.unwrap_or_else(|| GameMode::from_id(mode));
 
-    let s = if let Some(x) = score.get(0) {
-        x
+    if osu_id == 0 {
+        osu_id = get_osu_id(&api, &username).await?;
+    }
+
+    let score = if osu_id == 0 {
+        None
+    } else {
+        api.beatmap_user_score(bmap_id, osu_id as u64, Some(mode))
+            .await?
+    };
+
+    let s = if let Some(x) = score {
+        x.score
     } else {
         msg.channel_id
             .say(
@@ -1074,18 +829,35 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     };
 
-    let beatmap_raw = get_osu_beatmap(&bmap_id.to_string(), &osu_key).await?;
-    let beatmap = &beatmap_raw[0];
-
-    let accuracy = acc_math(
-        s.count300.parse()?,
-        s.count100.parse()?,
-        s.count50.parse()?,
-        s.countmiss.parse()?,
-    )
-    .await;
+    let beatmap = if let Some(x) = api.beatmap(bmap_id).await? {
+        x
+    } else {
+        msg.reply(ctx, "An invalid id was provided").await?;
+        return Ok(());
+    };
 
-    let mods: String = get_mods_short(s.enabled_mods.parse()?).await;
+    // The star rating and max combo of the beatmap on the mode of the score, with its mods.
+    let attributes = api
+        .beatmap_attributes(bmap_id, &s.mods, Some(mode))
+        .await?
+        .ok_or("The beatmap of the score no longer exists.")?;
+
+    let accuracy = mode_accuracy(mode, &s.statistics);
+    let mods = get_mods_short(&s.mods);
+
+    // Scores on unranked beatmaps don't give pp.
+    let score_pp = s.pp.unwrap_or_else(|| {
+        PpCalculation::from_score(
+            mode,
+            &beatmap.beatmap,
+            &attributes,
+            &s.mods,
+            &s.statistics,
+            s.max_combo,
+            s.score,
+        )
+        .calculate()
+    });
 
     let rating_url = format!("https://s.ppy.sh/images/{}.png", s.rank.to_uppercase());
 
@@ -1096,52 +868,53 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 
                 e.title(format!(
                     "{} - {} [**{}**]\nby {}",
-                    beatmap.artist, beatmap.title, beatmap.version, beatmap.creator
+                    beatmap.beatmapset.artist,
+                    beatmap.beatmapset.title,
+                    beatmap.beatmap.version,
+                    beatmap.beatmapset.creator
                 ));
-                e.url(format!("https://osu.ppy.sh/b/{}", beatmap.beatmap_id));
+                e.url(format!("https://osu.ppy.sh/b/{}", beatmap.beatmap.id));
 
                 e.description(format!(
-                    "**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {} - {} - {} - {}",
-                    s.score
-                        .parse::<u32>()
-                        .expect("NaN")
-                        .to_formatted_string(&Locale::en),
-                    s.maxcombo,
-                    beatmap.max_combo,
+                    "**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {}",
+                    s.score.to_formatted_string(&Locale::en),
+                    s.max_combo,
+                    attributes.max_combo,
                     accuracy,
-                    s.count300,
-                    s.count100,
-                    s.count50,
-                    s.countmiss
+                    mode_hits(mode, &s.statistics),
                 ));
-                e.timestamp(s.date.clone());
+                e.timestamp(&s.created_at);
                 e.thumbnail(format!(
                     "https://b.ppy.sh/thumb/{}l.jpg",
-                    beatmap.beatmapset_id
+                    beatmap.beatmapset.id
                 ));
 
                 e.author(|a| {
-                    a.name(username);
-                    if osu_id == 0 {
-                        a.icon_url(format!("https://a.ppy.sh/{}", osu_id + 1));
+                    if let Some(user) = &s.user {
+                        a.name(&user.username);
+                        a.icon_url(&user.avatar_url);
                     } else {
-                        a.icon_url(format!("https://a.ppy.sh/{}", osu_id));
-                        a.url(format!("https://osu.ppy.sh/u/{}", osu_id));
+                        a.name(&username);
                     }
-
-                    a
+                    a.url(format!("https://osu.ppy.sh/u/{}", osu_id))
                 });
 
                 e.footer(|f| {
                     if pp {
                         f.text(format!(
-                            "{}pp | {:.4}* | {}",
-                            s.pp.as_ref().unwrap_or(&"0".to_string()),
-                            beatmap.difficultyrating,
-                            mods
+                            "{:.2}pp | {:.4}* | {} | {}",
+                            score_pp,
+                            attributes.star_rating,
+                            mods,
+                            mode.display_name()
                         ));
                     } else {
-                        f.text(format!("{:.4}* | {}", beatmap.difficultyrating, mods));
+                        f.text(format!(
+                            "{:.4}* | {} | {}",
+                            attributes.star_rating,
+                            mods,
+                            mode.display_name()
+                        ));
                     }
                     f.icon_url(&rating_url)
                 });
@@ -1150,6 +923,379 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         })
         .await?;
 
+    set_channel_beatmap(ctx, msg.channel_id, bmap_id).await;
+
+    Ok(())
+}
+
+// A score of a guild member on the leaderboard of a beatmap.
+struct LeaderboardEntry {
+    discord_id: i64,
+    username: String,
+    show_pp: bool,
+    score: BeatmapScore,
+}
+
+impl LeaderboardEntry {
+    // The pp of the users that don't want to show them count as 0 when sorting.
+    fn pp(&self) -> f64 {
+        if self.show_pp {
+            self.score.pp.unwrap_or_default()
+        } else {
+            0.0
+        }
+    }
+}
+
+// Parses a mod filter like `+HDHR`, with `+NM` being no mods.
+fn parse_mod_filter(input: &str) -> Option<Vec<String>> {
+    let mods = input.strip_prefix('+')?.to_uppercase();
+
+    if mods == "NM" {
+        return Some(vec![]);
+    }
+
+    if mods.is_empty() || mods.len() % 2 != 0 || !mods.chars().all(|i| i.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let mut mods = mods
+        .as_bytes()
+        .chunks(2)
+        .map(|i| String::from_utf8_lossy(i).to_string())
+        .collect::<Vec<String>>();
+    mods.sort();
+
+    Some(mods)
+}
+
+fn leaderboard_embed(
+    beatmap: &BeatmapWithSet,
+    entries: &[LeaderboardEntry],
+    mode: GameMode,
+    sort_by_pp: bool,
+    index: usize,
+    max: usize,
+) -> CreateEmbed {
+    let mut e = CreateEmbed::default();
+
+    e.title(format!(
+        "{} - {} [**{}**]\nby {}",
+        beatmap.beatmapset.artist,
+        beatmap.beatmapset.title,
+        beatmap.beatmap.version,
+        beatmap.beatmapset.creator
+    ));
+    e.url(format!("https://osu.ppy.sh/b/{}", beatmap.beatmap.id));
+    e.thumbnail(format!(
+        "https://b.ppy.sh/thumb/{}l.jpg",
+        beatmap.beatmapset.id
+    ));
+
+    let page = entries
+        .iter()
+        .enumerate()
+        .skip(index * LEADERBOARD_PAGE_SIZE)
+        .take(LEADERBOARD_PAGE_SIZE)
+        .map(|(position, entry)| {
+            let s = &entry.score;
+            let pp = if entry.show_pp {
+                format!(" ┇ **{:.2}pp**", s.pp.unwrap_or_default())
+            } else {
+                String::new()
+            };
+
+            format!(
+                "**#{}** <@{}> `{}` ┇ {}\n**{}** ┇ x{} ┇ {:.2}% ┇ {}{}",
+                position + 1,
+                entry.discord_id,
+                entry.username,
+                s.rank.to_uppercase(),
+                s.score.to_formatted_string(&Locale::en),
+                s.max_combo,
+                mode_accuracy(mode, &s.statistics),
+                get_mods_short(&s.mods),
+                pp,
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n");
+
+    e.description(page);
+    e.footer(|f| {
+        f.text(format!(
+            "Page {} of {} | Sorted by {} | {}",
+            index + 1,
+            max + 1,
+            if sort_by_pp { "pp" } else { "score" },
+            mode.display_name()
+        ))
+    });
+
+    e
+}
+
+/// Shows the leaderboard of the guild members on a beatmap.
+/// Only the members that configured their osu! profile with `osuc` are on it,
+/// up to 25 of them on big guilds.
+///
+/// Without a beatmap, the last beatmap sent on the channel is used.
+///
+/// Parameters:
+/// `--pp` To sort the scores by pp instead of score.
+/// `+MODS` To only show the scores with these exact mods, like `+HDHR` or `+NM`.
+/// `-m taiko|fruits|mania` To show the scores of a converted beatmap.
+///
+/// Usage:
+/// `leaderboard`
+/// `leaderboard 1262832 --pp`
+/// `lb https://osu.ppy.sh/beatmapsets/599627#osu/1262832 +HDDT`
+#[command]
+#[aliases("lb", "guild_leaderboard", "glb", "server_leaderboard")]
+#[only_in("guilds")]
+async fn leaderboard(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (mode_flag, rest) = take_mode_flag(args.message());
+
+    let mut sort_by_pp = false;
+    let mut mod_filter = None;
+    let mut beatmap_id = None;
+
+    for word in rest.split_whitespace() {
+        if word == "--pp" {
+            sort_by_pp = true;
+        } else if word.starts_with('+') {
+            if let Some(x) = parse_mod_filter(word) {
+                mod_filter = Some(x);
+            } else {
+                msg.reply(ctx, "Invalid mods were provided, use them like `+HDHR`.")
+                    .await?;
+                return Ok(());
+            }
+        } else if let Some(x) = parse_beatmap_id(word) {
+            beatmap_id = Some(x);
+        } else {
+            msg.reply(ctx, "An invalid beatmap was provided.").await?;
+            return Ok(());
+        }
+    }
+
+    let beatmap_id = if let Some(x) = beatmap_id {
+        x
+    } else if let Some(x) = last_channel_beatmap(ctx, msg.channel_id).await {
+        x
+    } else {
+        msg.reply(
+            ctx,
+            "No beatmap was found on this channel, please specify one.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let api = OsuApi::from_context(ctx).await;
+
+    let beatmap = if let Some(x) = api.beatmap(beatmap_id).await? {
+        x
+    } else {
+        msg.reply(ctx, "An invalid beatmap was provided.").await?;
+        return Ok(());
+    };
+
+    let mode = mode_flag.unwrap_or_else(|| GameMode::from_id(beatmap.beatmap.mode_int as i32));
+
+    let member_ids = {
+        let guild = msg.guild(ctx).await.unwrap();
+        guild
+            .members
+            .keys()
+            .map(|i| i.0 as i64)
+            .collect::<Vec<i64>>()
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let mut users = sqlx::query!(
+        "SELECT discord_id, osu_id, osu_username, pp FROM osu_user WHERE discord_id = ANY($1) ORDER BY osu_id",
+        &member_ids
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    if users.is_empty() {
+        msg.reply(
+            ctx,
+            "Nobody on this guild has configured their osu! profile with `osuc`.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let linked_members = users.len();
+    users.truncate(MAX_LEADERBOARD_MEMBERS);
+
+    let _ = msg.channel_id.broadcast_typing(ctx).await;
+
+    let mut entries = Vec::new();
+
+    for user in users {
+        // A single failed request only leaves that member out of the leaderboard.
+        let scores = match api
+            .beatmap_user_scores(beatmap_id, user.osu_id as u64, Some(mode))
+            .await
+        {
+            Ok(x) => x,
+            Err(why) => {
+                warn!(
+                    "Error obtaining the scores of {} on {}: {}",
+                    user.osu_username, beatmap_id, why
+                );
+                continue;
+            }
+        };
+
+        let best = scores
+            .into_iter()
+            .filter(|s| {
+                mod_filter.as_ref().map_or(true, |filter| {
+                    let mut mods = s.mods.clone();
+                    mods.sort();
+                    &mods == filter
+                })
+            })
+            .max_by(|a, b| {
+                if sort_by_pp {
+                    a.pp.unwrap_or_default()
+                        .partial_cmp(&b.pp.unwrap_or_default())
+                        .unwrap_or(Ordering::Equal)
+                } else {
+                    a.score.cmp(&b.score)
+                }
+            });
+
+        if let Some(score) = best {
+            entries.push(LeaderboardEntry {
+                discord_id: user.discord_id,
+                username: user.osu_username,
+                show_pp: user.pp.unwrap_or(true),
+                score,
+            });
+        }
+    }
+
+    if entries.is_empty() {
+        msg.reply(ctx, "Nobody on this guild has a score on this beatmap.")
+            .await?;
+        return Ok(());
+    }
+
+    if sort_by_pp {
+        entries.sort_by(|a, b| b.pp().partial_cmp(&a.pp()).unwrap_or(Ordering::Equal));
+    } else {
+        entries.sort_by(|a, b| b.score.score.cmp(&a.score.score));
+    }
+
+    let uuid_prev = Uuid::new_v4().to_string();
+    let uuid_next = Uuid::new_v4().to_string();
+    let uuid_done = Uuid::new_v4().to_string();
+
+    let max = (entries.len() - 1) / LEADERBOARD_PAGE_SIZE;
+    let mut index = 0;
+
+    let mut message = msg
+        .channel_id
+        .send_message(ctx, |m| {
+            if linked_members > MAX_LEADERBOARD_MEMBERS {
+                m.content(format!(
+                    "Only {} of the {} linked members were checked.",
+                    MAX_LEADERBOARD_MEMBERS, linked_members
+                ));
+            }
+            m.set_embed(leaderboard_embed(
+                &beatmap, &entries, mode, sort_by_pp, index, max,
+            ));
+            m.reference_message(msg)
+        })
+        .await?;
+
+    set_channel_beatmap(ctx, msg.channel_id, beatmap_id).await;
+
+    if max == 0 {
+        return Ok(());
+    }
+
+    loop {
+        message
+            .edit(ctx, |m| {
+                m.set_embeds(vec![leaderboard_embed(
+                    &beatmap, &entries, mode, sort_by_pp, index, max,
+                )]);
+                m.components(|c| {
+                    c.create_action_row(|ar| {
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Previous");
+                            b.emoji(ReactionType::Unicode("⬅️".to_string()));
+                            b.disabled(index == 0);
+                            b.custom_id(&uuid_prev)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Secondary);
+                            b.label("Next");
+                            b.emoji(ReactionType::Unicode("➡️".to_string()));
+                            b.disabled(index == max);
+                            b.custom_id(&uuid_next)
+                        });
+                        ar.create_button(|b| {
+                            b.style(ButtonStyle::Success);
+                            b.label("Done!");
+                            b.emoji(ReactionType::Unicode("✅".to_string()));
+                            b.custom_id(&uuid_done)
+                        });
+                        ar
+                    })
+                })
+            })
+            .await?;
+
+        let mov_uuid_prev = uuid_prev.clone();
+        let mov_uuid_next = uuid_next.clone();
+        let mov_uuid_done = uuid_done.clone();
+
+        let mci = message
+            .await_component_interaction(ctx)
+            .author_id(msg.author.id.0)
+            .timeout(Duration::from_secs(120))
+            .filter(move |mci| {
+                matches!(mci.data.component_type, ComponentType::Button)
+                    && (mci.data.custom_id == mov_uuid_prev
+                        || mci.data.custom_id == mov_uuid_next
+                        || mci.data.custom_id == mov_uuid_done)
+            })
+            .await;
+
+        if let Some(mci) = mci {
+            mci.create_interaction_response(ctx, |ir| {
+                ir.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await?;
+
+            if mci.data.custom_id == uuid_prev {
+                index = index.saturating_sub(1);
+            } else if mci.data.custom_id == uuid_next {
+                index = (index + 1).min(max);
+            } else {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    message.edit(ctx, |m| m.components(|c| c)).await?;
+
     Ok(())
 }
 
@@ -1159,7 +1305,7 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 ///
 /// To use this command, first configure your osu! profile with `osuc`
 /// Affected parameters for configuration:
-/// - Mode: To specify the gamemode the play was on.
+/// - Mode: To specify the gamemode the play was on, unless specified with `-m taiko|fruits|mania`.
 /// - PP: To know if the bot should display the PP stadistics of the play.
 /// - Short Recent: To display the short version of recent instead of the long one.
 /// (Currently only short exists.)
@@ -1168,25 +1314,13 @@ async fn score(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 /// Usage:
 /// `recent`
 /// `recent [ Frost ]`
-/// `recent vicky5124`
+/// `recent vicky5124 -m fruits`
 #[command]
 #[aliases("rs", "rc")]
 async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult {
-    let mut arg_user = String::from("");
-    if !arguments.is_empty() {
-        let args = arguments.raw_quoted().collect::<Vec<&str>>();
-        for i in args {
-            arg_user += &format!("{} ", i).to_owned()[..];
-        }
-        arg_user.pop();
-    }
+    let (mode_flag, mut arg_user) = take_mode_flag(arguments.message());
 
-    // Obtains the osu! api key from the "global" data
-    let osu_key = {
-        let data = ctx.data.read().await; // set inmutable global data.
-        let tokens = data.get::<Tokens>().unwrap().clone(); // get the tokens from the global data.
-        tokens.old_osu.to_string()
-    };
+    let api = OsuApi::from_context(ctx).await;
 
     let pool = {
         let data_read = ctx.data.read().await;
@@ -1227,10 +1361,15 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
         user_data.short_recent = Some(true);
     }
 
+    if let Some(x) = mode_flag {
+        user_data.mode = Some(x.id());
+    }
+
+    let mode = GameMode::from_id(user_data.mode.unwrap_or(0));
     let user_data_name = user_data.name.replace("`", "").replace("@", "@\u{200b}");
 
     if user_data.osu_id == 0 {
-        let user_id = get_osu_id(&user_data.name, &osu_key).await?;
+        let user_id = get_osu_id(&api, &user_data.name).await?;
         if user_id == 0 {
             msg.channel_id
                 .say(
@@ -1252,7 +1391,9 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
         .say(ctx, format!("Obtaining **{}** recent data", user_data_name))
         .await?;
 
-    let user_recent_raw = get_osu_user_recent(user_data.osu_id, &osu_key).await?;
+    let user_recent_raw = api
+        .user_scores(user_data.osu_id as u64, ScoreKind::Recent, Some(mode), 50)
+        .await?;
 
     if user_recent_raw.is_empty() {
         bot_msg
@@ -1268,17 +1409,24 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
         return Ok(());
     }
 
+    let recent_count = user_recent_raw.len();
+    let beatmap_ids = user_recent_raw
+        .iter()
+        .map(|i| i.beatmap.id)
+        .collect::<Vec<u64>>();
+
     // Group all the needed data to EventData
     let event_data = EventData {
         user_db_data: Some(user_data),
-        user_recent_raw: Some(user_recent_raw.clone()),
-        osu_key: Some(osu_key),
+        user_recent_raw: Some(user_recent_raw),
+        api: Some(api),
     };
 
     let mut page = 0;
 
     // Build the initial recent embed
     short_recent_builder(ctx.http.clone(), &event_data, bot_msg.clone(), page).await?;
+    set_channel_beatmap(ctx, msg.channel_id, beatmap_ids[page]).await;
 
     // Add left and right reactions, to make the life easier for the user using the event.
 
@@ -1304,7 +1452,7 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
                     }
                 }
                 "⬅️" => {
-                    if page != user_recent_raw.len() - 1 {
+                    if page != recent_count - 1 {
                         page += 1;
                     }
                 }
@@ -1317,6 +1465,7 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
             {
                 break;
             }
+            set_channel_beatmap(ctx, msg.channel_id, beatmap_ids[page]).await;
             let _ = reaction.as_inner_ref().delete(ctx).await;
         } else {
             let _ = bot_msg.delete_reactions(ctx).await;
@@ -1328,23 +1477,22 @@ async fn recent(ctx: &Context, msg: &Message, arguments: Args) -> CommandResult
 }
 
 async fn top_play_embed_builder(
-    osu_key: &str,
+    api: &OsuApi,
     data: &OsuData,
-    play: &OsuUserBest,
-    user: &OsuUserData,
+    play: &Score,
     index: usize,
 ) -> Result<CreateEmbed, Box<dyn std::error::Error + Send + Sync>> {
-    let beatmap_raw = get_osu_beatmap(&play.beatmap_id, &osu_key).await?;
-    let beatmap = &beatmap_raw[0];
-
-    let mods: String = get_mods_short(play.enabled_mods.parse()?).await;
-    let accuracy = acc_math(
-        play.count300.parse()?,
-        play.count100.parse()?,
-        play.count50.parse()?,
-        play.countmiss.parse()?,
-    )
-    .await;
+    // The max combo and star rating on the mode of the play, with its mods.
+    let attributes = api
+        .beatmap_attributes(play.beatmap.id, &play.mods, Some(data.mode))
+        .await?
+        .ok_or("The beatmap of the play no longer exists.")?;
+
+    let beatmap = &play.beatmap;
+    let beatmapset = &play.beatmapset;
+
+    let mods = get_mods_short(&play.mods);
+    let accuracy = mode_accuracy(data.mode, &play.statistics);
     let rating_url = format!("https://s.ppy.sh/images/{}.png", play.rank.to_uppercase());
 
     let mut e = CreateEmbed::default();
@@ -1352,41 +1500,41 @@ async fn top_play_embed_builder(
     e.color(Colour::new(data.id as u32));
 
     e.author(|a| {
-        a.name(format!("Play #{} from \"{}\"", index, user.username));
-        a.icon_url(format!("https://a.ppy.sh/{}", user.user_id));
-        a.url(format!("https://osu.ppy.sh/u/{}", user.user_id))
+        a.name(format!("Play #{} from \"{}\"", index, play.user.username));
+        a.icon_url(&play.user.avatar_url);
+        a.url(format!("https://osu.ppy.sh/u/{}", play.user.id))
     });
 
     e.title(format!(
         "{} - {} [**{}**]\nby {}",
-        beatmap.artist, beatmap.title, beatmap.version, beatmap.creator
+        beatmapset.artist, beatmapset.title, beatmap.version, beatmapset.creator
     ));
-    e.url(format!("https://osu.ppy.sh/b/{}", beatmap.beatmap_id));
+    e.url(format!("https://osu.ppy.sh/b/{}", beatmap.id));
 
     e.description(format!(
-        "**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {} - {} - {} - {}",
-        play.score
-            .parse::<u32>()
-            .expect("NaN")
-            .to_formatted_string(&Locale::en),
-        play.maxcombo,
-        beatmap.max_combo,
+        "**{}** ┇ **x{} / {}**\n**{:.2}%** ┇ {}",
+        play.score.to_formatted_string(&Locale::en),
+        play.max_combo,
+        attributes.max_combo,
         accuracy,
-        play.count300,
-        play.count100,
-        play.count50,
-        play.countmiss
-    ));
-    e.timestamp(play.date.clone());
-    e.thumbnail(format!(
-        "https://b.ppy.sh/thumb/{}l.jpg",
-        beatmap.beatmapset_id
+        mode_hits(data.mode, &play.statistics),
     ));
+    e.timestamp(&play.created_at);
+    e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", beatmapset.id));
+
+    let weighted = play
+        .weight
+        .as_ref()
+        .map(|i| format!(" ({:.2}pp weighted)", i.pp))
+        .unwrap_or_default();
 
     e.footer(|f| {
         f.text(format!(
-            "{}pp | {:.4}* | {}",
-            &play.pp, beatmap.difficultyrating, mods
+            "{:.2}pp{} | {:.4}* | {}",
+            play.pp.unwrap_or_default(),
+            weighted,
+            attributes.star_rating,
+            mods
         ));
         f.icon_url(&rating_url)
     });
@@ -1395,10 +1543,10 @@ async fn top_play_embed_builder(
 }
 
 /// Command to show the top plays of a user.
-/// - Only osu!std supported.
 ///
 /// To use this command, you may want to configure your osu! profile with `osuc`
 /// Affected parameters for configuration:
+/// - Mode: The gamemode of the plays, unless specified with `-m taiko|fruits|mania`.
 /// - PP: If set to false, users will not be able to see your top plays.
 ///
 /// You can also invoke the command specifying a username.
@@ -1406,10 +1554,11 @@ async fn top_play_embed_builder(
 /// `osu_top`
 /// `osu!top [ Frost ]`
 /// `otop vicky5124`
+/// `otop vicky5124 -m mania`
 #[command]
 #[aliases("osutop", "otop", "top_plays", "topplays", "toplays", "top", "osu!top")]
 async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let mut args_user = args.message().to_string();
+    let (mode_flag, mut args_user) = take_mode_flag(args.message());
     if args_user.is_empty() {
         args_user = msg.author.name.to_string();
     }
@@ -1419,12 +1568,7 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         data_read.get::<DatabasePool>().unwrap().clone()
     };
 
-    let osu_key = {
-        let data_read = ctx.data.read().await;
-        let tokens = data_read.get::<Tokens>().unwrap().clone();
-
-        tokens.old_osu.to_string()
-    };
+    let api = OsuApi::from_context(ctx).await;
 
     let mut config = OsuData::default();
 
@@ -1436,6 +1580,7 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             config.username = info.osu_username;
             config.id = info.osu_id;
             config.pp = info.pp.unwrap_or(true);
+            config.mode = GameMode::from_id(info.mode.unwrap_or(0));
         } else {
             let data = sqlx::query!("SELECT * FROM osu_user WHERE osu_username = $1", &args_user)
                 .fetch_optional(&pool)
@@ -1444,14 +1589,15 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 config.username = info.osu_username;
                 config.id = info.osu_id;
                 config.pp = info.pp.unwrap_or(true);
+                config.mode = GameMode::from_id(info.mode.unwrap_or(0));
             } else {
                 config.id = id;
                 config.username = {
                     config.pp = true;
-                    let username = get_osu_username(&id, &osu_key).await?;
+                    let username = get_osu_username(&api, id).await?;
 
                     if username.is_empty() {
-                        config.id = get_osu_id(&args_user, &osu_key).await?;
+                        config.id = get_osu_id(&api, &args_user).await?;
                         args_user
                     } else {
                         username
@@ -1467,13 +1613,18 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             config.username = info.osu_username;
             config.id = info.osu_id;
             config.pp = info.pp.unwrap_or(true);
+            config.mode = GameMode::from_id(info.mode.unwrap_or(0));
         } else {
-            config.id = get_osu_id(&args_user, &osu_key).await?;
+            config.id = get_osu_id(&api, &args_user).await?;
             config.username = args_user;
             config.pp = true;
         }
     }
 
+    if let Some(x) = mode_flag {
+        config.mode = x;
+    }
+
     if !config.pp {
         msg.reply(
             ctx,
@@ -1486,14 +1637,17 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         return Ok(());
     }
 
-    let data = get_osu_user_best(&config.id, &osu_key).await?;
+    let data = api
+        .user_scores(config.id as u64, ScoreKind::Best, Some(config.mode), 100)
+        .await?;
 
     if data.is_empty() {
         msg.reply(
             ctx,
             format!(
-                "The user `{}` does not have any plays in osu!std.",
-                config.username.replace("@", "@\u{200b}")
+                "The user `{}` does not have any plays in {}.",
+                config.username.replace("@", "@\u{200b}"),
+                config.mode.display_name()
             ),
         )
         .await?;
@@ -1501,9 +1655,8 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     }
 
     let mut index = 0;
-    let user = &get_osu_user(&config.username, &osu_key).await?[0];
 
-    let embed = top_play_embed_builder(&osu_key, &config, &data[index], &user, index + 1).await?;
+    let embed = top_play_embed_builder(&api, &config, &data[index], index + 1).await?;
     let mut message = msg
         .channel_id
         .send_message(ctx, |m| {
@@ -1513,6 +1666,7 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             })
         })
         .await?;
+    set_channel_beatmap(ctx, msg.channel_id, data[index].beatmap.id).await;
 
     let left = ReactionType::Unicode(String::from("⬅️"));
     let right = ReactionType::Unicode(String::from("➡️"));
@@ -1543,8 +1697,7 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                 _ => (),
             }
 
-            let embed =
-                top_play_embed_builder(&osu_key, &config, &data[index], &user, index + 1).await?;
+            let embed = top_play_embed_builder(&api, &config, &data[index], index + 1).await?;
             message
                 .edit(ctx, |m| {
                     m.embed(|mut e| {
@@ -1553,6 +1706,7 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
                     })
                 })
                 .await?;
+            set_channel_beatmap(ctx, msg.channel_id, data[index].beatmap.id).await;
             let _ = reaction.as_inner_ref().delete(ctx).await;
         } else {
             let _ = message.delete_reactions(ctx).await;
@@ -1563,36 +1717,505 @@ async fn osu_top(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     Ok(())
 }
 
+/// Calculates the star rating and pp of a beatmap with the specified mods.
+///
+/// Along with SS, 99%, 98%, 97% and 95%, the pp of a specific score can be calculated with:
+/// - `-m` The mods, like `HDDT` or `HD DT`.
+/// - `-a` The accuracy of the score.
+/// - `-c` The max combo of the score, a full combo by default.
+/// - `-x` The misses of the score.
+///
+/// osu!std beatmaps can be converted with `-m taiko|fruits|mania`.
+/// osu!mania pp are calculated from the score instead, which can be specified with `-s`.
+///
+/// Without a beatmap, the last beatmap sent on the channel is used.
+///
+/// Usage:
+/// `mapp -m HDDT`
+/// `beatmap_pp 1262832 -m HDDT`
+/// `mapp https://osu.ppy.sh/beatmapsets/599627#osu/1262832 -m HR -a 97.5 -c 1200 -x 2`
+/// `mapp 1262832 -m mania -s 950000`
 #[command]
 #[aliases(mappp, mapp, map_pp, beatmappp, beatmapp)]
 async fn beatmap_pp(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    println!("{}", args.message());
-    let matches_result = App::new("Beatmap PP")
+    // `-m` is also used for the mods, so the mode is taken out first.
+    let (mode_flag, rest) = take_mode_flag(args.message());
+
+    let matches_result = App::new("beatmap_pp")
+        .arg(Arg::with_name("beatmap"))
         .arg(
             Arg::with_name("mods")
-                .long("mod")
+                .long("mods")
+                .alias("mod")
                 .short("m")
                 .multiple(true)
-                .takes_value(true)
-                .required(false),
+                .takes_value(true),
         )
-        .get_matches_from_safe(args.message().split(' '));
+        .arg(
+            Arg::with_name("accuracy")
+                .long("acc")
+                .short("a")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("combo")
+                .long("combo")
+                .short("c")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("misses")
+                .long("misses")
+                .short("x")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("score")
+                .long("score")
+                .short("s")
+                .takes_value(true),
+        )
+        .get_matches_from_safe(std::iter::once("beatmap_pp").chain(rest.split_whitespace()));
 
     let matches = match matches_result {
         Ok(x) => x,
         Err(why) => {
-            println!("{}", why);
+            msg.reply(ctx, format!("```{}```", why.message)).await?;
             return Ok(());
         }
     };
 
-    let mods = if let Some(mods) = matches.values_of("mods") {
-        mods.collect::<Vec<&str>>()
+    let beatmap_id = if let Some(beatmap) = matches.value_of("beatmap") {
+        if let Some(x) = parse_beatmap_id(beatmap) {
+            x
+        } else {
+            msg.reply(ctx, "An invalid beatmap id or link was provided.")
+                .await?;
+            return Ok(());
+        }
+    } else if let Some(x) = last_channel_beatmap(ctx, msg.channel_id).await {
+        x
     } else {
-        vec!["NM"]
+        msg.reply(
+            ctx,
+            "No beatmap was found on this channel, please specify one.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let mod_names = match parse_mods(
+        &matches
+            .values_of("mods")
+            .map(|i| i.collect::<Vec<&str>>())
+            .unwrap_or_default(),
+    ) {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, format!("`{}` is not a valid mod.", why))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let accuracy = matches
+        .value_of("accuracy")
+        .map(|i| i.trim_end_matches('%').parse::<f64>());
+    let combo = matches.value_of("combo").map(|i| i.parse::<u32>());
+    let misses = matches.value_of("misses").map(|i| i.parse::<u32>());
+    let score = matches.value_of("score").map(|i| i.parse::<u32>());
+
+    let (accuracy, combo, misses, score) = match (
+        accuracy.transpose(),
+        combo.transpose(),
+        misses.transpose(),
+        score.transpose(),
+    ) {
+        (Ok(a), Ok(c), Ok(x), Ok(s)) => (a, c, x, s),
+        _ => {
+            msg.reply(
+                ctx,
+                "The accuracy, combo, misses and score must be numbers.",
+            )
+            .await?;
+            return Ok(());
+        }
     };
 
-    msg.reply(ctx, format!("{:?}", mods)).await?;
+    let api = OsuApi::from_context(ctx).await;
+
+    let beatmap = if let Some(x) = api.beatmap(beatmap_id).await? {
+        x
+    } else {
+        msg.reply(ctx, format!("The beatmap `{}` does not exist.", beatmap_id))
+            .await?;
+        return Ok(());
+    };
+
+    // Only osu!std beatmaps can be converted to the other modes.
+    let beatmap_mode = GameMode::from_name(&beatmap.beatmap.mode).unwrap_or_default();
+    let mode = mode_flag.unwrap_or(beatmap_mode);
+
+    if beatmap_mode != GameMode::Osu && mode != beatmap_mode {
+        msg.reply(
+            ctx,
+            format!(
+                "{} beatmaps can't be converted to {}.",
+                beatmap_mode.display_name(),
+                mode.display_name()
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let speed = if mod_names.iter().any(|i| i == "DT" || i == "NC") {
+        1.5
+    } else if mod_names.iter().any(|i| i == "HT") {
+        0.75
+    } else {
+        1.0
+    };
+
+    let mods_text = get_mods_short(&mod_names);
+
+    let (difficulty_text, stats_text, max_combo, table) = if mode == GameMode::Osu {
+        let beatmap_file = api.beatmap_file(beatmap_id).await?;
+
+        let map = if let Ok(x) = Map::parse(beatmap_file.as_bytes()) {
+            x
+        } else {
+            msg.reply(ctx, "Unable to read the beatmap file.").await?;
+            return Ok(());
+        };
+
+        let mods = Mods::from_strs(&mod_names);
+        let difficulty = Difficulty::calc(&map, mods);
+        let map_statistics = MapStatistics::new(
+            beatmap.beatmap.ar,
+            beatmap.beatmap.accuracy,
+            beatmap.beatmap.cs,
+            beatmap.beatmap.drain,
+        )
+        .with_mods(mods);
+
+        let objects = (beatmap.beatmap.count_circles
+            + beatmap.beatmap.count_sliders
+            + beatmap.beatmap.count_spinners) as u32;
+
+        // Returns the real accuracy of the hit counts used, along with the pp.
+        let calculate = |accuracy: f64, combo: Option<u32>, misses: u32| {
+            let (n300, n100, n50) = accuracy_hits(objects, accuracy, misses);
+            let accuracy = Accuracy {
+                n300: n300 as i32,
+                n100: n100 as i32,
+                n50: n50 as i32,
+                misses: misses.min(objects) as i32,
+            };
+
+            let pp = PpV2::pp(
+                &map,
+                &map_statistics,
+                difficulty.aim,
+                difficulty.speed,
+                combo,
+                mods,
+                accuracy,
+                1,
+                None,
+            );
+
+            (accuracy.value() * 100.0, pp.total)
+        };
+
+        let mut table = [100.0, 99.0, 98.0, 97.0, 95.0]
+            .iter()
+            .map(|i| {
+                let (_, pp) = calculate(*i, None, 0);
+                format!("{:<16} {:>9.2}pp", format!("{}%", i), pp)
+            })
+            .collect::<Vec<String>>();
+
+        if accuracy.is_some() || combo.is_some() || misses.is_some() {
+            let (real_accuracy, pp) = calculate(
+                accuracy.unwrap_or(100.0),
+                combo.map(|i| i.min(map.max_combo)),
+                misses.unwrap_or(0),
+            );
+
+            let mut label = format!("{:.2}%", real_accuracy);
+            if let Some(x) = combo {
+                label.push_str(&format!(" x{}", x.min(map.max_combo)));
+            }
+            if let Some(x) = misses {
+                label.push_str(&format!(" {}m", x));
+            }
+
+            table.push(format!("{:<16} {:>9.2}pp", label, pp));
+        }
+
+        (
+            format!(
+                "**{:.2}\\*** ({:.2}\\* Aim | {:.2}\\* Speed)",
+                difficulty.total, difficulty.aim, difficulty.speed,
+            ),
+            format!(
+                "AR {:.1} | OD {:.1} | CS {:.1} | HP {:.1}",
+                difficulty.stats.ar, difficulty.stats.od, difficulty.stats.cs, difficulty.stats.hp,
+            ),
+            map.max_combo as u64,
+            table,
+        )
+    } else {
+        let attributes = if let Some(x) = api
+            .beatmap_attributes(beatmap_id, &mod_names, Some(mode))
+            .await?
+        {
+            x
+        } else {
+            msg.reply(ctx, format!("The beatmap `{}` does not exist.", beatmap_id))
+                .await?;
+            return Ok(());
+        };
+
+        // Every object that gives combo on osu!taiko and osu!catch is a hit,
+        // while on osu!mania the hold notes give combo along the hold.
+        let objects = if mode == GameMode::Mania {
+            beatmap.beatmap.count_circles + beatmap.beatmap.count_sliders
+        } else {
+            attributes.max_combo
+        };
+
+        // The hits of a score with the provided accuracy and misses.
+        let hits =
+            |accuracy: f64, misses: u64| accuracy_statistics(mode, objects, accuracy, misses);
+
+        let calculate = |stats: &Statistics, combo: u64, score: u128| {
+            let mut pp = PpCalculation::from_score(
+                mode,
+                &beatmap.beatmap,
+                &attributes,
+                &mod_names,
+                stats,
+                combo,
+                score,
+            );
+            // The scores on the table are without the multiplier of the mods.
+            pp.map_score_multiplier = 1.0;
+            pp.calculate()
+        };
+
+        let mut table = vec![];
+
+        // osu!mania pp are given by the score, not the accuracy.
+        if mode == GameMode::Mania {
+            let stats = hits(100.0, 0);
+
+            for i in &[1_000_000, 990_000, 980_000, 970_000, 950_000] {
+                let pp = calculate(&stats, objects, *i);
+                table.push(format!(
+                    "{:<16} {:>9.2}pp",
+                    i.to_formatted_string(&Locale::en),
+                    pp
+                ));
+            }
+
+            if let Some(x) = score {
+                let pp = calculate(&stats, objects, x as u128);
+                table.push(format!(
+                    "{:<16} {:>9.2}pp",
+                    x.to_formatted_string(&Locale::en),
+                    pp
+                ));
+            }
+        } else {
+            for i in &[100.0, 99.0, 98.0, 97.0, 95.0] {
+                let pp = calculate(&hits(*i, 0), attributes.max_combo, 0);
+                table.push(format!("{:<16} {:>9.2}pp", format!("{}%", i), pp));
+            }
+
+            if accuracy.is_some() || combo.is_some() || misses.is_some() {
+                let stats = hits(accuracy.unwrap_or(100.0), misses.unwrap_or(0) as u64);
+                let combo = combo
+                    .map(|i| (i as u64).min(attributes.max_combo))
+                    .unwrap_or(attributes.max_combo - stats.count_miss);
+                let pp = calculate(&stats, combo, 0);
+
+                let mut label = format!("{:.2}%", mode_accuracy(mode, &stats));
+                label.push_str(&format!(" x{}", combo));
+                if let Some(x) = misses {
+                    label.push_str(&format!(" {}m", x));
+                }
+
+                table.push(format!("{:<16} {:>9.2}pp", label, pp));
+            }
+        }
+
+        // Hard Rock and Easy change the stats like on osu!std,
+        // the speed of DT and HT is already on the attributes that have it.
+        let hard_rock = mod_names.iter().any(|i| i == "HR");
+        let easy = mod_names.iter().any(|i| i == "EZ");
+        let with_mods = |value: f64, hard_rock_multiplier: f64| {
+            if hard_rock {
+                (value * hard_rock_multiplier).min(10.0)
+            } else if easy {
+                value * 0.5
+            } else {
+                value
+            }
+        };
+
+        let hp = with_mods(beatmap.beatmap.drain, 1.4);
+
+        let stats_text = match mode {
+            GameMode::Fruits => format!(
+                "AR {:.1} | CS {:.1} | HP {:.1}",
+                attributes.approach_rate.unwrap_or(beatmap.beatmap.ar),
+                with_mods(beatmap.beatmap.cs, 1.3),
+                hp,
+            ),
+            GameMode::Mania => format!(
+                "{}K | OD {:.1} | HP {:.1}",
+                beatmap.beatmap.cs,
+                with_mods(beatmap.beatmap.accuracy, 1.4),
+                hp,
+            ),
+            _ => {
+                // The 300 hit window of osu!taiko goes from 50ms at OD 0 to 20ms at OD 10.
+                let od = attributes
+                    .great_hit_window
+                    .map(|i| (50.0 - i) / 3.0)
+                    .unwrap_or_else(|| with_mods(beatmap.beatmap.accuracy, 1.4));
+
+                format!("OD {:.1} | HP {:.1}", od, hp)
+            }
+        };
+
+        (
+            format!(
+                "**{:.2}\\*** {}",
+                attributes.star_rating,
+                mode.display_name()
+            ),
+            stats_text,
+            attributes.max_combo,
+            table,
+        )
+    };
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!(
+                    "{} - {} [{}]",
+                    beatmap.beatmapset.artist, beatmap.beatmapset.title, beatmap.beatmap.version
+                ));
+                e.url(&beatmap.beatmap.url);
+                e.thumbnail(&beatmap.beatmapset.covers.list_2x);
+                e.description(format!(
+                    "__Mapped by **[{}](https://osu.ppy.sh/users/{})**__ | {}\n{} **+{}**",
+                    beatmap.beatmapset.creator,
+                    beatmap.beatmapset.user_id,
+                    capitalize_first(&beatmap.beatmap.status),
+                    difficulty_text,
+                    mods_text,
+                ));
+                e.field("Stats", stats_text, false);
+                e.field("BPM", format!("{:.0}", beatmap.beatmap.bpm * speed), true);
+                e.field(
+                    "Length",
+                    format_duration((beatmap.beatmap.total_length as f64 / speed * 1000.0) as u64),
+                    true,
+                );
+                e.field("Max combo", format!("x{}", max_combo), true);
+                e.field("PP", format!("```\n{}\n```", table.join("\n")), false);
+
+                e
+            })
+        })
+        .await?;
+
+    set_channel_beatmap(ctx, msg.channel_id, beatmap_id).await;
 
     Ok(())
 }
+
+/// Reads an osu! replay file, and shows its pp, unstable rate and the histogram of its hit errors.
+///
+/// The replay can be attached, linked from Discord, or be on the message replied to.
+/// Replays attached on channels with osu! previews enabled are read without using the command.
+///
+/// Usage:
+/// `replay` with a `.osr` file attached
+/// `replay https://cdn.discordapp.com/attachments/.../replay.osr`
+#[command]
+#[aliases(osr, replay_info)]
+async fn replay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let attachment = msg
+        .attachments
+        .iter()
+        .chain(
+            msg.referenced_message
+                .iter()
+                .flat_map(|i| i.attachments.iter()),
+        )
+        .find(|i| is_osu_replay(i));
+
+    let data = if let Some(attachment) = attachment {
+        if attachment.size > MAX_REPLAY_SIZE {
+            msg.reply(ctx, "The replay file is too big.").await?;
+            return Ok(());
+        }
+
+        attachment.download().await?
+    } else if let Ok(url) = args.single::<String>() {
+        // Only the files uploaded to Discord are downloaded, so other hosts can't be reached.
+        let url = match Url::parse(url.trim_matches(|c| c == '<' || c == '>')) {
+            Ok(x) if x.scheme() == "https" && is_discord_cdn(x.host_str()) => x,
+            _ => {
+                msg.reply(ctx, "Only the replays uploaded to Discord can be linked.")
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        let mut response = client.get(url).send().await?.error_for_status()?;
+
+        // Chunked responses don't have a length, so the size is checked while downloading.
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (data.len() + chunk.len()) as u64 > MAX_REPLAY_SIZE {
+                msg.reply(ctx, "The replay file is too big.").await?;
+                return Ok(());
+            }
+
+            data.extend_from_slice(&chunk);
+        }
+
+        data
+    } else {
+        msg.reply(ctx, "Please, attach or link an osu! replay file.")
+            .await?;
+        return Ok(());
+    };
+
+    let api = OsuApi::from_context(ctx).await;
+
+    if let Err(why) = send_replay_summary(ctx, msg, &api, data).await {
+        msg.reply(ctx, format!("Unable to read the replay: {}", why))
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Checks if the host of an url is where the Discord attachments are stored.
+fn is_discord_cdn(host: Option<&str>) -> bool {
+    matches!(
+        host,
+        Some("cdn.discordapp.com") | Some("media.discordapp.net")
+    )
+}
@@ -0,0 +1,383 @@
+//! The commands to track the new top plays of osu! players on a guild, and their rank over time.
+//! The plays are checked and announced by `check_osu_top_plays` on the notification loop,
+//! and the ranks of the linked players are saved daily by `snapshot_osu_users`.
+
+use crate::global_data::DatabasePool;
+use crate::utils::osu::take_mode_flag;
+use crate::utils::osu_api::{OsuApi, ScoreKind};
+use crate::utils::osu_images::{CardTheme, HistoryChart};
+use crate::utils::osu_model::GameMode;
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use chrono::{Duration, NaiveDate, Utc};
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    http::AttachmentType,
+    model::{channel::Message, id::ChannelId},
+    prelude::Context,
+};
+use tokio::task::spawn_blocking;
+
+// Every tracked player costs an API request on each check, so the amount per guild is limited.
+const MAX_GUILD_TRACKED_PLAYERS: i64 = 25;
+// How many days of the saved snapshots are drawn on the history graph.
+const HISTORY_DAYS: i32 = 365;
+
+/// Announces the new top plays of an osu! player on a channel.
+///
+/// The plays are announced on the current channel, unless another channel is mentioned.
+/// The mode defaults to the main mode of the player, use `-m taiko|fruits|mania` for another.
+///
+/// Usage:
+/// `osu track vicky5124`
+/// `osu track [ Frost ] #osu-plays`
+/// `osu track Majorowsky -m mania`
+#[command("track")]
+#[required_permissions(MANAGE_CHANNELS)]
+#[only_in("guilds")]
+#[min_args(1)]
+async fn osu_track(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (mode_flag, rest) = take_mode_flag(args.message());
+
+    let mut words = rest.split_whitespace().collect::<Vec<&str>>();
+    let channel_id = match words.last() {
+        Some(word) if word.starts_with("<#") => {
+            if let Ok(x) = word.parse::<ChannelId>() {
+                words.pop();
+                x
+            } else {
+                msg.reply(ctx, "An invalid channel was provided.").await?;
+                return Ok(());
+            }
+        }
+        _ => msg.channel_id,
+    };
+
+    let username = words.join(" ").replace("\"", "");
+    if username.is_empty() {
+        msg.reply(ctx, "Please, specify the osu! player to track.")
+            .await?;
+        return Ok(());
+    }
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+    let api = OsuApi::from_context(ctx).await;
+
+    let guild_id = msg.guild_id.unwrap().0 as i64;
+
+    let tracked = sqlx::query!(
+        "SELECT count(*) FROM osu_tracked_channels WHERE guild_id = $1",
+        guild_id
+    )
+    .fetch_one(&pool)
+    .await?
+    .count
+    .unwrap_or_default();
+
+    if tracked >= MAX_GUILD_TRACKED_PLAYERS {
+        msg.reply(
+            ctx,
+            format!(
+                "This guild already tracks {} players, untrack someone first.",
+                MAX_GUILD_TRACKED_PLAYERS
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let user = if let Some(x) = api.user_by_name(&username, mode_flag).await? {
+        x
+    } else {
+        msg.reply(ctx, "The provided user was not found.").await?;
+        return Ok(());
+    };
+
+    let mode = mode_flag.unwrap_or(user.playmode);
+    let osu_id = user.id as i64;
+
+    // The current top plays are marked as seen, so only the plays made from now on get announced.
+    let top_plays = api
+        .user_scores(user.id, ScoreKind::Best, Some(mode), 100)
+        .await?
+        .into_iter()
+        .map(|i| i.id as i64)
+        .collect::<Vec<i64>>();
+
+    let mut transaction = pool.begin().await?;
+
+    let new_player = sqlx::query!(
+        "INSERT INTO osu_tracked_players (osu_id, mode, osu_username, global_rank, pp) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING RETURNING osu_id",
+        osu_id,
+        mode.id(),
+        &user.username,
+        user.statistics.global_rank.map(|i| i as i64),
+        user.statistics.pp,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .is_some();
+
+    if new_player {
+        sqlx::query!(
+            "INSERT INTO osu_tracked_scores (osu_id, mode, score_id) SELECT $1, $2, * FROM UNNEST($3::bigint[]) ON CONFLICT DO NOTHING",
+            osu_id,
+            mode.id(),
+            &top_plays,
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+
+    sqlx::query!(
+        "INSERT INTO osu_tracked_channels (guild_id, channel_id, osu_id, mode) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id, osu_id, mode) DO UPDATE SET channel_id = $2",
+        guild_id,
+        channel_id.0 as i64,
+        osu_id,
+        mode.id(),
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "The new {} top plays of `{}` will be announced on <#{}>",
+            mode.display_name(),
+            user.username,
+            channel_id
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Stops announcing the top plays of an osu! player.
+///
+/// Without `-m`, the player is untracked on every mode.
+///
+/// Usage:
+/// `osu untrack vicky5124`
+/// `osu untrack Majorowsky -m mania`
+#[command("untrack")]
+#[required_permissions(MANAGE_CHANNELS)]
+#[only_in("guilds")]
+#[min_args(1)]
+async fn osu_untrack(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (mode_flag, username) = take_mode_flag(args.message());
+    let username = username.replace("\"", "");
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let removed = sqlx::query!(
+        "DELETE FROM osu_tracked_channels c USING osu_tracked_players p WHERE c.osu_id = p.osu_id AND c.mode = p.mode AND c.guild_id = $1 AND lower(p.osu_username) = lower($2) AND ($3::int IS NULL OR c.mode = $3) RETURNING c.osu_id",
+        msg.guild_id.unwrap().0 as i64,
+        &username,
+        mode_flag.map(GameMode::id),
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    if removed.is_empty() {
+        msg.reply(ctx, "That player is not tracked on this guild.")
+            .await?;
+        return Ok(());
+    }
+
+    // Players no guild tracks anymore stop being checked, along with their seen plays.
+    sqlx::query!("DELETE FROM osu_tracked_players p WHERE NOT EXISTS (SELECT 1 FROM osu_tracked_channels c WHERE c.osu_id = p.osu_id AND c.mode = p.mode)")
+        .execute(&pool)
+        .await?;
+
+    msg.react(ctx, '👍').await?;
+
+    Ok(())
+}
+
+/// Lists the osu! players tracked on this guild.
+#[command("tracked")]
+#[aliases("tracking", "tracklist")]
+#[only_in("guilds")]
+async fn osu_tracked(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let players = sqlx::query!(
+        "SELECT p.osu_username, p.mode, c.channel_id FROM osu_tracked_channels c JOIN osu_tracked_players p ON p.osu_id = c.osu_id AND p.mode = c.mode WHERE c.guild_id = $1 ORDER BY lower(p.osu_username)",
+        msg.guild_id.unwrap().0 as i64
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    if players.is_empty() {
+        msg.reply(ctx, "No osu! players are tracked on this guild.")
+            .await?;
+        return Ok(());
+    }
+
+    let list = players
+        .iter()
+        .map(|i| {
+            format!(
+                "`{}` ({}) on <#{}>",
+                i.osu_username,
+                GameMode::from_id(i.mode).display_name(),
+                i.channel_id
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Tracked osu! players");
+                e.description(list);
+                e.footer(|f| {
+                    f.text(format!(
+                        "{} / {} players",
+                        players.len(),
+                        MAX_GUILD_TRACKED_PLAYERS
+                    ))
+                })
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Draws a graph of the global rank and pp of an osu! player over time.
+///
+/// The last 90 days of ranks come from osu!, the older ones and the pp come from the daily
+/// snapshots of the players linked with `osuc`.
+/// Without a player, your linked account is used.
+///
+/// Usage:
+/// `osu history`
+/// `osu history vicky5124`
+/// `osu history Majorowsky -m mania`
+#[command("history")]
+#[aliases("rank_history", "graph")]
+async fn osu_history(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (mode_flag, username) = take_mode_flag(args.message());
+    let username = username.replace("\"", "");
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+    let api = OsuApi::from_context(ctx).await;
+
+    let linked = if username.is_empty() {
+        sqlx::query!(
+            "SELECT osu_id, mode FROM osu_user WHERE discord_id = $1",
+            msg.author.id.0 as i64
+        )
+        .fetch_optional(&pool)
+        .await?
+    } else {
+        None
+    };
+
+    if username.is_empty() && linked.is_none() {
+        msg.reply(
+            ctx,
+            "Please, specify the osu! player, or configure your profile with `osuc`.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let requested_mode =
+        mode_flag.or_else(|| linked.as_ref().and_then(|i| i.mode).map(GameMode::from_id));
+
+    let user = match &linked {
+        Some(row) => api.user_by_id(row.osu_id as u64, requested_mode).await?,
+        None => api.user_by_name(&username, requested_mode).await?,
+    };
+
+    let user = if let Some(x) = user {
+        x
+    } else {
+        msg.reply(ctx, "The provided user was not found.").await?;
+        return Ok(());
+    };
+
+    let mode = requested_mode.unwrap_or(user.playmode);
+    let today = Utc::now().naive_utc().date();
+
+    let snapshots = sqlx::query!(
+        "SELECT day, global_rank, pp FROM osu_user_snapshots WHERE osu_id = $1 AND mode = $2 AND day > CURRENT_DATE - $3::int ORDER BY day",
+        user.id as i64,
+        mode.id(),
+        HISTORY_DAYS,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut ranks = snapshots
+        .iter()
+        .filter_map(|i| Some((i.day, i.global_rank? as u64)))
+        .collect::<BTreeMap<NaiveDate, u64>>();
+    let mut pp = snapshots
+        .iter()
+        .filter_map(|i| Some((i.day, i.pp?)))
+        .collect::<BTreeMap<NaiveDate, f64>>();
+
+    // The ranks of osu! are used over the snapshots of the same days, the last one is today.
+    if let Some(history) = &user.rank_history {
+        let days = history.data.len() as i64;
+
+        for (index, rank) in history.data.iter().enumerate() {
+            // The days the player was inactive have no rank.
+            if *rank != 0 {
+                ranks.insert(today - Duration::days(days - 1 - index as i64), *rank);
+            }
+        }
+    }
+
+    if let Some(rank) = user.statistics.global_rank {
+        ranks.insert(today, rank);
+    }
+    pp.insert(today, user.statistics.pp);
+
+    let chart = HistoryChart {
+        title: format!("{} ({})", user.username, mode.display_name()),
+        ranks: ranks.into_iter().collect(),
+        pp: pp.into_iter().collect(),
+        theme: CardTheme::default(),
+    };
+
+    let bytes = spawn_blocking(move || chart.draw()).await??;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.add_file(AttachmentType::Bytes {
+                data: Cow::from(bytes),
+                filename: "history.png".to_string(),
+            });
+            m.embed(|e| {
+                e.author(|a| {
+                    a.name(format!("{} ({})", user.username, mode.display_name()));
+                    a.url(format!("https://osu.ppy.sh/u/{}", user.id))
+                });
+                e.image("attachment://history.png")
+            })
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,798 @@
+use crate::global_data::{DatabasePool, Lavalink, MusicStates};
+use crate::utils::music::{queueable_tracks, save_guild_queue};
+
+use std::borrow::Cow;
+
+use serde_json::json;
+use sqlx::PgPool;
+
+use lavalink_rs::model::Track;
+
+use serenity::{
+    framework::standard::{macros::command, Args, CommandResult},
+    http::AttachmentType,
+    model::channel::Message,
+    prelude::Context,
+};
+
+// Limits to keep a single user from storing too much.
+const MAX_PLAYLIST_TRACKS: usize = 500;
+const MAX_USER_PLAYLISTS: i64 = 25;
+
+/// Who a playlist belongs to, either a user or a guild.
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+    owner_id: i64,
+    guild_playlist: bool,
+}
+
+struct Playlist {
+    id: i32,
+    name: String,
+    tracks: Vec<Track>,
+}
+
+impl Playlist {
+    fn from_row(id: i32, name: String, tracks: Vec<String>) -> Self {
+        Self {
+            id,
+            name,
+            // The tracks are stored as the json of the lavalink track, so the info doesn't need to
+            // be decoded again when loaded.
+            tracks: tracks
+                .iter()
+                .filter_map(|i| serde_json::from_str(i).ok())
+                .collect(),
+        }
+    }
+}
+
+fn serialize_tracks(tracks: &[Track]) -> Vec<String> {
+    tracks
+        .iter()
+        .filter_map(|i| serde_json::to_string(i).ok())
+        .collect()
+}
+
+/// Obtains the scope of the command from the `-g`/`--guild` flag, and the rest of the arguments.
+///
+/// Modifying a guild playlist requires the `Manage Server` permission.
+async fn parse_scope(
+    ctx: &Context,
+    msg: &Message,
+    args: &Args,
+    modifying: bool,
+) -> Result<(Scope, bool, Vec<String>), String> {
+    let mut guild_flag = false;
+    let mut rest = Vec::new();
+
+    for arg in args.raw_quoted() {
+        if arg == "-g" || arg == "--guild" {
+            guild_flag = true;
+        } else {
+            rest.push(arg.to_string());
+        }
+    }
+
+    if guild_flag && modifying {
+        let can_manage = match msg.member(ctx).await {
+            Ok(member) => member
+                .permissions(ctx)
+                .await
+                .map(|i| i.manage_guild())
+                .unwrap_or(false),
+            Err(_) => false,
+        };
+
+        if !can_manage {
+            return Err(
+                "You need the `Manage Server` permission to modify the guild playlists."
+                    .to_string(),
+            );
+        }
+    }
+
+    let scope = if guild_flag {
+        Scope {
+            owner_id: msg.guild_id.unwrap().0 as i64,
+            guild_playlist: true,
+        }
+    } else {
+        Scope {
+            owner_id: msg.author.id.0 as i64,
+            guild_playlist: false,
+        }
+    };
+
+    Ok((scope, guild_flag, rest))
+}
+
+async fn get_playlist(
+    pool: &PgPool,
+    scope: Scope,
+    name: &str,
+) -> Result<Option<Playlist>, sqlx::Error> {
+    Ok(sqlx::query!(
+        "SELECT id, name, tracks FROM playlists WHERE owner_id = $1 AND guild_playlist = $2 AND lower(name) = lower($3)",
+        scope.owner_id,
+        scope.guild_playlist,
+        name
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| Playlist::from_row(row.id, row.name, row.tracks)))
+}
+
+/// Obtains a playlist to read from, falling back to the guild playlists if the user doesn't have
+/// one with that name.
+async fn find_playlist(
+    pool: &PgPool,
+    msg: &Message,
+    scope: Scope,
+    guild_flag: bool,
+    name: &str,
+) -> Result<Option<Playlist>, sqlx::Error> {
+    let playlist = get_playlist(pool, scope, name).await?;
+
+    if playlist.is_none() && !guild_flag {
+        let guild_scope = Scope {
+            owner_id: msg.guild_id.unwrap().0 as i64,
+            guild_playlist: true,
+        };
+
+        return get_playlist(pool, guild_scope, name).await;
+    }
+
+    Ok(playlist)
+}
+
+async fn save_playlist(
+    ctx: &Context,
+    msg: &Message,
+    pool: &PgPool,
+    scope: Scope,
+    name: &str,
+    tracks: &[Track],
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    // The `--guild` flag is not part of the name, so it can be left empty.
+    if name.trim().is_empty() {
+        msg.reply(ctx, "Please, specify the name of the playlist.")
+            .await?;
+        return Ok(false);
+    }
+
+    if tracks.len() > MAX_PLAYLIST_TRACKS {
+        msg.reply(
+            ctx,
+            format!(
+                "Playlists can't have more than {} tracks.",
+                MAX_PLAYLIST_TRACKS
+            ),
+        )
+        .await?;
+        return Ok(false);
+    }
+
+    if !scope.guild_playlist && get_playlist(pool, scope, name).await?.is_none() {
+        let count = sqlx::query!(
+            "SELECT COUNT(*) AS count FROM playlists WHERE owner_id = $1 AND guild_playlist = false",
+            scope.owner_id
+        )
+        .fetch_one(pool)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        if count >= MAX_USER_PLAYLISTS {
+            msg.reply(
+                ctx,
+                format!(
+                    "You can't have more than {} playlists, delete one first.",
+                    MAX_USER_PLAYLISTS
+                ),
+            )
+            .await?;
+            return Ok(false);
+        }
+    }
+
+    sqlx::query!(
+        "INSERT INTO playlists (owner_id, guild_playlist, name, tracks) VALUES ($1, $2, $3, $4) ON CONFLICT (owner_id, guild_playlist, lower(name)) DO UPDATE SET tracks = $4",
+        scope.owner_id,
+        scope.guild_playlist,
+        name,
+        &serialize_tracks(tracks)
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(true)
+}
+
+fn track_title(track: &Track) -> String {
+    track
+        .info
+        .as_ref()
+        .map(|i| i.title.replace("@", "@\u{200B}"))
+        .unwrap_or_else(|| "Unknown track".to_string())
+}
+
+/// Saves the current queue as a playlist, replacing it if it already exists.
+///
+/// Usage:
+/// `playlist save chill`
+/// `playlist save "guild mix" --guild`
+#[command("save")]
+#[min_args(1)]
+async fn playlist_save(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, _, rest) = match parse_scope(ctx, msg, &args, true).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let name = rest.join(" ");
+
+    let (lava_client, pool) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
+    };
+
+    let tracks = if let Some(node) = lava_client.nodes().await.get(&msg.guild_id.unwrap().0) {
+        node.queue
+            .iter()
+            .map(|i| i.track.clone())
+            .collect::<Vec<Track>>()
+    } else {
+        vec![]
+    };
+
+    if tracks.is_empty() {
+        msg.reply(ctx, "The queue is empty.").await?;
+        return Ok(());
+    }
+
+    if save_playlist(ctx, msg, &pool, scope, &name, &tracks).await? {
+        msg.reply(
+            ctx,
+            format!("Saved {} tracks to the playlist `{}`", tracks.len(), name),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Adds a track to a playlist, creating it if it doesn't exist.
+///
+/// Usage:
+/// `playlist add chill starmachine2000`
+/// `playlist add "guild mix" https://www.youtube.com/watch?v=dQw4w9WgXcQ --guild`
+#[command("add")]
+#[min_args(2)]
+async fn playlist_add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, _, rest) = match parse_scope(ctx, msg, &args, true).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    // `min_args` also counts the `--guild` flag.
+    if rest.len() < 2 {
+        msg.reply(ctx, "Usage: `playlist add <playlist> <track>`")
+            .await?;
+        return Ok(());
+    }
+
+    let name = &rest[0];
+    let query = rest[1..].join(" ");
+    let query = query.trim_start_matches('<').trim_end_matches('>');
+
+    let (lava_client, pool) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
+    };
+
+    let res = lava_client.auto_search_tracks(query).await?;
+
+    let track = if let Some(x) = res.tracks.into_iter().next() {
+        x
+    } else {
+        msg.reply(ctx, "Could not find any video of the search query.")
+            .await?;
+        return Ok(());
+    };
+
+    let mut tracks = get_playlist(&pool, scope, name)
+        .await?
+        .map(|i| i.tracks)
+        .unwrap_or_default();
+    tracks.push(track.clone());
+
+    if save_playlist(ctx, msg, &pool, scope, name, &tracks).await? {
+        msg.reply(
+            ctx,
+            format!("Added `{}` to the playlist `{}`", track_title(&track), name),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Removes a track from a playlist by it's position.
+///
+/// Usage:
+/// `playlist remove chill 3`
+/// `playlist remove "guild mix" 1 --guild`
+#[command("remove")]
+#[min_args(2)]
+async fn playlist_remove(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, _, rest) = match parse_scope(ctx, msg, &args, true).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    // `min_args` also counts the `--guild` flag.
+    if rest.len() < 2 {
+        msg.reply(ctx, "Usage: `playlist remove <playlist> <track number>`")
+            .await?;
+        return Ok(());
+    }
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let name = &rest[0];
+
+    let mut playlist = if let Some(x) = get_playlist(&pool, scope, name).await? {
+        x
+    } else {
+        msg.reply(ctx, "That playlist doesn't exist.").await?;
+        return Ok(());
+    };
+
+    let index = match rest[1].parse::<usize>() {
+        Ok(x) if x >= 1 && x <= playlist.tracks.len() => x - 1,
+        _ => {
+            msg.reply(ctx, "Please specify a valid track number.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let track = playlist.tracks.remove(index);
+
+    sqlx::query!(
+        "UPDATE playlists SET tracks = $2 WHERE id = $1",
+        playlist.id,
+        &serialize_tracks(&playlist.tracks)
+    )
+    .execute(&pool)
+    .await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Removed `{}` from the playlist `{}`",
+            track_title(&track),
+            playlist.name
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Lists your playlists and the playlists of the guild.
+///
+/// Usage: `playlist list`
+#[command("list")]
+async fn playlist_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let mut text = String::new();
+
+    for (title, owner_id, guild_playlist) in &[
+        ("Your playlists", msg.author.id.0 as i64, false),
+        ("Guild playlists", msg.guild_id.unwrap().0 as i64, true),
+    ] {
+        let playlists = sqlx::query!(
+            "SELECT name, cardinality(tracks) AS count FROM playlists WHERE owner_id = $1 AND guild_playlist = $2 ORDER BY name",
+            owner_id,
+            guild_playlist
+        )
+        .fetch_all(&pool)
+        .await?;
+
+        text.push_str(&format!("**{}**\n", title));
+
+        if playlists.is_empty() {
+            text.push_str("None\n");
+        }
+
+        for i in playlists {
+            text.push_str(&format!(
+                "`{}` - {} tracks\n",
+                i.name.replace("`", "'"),
+                i.count.unwrap_or(0)
+            ));
+        }
+
+        text.push('\n');
+    }
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Playlists");
+                e.description(text)
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Shows the tracks of a playlist.
+///
+/// Usage:
+/// `playlist show chill`
+/// `playlist show "guild mix" --guild`
+#[command("show")]
+#[min_args(1)]
+#[aliases(view, tracks)]
+async fn playlist_show(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, guild_flag, rest) = match parse_scope(ctx, msg, &args, false).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let name = rest.join(" ");
+
+    let playlist = if let Some(x) = find_playlist(&pool, msg, scope, guild_flag, &name).await? {
+        x
+    } else {
+        msg.reply(ctx, "That playlist doesn't exist.").await?;
+        return Ok(());
+    };
+
+    let mut text = String::from("```st\n");
+    for (index, track) in playlist.tracks.iter().take(25).enumerate() {
+        text += &format!("{}: {}\n", index + 1, track_title(track));
+    }
+
+    if playlist.tracks.len() > 25 {
+        text += &format!("... {}", playlist.tracks.len());
+    }
+
+    text += "\n```";
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(&playlist.name);
+                e.description(text);
+                e.footer(|f| f.text(format!("{} tracks", playlist.tracks.len())))
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a playlist.
+///
+/// Usage:
+/// `playlist delete chill`
+/// `playlist delete "guild mix" --guild`
+#[command("delete")]
+#[min_args(1)]
+#[aliases(del, rm)]
+async fn playlist_delete(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, _, rest) = match parse_scope(ctx, msg, &args, true).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let name = rest.join(" ");
+
+    if let Some(playlist) = get_playlist(&pool, scope, &name).await? {
+        sqlx::query!("DELETE FROM playlists WHERE id = $1", playlist.id)
+            .execute(&pool)
+            .await?;
+
+        msg.reply(ctx, format!("Deleted the playlist `{}`", playlist.name))
+            .await?;
+    } else {
+        msg.reply(ctx, "That playlist doesn't exist.").await?;
+    }
+
+    Ok(())
+}
+
+/// Adds all the tracks of a playlist to the queue.
+/// If you don't have a playlist with that name, the guild playlist is used.
+///
+/// Usage:
+/// `playlist load chill`
+/// `playlist load "guild mix" --guild`
+#[command("load")]
+#[min_args(1)]
+#[aliases(play, queue)]
+async fn playlist_load(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, guild_flag, rest) = match parse_scope(ctx, msg, &args, false).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = msg.guild_id.unwrap();
+
+    let manager = songbird::get(ctx).await.unwrap().clone();
+
+    if manager.get(guild_id).is_none() {
+        msg.channel_id.say(ctx, "Please, connect the bot to the voice channel you are currently on first with the `join` command.").await?;
+        return Ok(());
+    }
+
+    let (lava_client, pool, music_states) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+            data_read.get::<MusicStates>().unwrap().clone(),
+        )
+    };
+
+    let name = rest.join(" ");
+
+    let playlist = if let Some(x) = find_playlist(&pool, msg, scope, guild_flag, &name).await? {
+        x
+    } else {
+        msg.reply(ctx, "That playlist doesn't exist.").await?;
+        return Ok(());
+    };
+
+    let (tracks, limits) = queueable_tracks(ctx, msg, playlist.tracks).await;
+
+    if let Some(limits) = limits {
+        msg.reply(
+            ctx,
+            format!(
+                "Only {} tracks of the playlist can be queued, this server allows {}.",
+                tracks.len(),
+                limits
+            ),
+        )
+        .await?;
+    }
+
+    {
+        let mut music_states = music_states.write().await;
+        let state = music_states.entry(guild_id.0).or_default();
+        for track in &tracks {
+            state.set_request_channel(&track.track, msg.channel_id.0);
+        }
+    }
+
+    for track in &tracks {
+        lava_client
+            .play(guild_id, track.clone())
+            .requester(msg.author.id)
+            .queue()
+            .await?;
+    }
+
+    save_guild_queue(ctx, guild_id.0).await;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Added {} tracks from the playlist `{}` to the queue.",
+            tracks.len(),
+            playlist.name
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends a playlist as a JSON file of lavalink tracks, which can be imported with `playlist import`.
+///
+/// Usage:
+/// `playlist export chill`
+/// `playlist export "guild mix" --guild`
+#[command("export")]
+#[min_args(1)]
+async fn playlist_export(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, guild_flag, rest) = match parse_scope(ctx, msg, &args, false).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let name = rest.join(" ");
+
+    let playlist = if let Some(x) = find_playlist(&pool, msg, scope, guild_flag, &name).await? {
+        x
+    } else {
+        msg.reply(ctx, "That playlist doesn't exist.").await?;
+        return Ok(());
+    };
+
+    let data = serde_json::to_vec_pretty(&json!({
+        "name": playlist.name,
+        "tracks": playlist.tracks.iter().map(|i| &i.track).collect::<Vec<_>>(),
+    }))?;
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.add_file(AttachmentType::Bytes {
+                data: Cow::from(data),
+                filename: format!("{}.json", playlist.name.replace(&['/', '\\'][..], "_")),
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Creates a playlist from an attached JSON file made with `playlist export`,
+/// or a JSON list of encoded lavalink tracks.
+///
+/// Usage: `playlist import chill` with the file attached.
+#[command("import")]
+#[min_args(1)]
+async fn playlist_import(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (scope, _, rest) = match parse_scope(ctx, msg, &args, true).await {
+        Ok(x) => x,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+
+    let attachment = if let Some(x) = msg.attachments.first() {
+        x
+    } else {
+        msg.reply(ctx, "Please, attach the playlist file.").await?;
+        return Ok(());
+    };
+
+    let value = match serde_json::from_slice::<serde_json::Value>(&attachment.download().await?) {
+        Ok(x) => x,
+        Err(_) => {
+            msg.reply(ctx, "The attached file is not valid JSON.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let encoded_tracks = value
+        .get("tracks")
+        .unwrap_or(&value)
+        .as_array()
+        .map(|i| {
+            i.iter()
+                .filter_map(|i| i.as_str().map(|i| i.to_string()))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    if encoded_tracks.is_empty() {
+        msg.reply(ctx, "The attached file has no tracks.").await?;
+        return Ok(());
+    }
+
+    if encoded_tracks.len() > MAX_PLAYLIST_TRACKS {
+        msg.reply(
+            ctx,
+            format!(
+                "Playlists can't have more than {} tracks.",
+                MAX_PLAYLIST_TRACKS
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let (lava_client, pool) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<Lavalink>()
+                .unwrap()
+                .client(msg.guild_id.unwrap().0)
+                .await,
+            data_read.get::<DatabasePool>().unwrap().clone(),
+        )
+    };
+
+    let mut tracks = Vec::new();
+    let mut invalid = 0;
+
+    for encoded in encoded_tracks {
+        match lava_client.decode_track(&encoded).await {
+            Ok(info) => tracks.push(Track {
+                track: encoded,
+                info: Some(info),
+            }),
+            Err(_) => invalid += 1,
+        }
+    }
+
+    let name = rest.join(" ");
+
+    if save_playlist(ctx, msg, &pool, scope, &name, &tracks).await? {
+        let mut content = format!(
+            "Imported {} tracks to the playlist `{}`",
+            tracks.len(),
+            name
+        );
+
+        if invalid > 0 {
+            content.push_str(&format!("\n{} invalid tracks were skipped.", invalid));
+        }
+
+        msg.reply(ctx, content).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,326 @@
+use crate::global_data::{DatabasePool, Tokens};
+use crate::logging::attachments::remove_unreferenced_files;
+
+use std::{borrow::Cow, time::Duration};
+
+use futures::TryStreamExt;
+use serde_json::{json, Value};
+
+use serenity::{
+    framework::standard::{macros::command, CommandResult},
+    http::AttachmentType,
+    model::channel::Message,
+    prelude::Context,
+};
+
+// The logged messages are split on files smaller than this, as Discord rejects uploads over 8MB.
+const MAX_FILE_SIZE: usize = 7_500_000;
+
+/// Sends you a DM with a JSON archive of all the data the bot stores about you.
+///
+/// This includes the logged messages you have sent on guilds with logging, the list of their
+/// archived attachments, your reminders, your osu! account configuration, your best boy and
+/// best girl tags, your saved playlists, and your active mutes.
+#[command]
+#[aliases(download, data)]
+async fn export(ctx: &Context, msg: &Message) -> CommandResult {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let user_id = msg.author.id.0 as i64;
+
+    let reminders = sqlx::query!("SELECT * FROM reminders WHERE user_id = $1", user_id)
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            json!({
+                "id": row.id,
+                "date": row.date.to_rfc3339(),
+                "message_id": row.message_id,
+                "channel_id": row.channel_id,
+                "guild_id": row.guild_id,
+                "message": row.message,
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let mutes = sqlx::query!("SELECT * FROM muted_members WHERE user_id = $1", user_id)
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            json!({
+                "id": row.id,
+                "date": row.date.to_rfc3339(),
+                "message_id": row.message_id,
+                "channel_id": row.channel_id,
+                "guild_id": row.guild_id,
+                "message": row.message,
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let osu_user = sqlx::query!("SELECT * FROM osu_user WHERE discord_id = $1", user_id)
+        .fetch_optional(&pool)
+        .await?
+        .map(|row| {
+            json!({
+                "osu_id": row.osu_id,
+                "osu_username": row.osu_username,
+                "pp": row.pp,
+                "mode": row.mode,
+                "short_recent": row.short_recent,
+            })
+        });
+
+    let best_bg = sqlx::query!("SELECT * FROM best_bg WHERE user_id = $1", user_id)
+        .fetch_optional(&pool)
+        .await?
+        .map(|row| {
+            json!({
+                "best_boy": row.best_boy,
+                "best_girl": row.best_girl,
+                "booru": row.booru,
+            })
+        });
+
+    // The messages are streamed and written as they come, as there can be a lot of them.
+    let mut log_message_files = Vec::new();
+    let mut current_file = Vec::new();
+    let mut log_messages_count = 0_u64;
+
+    let mut rows = sqlx::query!("SELECT id, channel_id, guild_id, content, content_history, attachments, attachments_history, embeds, embeds_history, pinned, creation_timestamp, edited_timestamp FROM log_messages WHERE author_id = $1 ORDER BY id", user_id)
+        .fetch(&pool);
+
+    while let Some(row) = rows.try_next().await? {
+        let message = serde_json::to_vec_pretty(&json!({
+            "id": row.id,
+            "channel_id": row.channel_id,
+            "guild_id": row.guild_id,
+            "content": row.content,
+            "content_history": row.content_history,
+            "attachments": row.attachments,
+            "attachments_history": row.attachments_history,
+            "embeds": row.embeds,
+            "embeds_history": row.embeds_history,
+            "pinned": row.pinned,
+            "creation_timestamp": row.creation_timestamp.to_rfc3339(),
+            "edited_timestamp": row.edited_timestamp.map(|i| i.to_rfc3339()),
+        }))?;
+
+        // Each file is a JSON array, the extra bytes are for the brackets and the separator.
+        if !current_file.is_empty() && current_file.len() + message.len() + 4 > MAX_FILE_SIZE {
+            current_file.extend_from_slice(b"\n]");
+            log_message_files.push(std::mem::take(&mut current_file));
+        }
+
+        current_file.extend_from_slice(if current_file.is_empty() {
+            b"[\n"
+        } else {
+            b",\n"
+        });
+        current_file.extend_from_slice(&message);
+        log_messages_count += 1;
+    }
+
+    if !current_file.is_empty() {
+        current_file.extend_from_slice(b"\n]");
+        log_message_files.push(current_file);
+    }
+
+    let archived_attachments = sqlx::query!("SELECT message_id, guild_id, filename, hash, size, archived_at FROM archived_attachments WHERE author_id = $1 ORDER BY id", user_id)
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            json!({
+                "message_id": row.message_id,
+                "guild_id": row.guild_id,
+                "filename": row.filename,
+                "sha256": row.hash,
+                "size": row.size,
+                "archived_at": row.archived_at.to_rfc3339(),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let playlists = sqlx::query!(
+        "SELECT name, tracks, created_at FROM playlists WHERE owner_id = $1 AND guild_playlist = false ORDER BY id",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        json!({
+            "name": row.name,
+            "tracks": row.tracks.iter().filter_map(|i| serde_json::from_str::<Value>(i).ok()).collect::<Vec<Value>>(),
+            "created_at": row.created_at.to_rfc3339(),
+        })
+    })
+    .collect::<Vec<Value>>();
+
+    let mut files = vec![(
+        format!("robo_arc_data_{}.json", user_id),
+        serde_json::to_vec_pretty(&json!({
+            "user_id": user_id,
+            "exported_at": chrono::offset::Utc::now().to_rfc3339(),
+            "reminders": reminders,
+            "mutes": mutes,
+            "osu_user": osu_user,
+            "best_bg": best_bg,
+            "playlists": playlists,
+            "archived_attachments": archived_attachments,
+            "log_messages_count": log_messages_count,
+        }))?,
+    )];
+
+    for (index, data) in log_message_files.into_iter().enumerate() {
+        files.push((
+            format!("robo_arc_log_messages_{}_{}.json", user_id, index + 1),
+            data,
+        ));
+    }
+
+    let total = files.len();
+
+    for (index, (filename, data)) in files.into_iter().enumerate() {
+        let attachment = AttachmentType::Bytes {
+            data: Cow::from(data),
+            filename,
+        };
+
+        if let Err(why) = msg
+            .author
+            .direct_message(ctx, |m| {
+                if index == 0 {
+                    m.content("Here's all the data i have stored about you.");
+                }
+                m.add_file(attachment)
+            })
+            .await
+        {
+            error!("Error sending data export: {}", why);
+            msg.reply(
+                ctx,
+                format!(
+                    "I was unable to DM you the data export ({}/{} files sent), please make sure your DMs are open.",
+                    index, total
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    msg.react(ctx, '✅').await?;
+
+    Ok(())
+}
+
+/// Deletes all the data the bot stores about you.
+///
+/// This includes the logged messages you have sent on guilds with logging and their archived
+/// attachments, your reminders, your osu! account configuration, your best boy and best girl tags
+/// and your saved playlists.
+/// Active mutes are kept until they expire, at which point they are deleted.
+///
+/// This cannot be undone, use `privacy export` first if you want a copy.
+#[command]
+#[aliases(delete, remove)]
+async fn forget(ctx: &Context, msg: &Message) -> CommandResult {
+    let mut confirmation = msg
+        .reply(
+            ctx,
+            "This will delete all the data i have stored about you, and it cannot be undone.\nAre you sure?",
+        )
+        .await?;
+    confirmation.react(ctx, '✅').await?;
+    confirmation.react(ctx, '❌').await?;
+
+    let confirmed = if let Some(reaction) = msg
+        .author
+        .await_reaction(ctx)
+        .message_id(confirmation.id)
+        .timeout(Duration::from_secs(120))
+        .await
+    {
+        reaction.as_inner_ref().emoji.as_data().as_str() == "✅"
+    } else {
+        false
+    };
+
+    let _ = confirmation.delete_reactions(ctx).await;
+
+    if !confirmed {
+        confirmation
+            .edit(ctx, |m| m.content("Cancelled, nothing was deleted."))
+            .await?;
+        return Ok(());
+    }
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let user_id = msg.author.id.0 as i64;
+
+    let mut transaction = pool.begin().await?;
+
+    let messages = sqlx::query!("DELETE FROM log_messages WHERE author_id = $1", user_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    let attachment_hashes = sqlx::query!(
+        "DELETE FROM archived_attachments WHERE author_id = $1 RETURNING hash",
+        user_id
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|i| i.hash)
+    .collect::<Vec<String>>();
+    sqlx::query!("DELETE FROM reminders WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query!("DELETE FROM osu_user WHERE discord_id = $1", user_id)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query!("DELETE FROM best_bg WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM playlists WHERE owner_id = $1 AND guild_playlist = false",
+        user_id
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    let archive_config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Tokens>()
+            .unwrap()
+            .attachment_archive
+            .clone()
+    };
+
+    remove_unreferenced_files(&pool, &archive_config, attachment_hashes).await?;
+
+    confirmation
+        .edit(ctx, |m| {
+            m.content(format!(
+                "Done, all your data has been deleted, including {} logged messages.",
+                messages
+            ))
+        })
+        .await?;
+
+    Ok(())
+}
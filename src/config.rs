@@ -1,6 +1,8 @@
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConfigurationData {
-    pub old_osu: String,
+    /// The legacy osu! API v1 key, no longer used by any command.
+    #[serde(default)]
+    pub old_osu: Option<String>,
     pub discord: String,
     pub twitch: String,
     pub twitch_client_id: String,
@@ -14,6 +16,10 @@ pub struct ConfigurationData {
     pub web_server: WebServerConfig,
     pub ibm: IBMConfig,
     pub osu: OsuConfig,
+    #[serde(default)]
+    pub attachment_archive: AttachmentArchiveConfig,
+    #[serde(default)]
+    pub music: MusicConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -33,6 +39,39 @@ pub struct LavalinkConfig {
     pub host: String,
     pub port: u16,
     pub password: String,
+    /// The voice regions the main node is close to.
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// Additional nodes, new players are balanced between all of them.
+    #[serde(default)]
+    pub nodes: Vec<LavalinkNodeConfig>,
+}
+
+impl LavalinkConfig {
+    /// The main node, followed by the additional ones.
+    pub fn all_nodes(&self) -> Vec<LavalinkNodeConfig> {
+        let main = LavalinkNodeConfig {
+            name: "main".to_string(),
+            host: self.host.clone(),
+            port: self.port,
+            password: self.password.clone(),
+            regions: self.regions.clone(),
+        };
+
+        std::iter::once(main)
+            .chain(self.nodes.iter().cloned())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LavalinkNodeConfig {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+    #[serde(default)]
+    pub regions: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -52,3 +91,43 @@ pub struct OsuConfig {
     pub client_id: u16,
     pub client_secret: String,
 }
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AttachmentArchiveConfig {
+    pub path: String,
+    pub public_url: Option<String>,
+    pub max_file_size: u64,
+    pub max_guild_size: u64,
+    pub retention_days: i32,
+}
+
+impl Default for AttachmentArchiveConfig {
+    fn default() -> Self {
+        Self {
+            path: "attachment_archive".to_string(),
+            public_url: None,
+            max_file_size: 8_388_608,
+            max_guild_size: 1_073_741_824,
+            retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MusicConfig {
+    /// Seconds to wait before leaving a voice channel nobody else is on.
+    pub alone_timeout: u64,
+    /// Seconds to wait before leaving a voice channel after the queue ends.
+    pub idle_timeout: u64,
+}
+
+impl Default for MusicConfig {
+    fn default() -> Self {
+        Self {
+            alone_timeout: 60,
+            idle_timeout: 300,
+        }
+    }
+}
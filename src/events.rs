@@ -1,4 +1,11 @@
 use crate::notifications::notification_loop;
+use crate::utils::lavalink::failover_loop;
+use crate::utils::music::{
+    format_duration, leave_voice, restore_queue_snapshots, save_queue_position,
+    save_queue_snapshot, AudioSettings, AutoLeave, LeaveReason, LoopMode, MusicStateMap,
+};
+use crate::utils::osu::{parse_beatmap_id, set_channel_beatmap};
+use crate::utils::osu_preview::{is_osu_replay, preview_osu_links, preview_osu_replays};
 use crate::AnnoyedChannels;
 use crate::DatabasePool;
 use crate::Tokens;
@@ -6,13 +13,20 @@ use crate::Tokens;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 
-use lavalink_rs::gateway::LavalinkEventHandler;
+use lavalink_rs::{
+    gateway::LavalinkEventHandler,
+    model::{PlayerUpdate, Stats, TrackException, TrackFinish, TrackStart, TrackStuck},
+    LavalinkClient,
+};
 use tokio::sync::Mutex;
 use warp::{reply::json, reply::Json, Filter};
 
 use serenity::{
     async_trait,
+    builder::CreateMessage,
+    http::Http,
     model::{
         channel::{Message, Reaction, ReactionType},
         gateway::{Activity, Ready},
@@ -20,6 +34,7 @@ use serenity::{
         id::{ChannelId, GuildId},
         user::OnlineStatus,
         //event::VoiceServerUpdateEvent,
+        voice::VoiceState,
     },
     prelude::{Context, EventHandler},
 };
@@ -29,10 +44,281 @@ pub struct Allow {
     allowed: bool,
 }
 
-pub struct LavalinkHandler;
+/// The event handler of a lavalink node.
+pub struct LavalinkHandler {
+    /// The index of the node on the lavalink pool.
+    pub node_index: usize,
+    pub music_states: MusicStateMap,
+    pub http: Arc<Http>,
+    pub auto_leave: AutoLeave,
+}
+
+impl LavalinkHandler {
+    /// Sends a message to the channel the track was requested from.
+    async fn announce<F>(&self, guild_id: u64, track: &str, f: F)
+    where
+        F: for<'a, 'b> FnOnce(&'b mut CreateMessage<'a>) -> &'b mut CreateMessage<'a>,
+    {
+        let channel_id = {
+            let music_states = self.music_states.read().await;
+            music_states
+                .get(&guild_id)
+                .and_then(|state| state.request_channel(track))
+        };
+
+        if let Some(channel_id) = channel_id {
+            if let Err(why) = ChannelId(channel_id).send_message(&self.http, f).await {
+                warn!("Unable to send a music announcement: {}", why);
+            }
+        }
+    }
+
+    /// Skips a track that got stuck, stopping the player if the queue ended.
+    async fn skip_failed(&self, client: &LavalinkClient, guild_id: u64) {
+        client.skip(GuildId(guild_id)).await;
+
+        let queue_ended = client
+            .nodes()
+            .await
+            .get(&guild_id)
+            .map(|node| node.queue.is_empty() && node.now_playing.is_none())
+            .unwrap_or(false);
+
+        if queue_ended {
+            if let Err(why) = client.stop(GuildId(guild_id)).await {
+                error!("Error stopping the player after a failed track: {}", why);
+            }
+        }
+    }
+
+    /// The title of the track that is currently playing, if it's the provided track.
+    async fn current_title(&self, guild_id: u64, track: &str) -> String {
+        let music_states = self.music_states.read().await;
+        music_states
+            .get(&guild_id)
+            .and_then(|state| state.current.as_ref())
+            .filter(|current| current.track.track == track)
+            .and_then(|current| current.track.info.as_ref())
+            .map(|info| info.title.replace("@", "@\u{200B}"))
+            .unwrap_or_else(|| "Unknown track".to_string())
+    }
+
+    async fn save_queue(&self, client: &LavalinkClient, guild_id: u64) {
+        save_queue_snapshot(
+            &self.auto_leave.songbird,
+            client,
+            &self.music_states,
+            &self.auto_leave.redis_pool,
+            guild_id,
+        )
+        .await;
+    }
+}
 
 #[async_trait]
-impl LavalinkEventHandler for LavalinkHandler {}
+impl LavalinkEventHandler for LavalinkHandler {
+    async fn track_start(&self, client: LavalinkClient, event: TrackStart) {
+        let guild_id = event.guild_id.0;
+
+        let current = client
+            .nodes()
+            .await
+            .get(&guild_id)
+            .and_then(|node| node.now_playing.clone());
+
+        let apply_audio = {
+            let mut music_states = self.music_states.write().await;
+            let state = music_states.entry(guild_id).or_default();
+            state.current = current.clone();
+            state.position = Some((0, Instant::now()));
+            state.idle_since = None;
+            state.skip_votes.clear();
+            !std::mem::replace(&mut state.audio_applied, true)
+        };
+
+        // New players start with the default volume and no filters.
+        if apply_audio {
+            let settings = AudioSettings::load(&self.auto_leave.pool, guild_id).await;
+
+            if settings != AudioSettings::default() {
+                if let Err(why) = settings.apply(&client, guild_id).await {
+                    error!("Error applying the saved audio settings: {}", why);
+                }
+            }
+        }
+
+        self.save_queue(&client, guild_id).await;
+
+        let track = if let Some(x) = current {
+            x
+        } else {
+            return;
+        };
+
+        let track_info = if let Some(x) = track.track.info.as_ref() {
+            x
+        } else {
+            return;
+        };
+
+        self.announce(guild_id, &event.track, |m| {
+            m.content("Now playing:");
+            m.embed(|e| {
+                e.title(&track_info.title);
+                e.thumbnail(format!(
+                    "https://i.ytimg.com/vi/{}/default.jpg",
+                    track_info.identifier
+                ));
+                e.url(&track_info.uri);
+                e.field("Uploader", &track_info.author, true);
+                e.field("Length", format_duration(track_info.length), true);
+                if let Some(requester) = track.requester {
+                    e.field("Requested by", format!("<@{}>", requester.0), true);
+                }
+                e
+            })
+        })
+        .await;
+    }
+
+    async fn track_finish(&self, client: LavalinkClient, event: TrackFinish) {
+        let guild_id = event.guild_id.0;
+
+        debug!(
+            "Track finished on guild {} with reason {}",
+            guild_id, event.reason
+        );
+
+        let (loop_mode, track) = {
+            let mut music_states = self.music_states.write().await;
+            let state = music_states.entry(guild_id).or_default();
+            state.position = None;
+            (state.loop_mode, state.current.take())
+        };
+
+        let still_queued = client
+            .nodes()
+            .await
+            .get(&guild_id)
+            .map(|node| node.queue.iter().any(|i| i.track.track == event.track))
+            .unwrap_or(false);
+
+        // Only tracks that ended by themselves are looped, so skip and stop still work.
+        if event.reason != "FINISHED" || loop_mode == LoopMode::Off {
+            let queue_ended = client
+                .nodes()
+                .await
+                .get(&guild_id)
+                .map(|node| node.queue.is_empty())
+                .unwrap_or(false);
+
+            if let Some(state) = self.music_states.write().await.get_mut(&guild_id) {
+                if !still_queued {
+                    state.request_channels.remove(&event.track);
+                }
+
+                if queue_ended {
+                    state.idle_since = Some(Instant::now());
+                    self.auto_leave.schedule(guild_id, LeaveReason::Idle);
+                }
+            }
+
+            self.save_queue(&client, guild_id).await;
+
+            return;
+        }
+
+        let track = if let Some(x) = track {
+            x
+        } else {
+            return;
+        };
+
+        // If the queue loop is still running, the track just needs to be put back on the queue,
+        // otherwise it has to be queued again to restart the loop.
+        if client.loops().await.contains(&guild_id) {
+            if let Some(mut node) = client.nodes().await.get_mut(&guild_id) {
+                if loop_mode == LoopMode::Track {
+                    node.queue.insert(0, track);
+                } else {
+                    node.queue.push(track);
+                }
+
+                return;
+            }
+        }
+
+        let mut play = client.play(event.guild_id, track.track);
+        if let Some(requester) = track.requester {
+            play = play.requester(requester);
+        }
+
+        if let Err(why) = play.queue().await {
+            error!("Error queueing the looped track: {}", why);
+        }
+    }
+
+    async fn track_exception(&self, _client: LavalinkClient, event: TrackException) {
+        let guild_id = event.guild_id.0;
+
+        warn!("Track exception on guild {}: {}", guild_id, event.error);
+
+        let title = self.current_title(guild_id, &event.track).await;
+
+        // Lavalink ends the track with `LOAD_FAILED` after the exception, which already moves
+        // the queue, so it's only announced.
+        self.announce(guild_id, &event.track, |m| {
+            m.content(format!(
+                "An error occurred while playing `{}`, skipping it.\n```{}```",
+                title, event.error
+            ))
+        })
+        .await;
+    }
+
+    async fn track_stuck(&self, client: LavalinkClient, event: TrackStuck) {
+        let guild_id = event.guild_id.0;
+
+        warn!(
+            "Track stuck on guild {} for {}ms",
+            guild_id, event.threshold_ms
+        );
+
+        let title = self.current_title(guild_id, &event.track).await;
+
+        self.announce(guild_id, &event.track, |m| {
+            m.content(format!(
+                "`{}` got stuck for over {} seconds, skipping it.",
+                title,
+                event.threshold_ms / 1000
+            ))
+        })
+        .await;
+
+        self.skip_failed(&client, guild_id).await;
+    }
+
+    async fn player_update(&self, _client: LavalinkClient, event: PlayerUpdate) {
+        let position = event.state.position as u64;
+
+        if let Some(state) = self.music_states.write().await.get_mut(&event.guild_id.0) {
+            state.position = Some((position, Instant::now()));
+        } else {
+            return;
+        }
+
+        // Keeps the saved position up to date, in case the bot restarts.
+        // The rest of the queue is only saved when it changes.
+        save_queue_position(&self.auto_leave.redis_pool, event.guild_id.0, position).await;
+    }
+
+    async fn stats(&self, _client: LavalinkClient, event: Stats) {
+        self.auto_leave
+            .lavalink
+            .update_stats(self.node_index, &event)
+            .await;
+    }
+}
 
 // Defines the handler to be used for events.
 #[derive(Debug)]
@@ -65,22 +351,45 @@ impl EventHandler for Handler {
 
             let ctx = Arc::new(ctx);
 
-            let web_server_info = {
+            let (web_server_info, archive_info) = {
                 let read_data = ctx.data.read().await;
                 let config = read_data.get::<Tokens>().unwrap();
-                config.web_server.clone()
+                (config.web_server.clone(), config.attachment_archive.clone())
             };
 
             let ctx_clone = Arc::clone(&ctx);
             let ctx_clone2 = Arc::clone(&ctx);
+            let ctx_clone3 = Arc::clone(&ctx);
+
+            // Resume the queues that were playing before the restart.
+            tokio::spawn(async move { restore_queue_snapshots(&ctx_clone3).await });
+
+            // Move the players of the lavalink nodes that stop responding.
+            let auto_leave = AutoLeave::from_context(&ctx).await;
+            tokio::spawn(async move { failover_loop(auto_leave).await });
 
             let notification_loop = tokio::spawn(async move { notification_loop(ctx_clone).await });
 
             tokio::spawn(async move {
-                let routes = warp::path::param()
+                let guild_route = warp::path::param()
                     .and(warp::any().map(move || ctx_clone2.clone()))
                     .and_then(is_on_guild);
 
+                // Archived attachments of deleted messages, only served if a public url is configured.
+                let serve_attachments = archive_info.public_url.is_some();
+                let attachments_route = warp::path("attachments")
+                    .and(warp::any().and_then(move || async move {
+                        if serve_attachments {
+                            Ok::<(), warp::Rejection>(())
+                        } else {
+                            Err(warp::reject::not_found())
+                        }
+                    }))
+                    .untuple_one()
+                    .and(warp::fs::dir(archive_info.path));
+
+                let routes = attachments_route.or(guild_route);
+
                 let ip = web_server_info.server_ip;
                 let port = web_server_info.server_port;
 
@@ -149,6 +458,29 @@ impl EventHandler for Handler {
             }
         }
 
+        // Remember the pasted beatmaps, so the osu! commands can default to them.
+        if msg.content.contains("ppy.sh") {
+            if let Some(beatmap_id) = msg
+                .content
+                .split_whitespace()
+                .filter(|i| i.contains("ppy.sh"))
+                .filter_map(parse_beatmap_id)
+                .last()
+            {
+                set_channel_beatmap(&ctx, msg.channel_id, beatmap_id).await;
+            }
+
+            if let Err(why) = preview_osu_links(&ctx, &msg).await {
+                error!("Error previewing the osu! links of a message: {}", why);
+            }
+        }
+
+        if msg.attachments.iter().any(is_osu_replay) {
+            if let Err(why) = preview_osu_replays(&ctx, &msg).await {
+                error!("Error reading the osu! replay of a message: {}", why);
+            }
+        }
+
         if msg.content.contains("discordapp.com/channels/")
             || msg.content.contains("discord.com/channels/")
         {
@@ -322,6 +654,87 @@ impl EventHandler for Handler {
         }
     }
 
+    // This function triggers every time someone joins, leaves or moves voice channels.
+    // It's used to leave the voice channel when nobody is listening anymore.
+    async fn voice_state_update(
+        &self,
+        ctx: Context,
+        guild_id: Option<GuildId>,
+        _old: Option<VoiceState>,
+        new: VoiceState,
+    ) {
+        let guild_id = if let Some(x) = guild_id {
+            x
+        } else {
+            return;
+        };
+
+        let bot_id = ctx.cache.current_user_id().await;
+        let auto_leave = AutoLeave::from_context(&ctx).await;
+
+        // Someone disconnected the bot, so the player is left behind.
+        if new.user_id == bot_id && new.channel_id.is_none() {
+            let lava_client = auto_leave.lavalink.client(guild_id.0).await;
+
+            if lava_client.nodes().await.contains_key(&guild_id.0) {
+                if let Err(why) = leave_voice(
+                    &auto_leave.songbird,
+                    &auto_leave.lavalink,
+                    &auto_leave.music_states,
+                    &auto_leave.redis_pool,
+                    guild_id.0,
+                )
+                .await
+                {
+                    error!("Error cleaning up a disconnected player: {}", why);
+                }
+            }
+
+            return;
+        }
+
+        let guild = if let Some(x) = ctx.cache.guild(guild_id).await {
+            x
+        } else {
+            return;
+        };
+
+        let bot_channel = if let Some(x) = guild
+            .voice_states
+            .get(&bot_id)
+            .and_then(|voice_state| voice_state.channel_id)
+        {
+            x
+        } else {
+            return;
+        };
+
+        let listeners = guild
+            .voice_states
+            .values()
+            .filter(|voice_state| voice_state.channel_id == Some(bot_channel))
+            .filter(|voice_state| {
+                guild
+                    .members
+                    .get(&voice_state.user_id)
+                    .map(|member| !member.user.bot)
+                    .unwrap_or(voice_state.user_id != bot_id)
+            })
+            .count();
+
+        let mut music_states = auto_leave.music_states.write().await;
+        let state = music_states.entry(guild_id.0).or_default();
+
+        if listeners == 0 {
+            if state.alone_since.is_none() {
+                state.alone_since = Some(Instant::now());
+                auto_leave.schedule(guild_id.0, LeaveReason::Alone);
+            }
+        } else {
+            state.alone_since = None;
+        }
+    }
+
     //async fn voice_server_update(&self, ctx: Context, vsu: VoiceServerUpdateEvent) {
     //    dbg!(&vsu);
 
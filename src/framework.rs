@@ -14,6 +14,9 @@ use crate::commands::moderation::*; // Import everything from the moderation mod
 use crate::commands::music::*; // Import everything from the configuration module.
 use crate::commands::new_osu::*; // Import everything from the new osu module.
 use crate::commands::osu::*; // Import everything from the osu module.
+use crate::commands::osu_tracking::*; // Import everything from the osu tracking module.
+use crate::commands::playlist::*; // Import everything from the playlist module.
+use crate::commands::privacy::*; // Import everything from the privacy module.
 use crate::commands::sankaku::*; // Import everything from the sankaku booru module.
 use crate::commands::serenity_docs::*; // Import everything from the serenity_docs module.
 
@@ -36,11 +39,13 @@ use serenity::{
     Osu,
     Fun,
     Music,
+    Playlists,
     AllBoorus,
     ImageManipulation,
     Mod,
     SerenityDocs,
-    Games
+    Games,
+    Privacy
 )]
 pub struct Master;
 
@@ -77,7 +82,16 @@ pub struct Sankaku;
 // This group contains all the osu! related commands.
 #[group("osu!")]
 #[description = "All the osu! related commands"]
-#[commands(configure_osu, recent, score, osu_profile, osu_top, beatmap_pp)]
+#[commands(
+    configure_osu,
+    recent,
+    score,
+    leaderboard,
+    osu_profile,
+    osu_top,
+    beatmap_pp,
+    replay
+)]
 pub struct Osu;
 
 #[group("new osu!")]
@@ -85,6 +99,20 @@ pub struct Osu;
 #[commands(new_recent)]
 pub struct NewOsu;
 
+// The osu! top play tracking commands.
+// Without a subcommand it shows the profile of the user, like `osu_profile`.
+#[group("osu! Tracking")]
+#[description = "All the commands related to announcing the new top plays of osu! players, and their rank over time.
+Basic usage:
+`osu track <user> [#channel]`
+`osu untrack <user>`
+`osu tracked`
+`osu history [user]`"]
+#[prefixes("osu")]
+#[default_command(osu_profile)]
+#[commands(osu_track, osu_untrack, osu_tracked, osu_history)]
+pub struct OsuTracking;
+
 // The Booru command group.
 // This group will contain every single command from every booru that gets implemented.
 // As you can see on the last line, the description also supports url markdown.
@@ -144,7 +172,8 @@ pub struct Games;
     permanent_mute,
     temporal_mute,
     permanent_self_mute,
-    temporal_self_mute
+    temporal_self_mute,
+    history
 )]
 pub struct Mod;
 
@@ -156,27 +185,72 @@ pub struct Mod;
     join,
     leave,
     play,
+    search,
     play_playlist,
     pause,
     resume,
     stop,
     skip,
+    skipto,
     remove,
+    move_track,
+    swap,
+    dedupe,
     seek,
     shuffle,
     queue,
     clear_queue,
     now_playing,
+    loop_mode,
+    stay,
+    volume,
+    filters,
     equalize,
     equalize_band
 )]
 pub struct Music;
 
+// The saved playlists commands.
+// Without a subcommand it adds a remote playlist to the queue, like `play_playlist`.
+#[group("Playlists")]
+#[description = "All the commands related to saved playlists.
+Add `--guild` to use the playlists of the guild instead of your own.
+Basic usage:
+`playlist save <name>`
+`playlist load <name>`
+`playlist list`"]
+#[only_in("guilds")]
+#[prefixes("playlist", "pl")]
+#[default_command(play_playlist)]
+#[commands(
+    playlist_save,
+    playlist_add,
+    playlist_remove,
+    playlist_list,
+    playlist_show,
+    playlist_delete,
+    playlist_load,
+    playlist_export,
+    playlist_import
+)]
+pub struct Playlists;
+
 #[group("Serenity Documentation")]
 #[description = "All the commands related to serenity's documentation."]
 #[commands(example, rtfm)]
 pub struct SerenityDocs;
 
+// The privacy commands.
+// For users to obtain or delete all the data the bot stores about them.
+#[group("Privacy")]
+#[description = "All the commands related to the data the bot stores about you.
+Basic usage:
+`privacy export`
+`privacy forget`"]
+#[prefixes("privacy")]
+#[commands(export, forget)]
+pub struct Privacy;
+
 // The configuration command.
 // Technically a group, but it only has a single command.
 #[group("Configuration")]
@@ -1,4 +1,8 @@
-use crate::{notifications::TwitchStreamData, Booru, ConfigurationData};
+use crate::{
+    notifications::TwitchStreamData,
+    utils::{lavalink::LavalinkPool, music::MusicStateMap, osu_api::OsuApi},
+    Booru, ConfigurationData,
+};
 
 use std::{collections::HashSet, sync::Arc, time::Instant};
 
@@ -7,8 +11,6 @@ use tokio::sync::{Mutex, RwLock};
 use serenity::{client::bridge::gateway::ShardManager, prelude::TypeMapKey};
 
 use darkredis::ConnectionPool as RedisPool;
-use lavalink_rs::LavalinkClient;
-use reqwest::Client as ReqwestClient;
 use sqlx::PgPool; // PostgreSQL Pool Structure
 
 // Defining the structures to be used for "global" data
@@ -20,10 +22,11 @@ pub struct Tokens; // For the configuration found on "config.toml"
 pub struct AnnoyedChannels; // This is a HashSet of all the channels the bot is allowed to be annoyed on.
 pub struct BooruList; // This is a HashSet of all the boorus found on "boorus.json"
 pub struct BooruCommands; // This is a HashSet of all the commands/aliases found on "boorus.json"
-pub struct Lavalink; //  This is the struct for the lavalink client.
+pub struct Lavalink; //  This is the struct for the lavalink nodes.
 pub struct SentTwitchStreams; //  This is the struct for the stream data that has already been sent.
 pub struct Uptime; //  This is for the startup time of the bot.
 pub struct OsuHttpClient; // This is the HTTP client to comunicate with osu! API v2.
+pub struct MusicStates; // This is the music state of every guild that isn't stored by lavalink.
 
 impl TypeMapKey for ShardManagerContainer {
     type Value = Arc<Mutex<ShardManager>>;
@@ -54,7 +57,7 @@ impl TypeMapKey for BooruCommands {
 }
 
 impl TypeMapKey for Lavalink {
-    type Value = LavalinkClient;
+    type Value = LavalinkPool;
 }
 
 impl TypeMapKey for SentTwitchStreams {
@@ -66,5 +69,9 @@ impl TypeMapKey for Uptime {
 }
 
 impl TypeMapKey for OsuHttpClient {
-    type Value = Arc<RwLock<ReqwestClient>>;
+    type Value = OsuApi;
+}
+
+impl TypeMapKey for MusicStates {
+    type Value = MusicStateMap;
 }
@@ -0,0 +1,233 @@
+use crate::config::AttachmentArchiveConfig;
+use crate::global_data::{DatabasePool, Tokens};
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use sqlx::PgPool;
+
+use serenity::{model::channel::Message, prelude::Context};
+
+/// An attachment stored on the local archive.
+pub struct ArchivedAttachment {
+    pub filename: String,
+    pub hash: String,
+    pub size: i64,
+}
+
+/// The path of a file on the archive, files are stored by the sha256 of their content.
+pub fn archive_path(config: &AttachmentArchiveConfig, hash: &str) -> PathBuf {
+    PathBuf::from(&config.path).join(&hash[..2]).join(hash)
+}
+
+/// The url a file is served at by the web server, if a public url is configured.
+pub fn archive_url(
+    config: &AttachmentArchiveConfig,
+    attachment: &ArchivedAttachment,
+) -> Option<String> {
+    config.public_url.as_ref().map(|url| {
+        format!(
+            "{}/attachments/{}/{}",
+            url.trim_end_matches('/'),
+            &attachment.hash[..2],
+            attachment.hash
+        )
+    })
+}
+
+pub async fn guild_archives_attachments(pool: &PgPool, guild_id: u64) -> bool {
+    match sqlx::query!(
+        "SELECT guild_id FROM attachment_archive_guilds WHERE guild_id = $1",
+        guild_id as i64
+    )
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(x) => x.is_some(),
+        Err(why) => {
+            error!("Error quering Database: {}", why);
+            false
+        }
+    }
+}
+
+/// Downloads and stores the attachments of a message, if the guild has archiving enabled.
+#[instrument(skip(ctx))]
+pub async fn archive_attachments(ctx: Arc<Context>, message: &Message) {
+    if message.attachments.is_empty() {
+        return;
+    }
+
+    let guild_id = if let Some(x) = message.guild_id {
+        x.0
+    } else {
+        return;
+    };
+
+    let (pool, config) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read.get::<DatabasePool>().unwrap().clone(),
+            data_read
+                .get::<Tokens>()
+                .unwrap()
+                .attachment_archive
+                .clone(),
+        )
+    };
+
+    if !guild_archives_attachments(&pool, guild_id).await {
+        return;
+    }
+
+    let mut guild_size = match sqlx::query!(
+        "SELECT SUM(size)::bigint AS total FROM archived_attachments WHERE guild_id = $1",
+        guild_id as i64
+    )
+    .fetch_one(&pool)
+    .await
+    {
+        Ok(x) => x.total.unwrap_or(0) as u64,
+        Err(why) => {
+            error!("Error quering Database: {}", why);
+            return;
+        }
+    };
+
+    for attachment in &message.attachments {
+        if attachment.size > config.max_file_size {
+            debug!("Attachment {} is too big to be archived.", attachment.id);
+            continue;
+        }
+
+        if guild_size + attachment.size > config.max_guild_size {
+            warn!(
+                "Guild {} has reached the attachment archive size limit.",
+                guild_id
+            );
+            return;
+        }
+
+        let bytes = match reqwest::get(&attachment.url).await {
+            Ok(res) => match res.bytes().await {
+                Ok(x) => x,
+                Err(why) => {
+                    error!("Error downloading attachment {}: {}", attachment.id, why);
+                    continue;
+                }
+            },
+            Err(why) => {
+                error!("Error downloading attachment {}: {}", attachment.id, why);
+                continue;
+            }
+        };
+
+        let hash = {
+            let mut hasher = Sha256::new();
+            hasher.input(&bytes);
+            hasher.result_str()
+        };
+
+        let path = archive_path(&config, &hash);
+
+        // Identical files are only stored once.
+        if tokio::fs::metadata(&path).await.is_err() {
+            if let Some(parent) = path.parent() {
+                if let Err(why) = tokio::fs::create_dir_all(parent).await {
+                    error!("Error creating attachment archive directory: {}", why);
+                    return;
+                }
+            }
+
+            if let Err(why) = tokio::fs::write(&path, &bytes).await {
+                error!("Error writing archived attachment: {}", why);
+                continue;
+            }
+        }
+
+        if let Err(why) = sqlx::query!(
+            "INSERT INTO archived_attachments (message_id, guild_id, author_id, filename, hash, size) VALUES ($1, $2, $3, $4, $5, $6)",
+            message.id.0 as i64,
+            guild_id as i64,
+            message.author.id.0 as i64,
+            &attachment.filename,
+            &hash,
+            bytes.len() as i64,
+        )
+        .execute(&pool)
+        .await
+        {
+            error!("Error inserting archived attachment to database: {}", why);
+            continue;
+        }
+
+        guild_size += bytes.len() as u64;
+    }
+}
+
+pub async fn archived_attachments(pool: &PgPool, message_id: u64) -> Vec<ArchivedAttachment> {
+    match sqlx::query_as!(
+        ArchivedAttachment,
+        "SELECT filename, hash, size FROM archived_attachments WHERE message_id = $1 ORDER BY id",
+        message_id as i64
+    )
+    .fetch_all(pool)
+    .await
+    {
+        Ok(x) => x,
+        Err(why) => {
+            error!("Error quering Database: {}", why);
+            vec![]
+        }
+    }
+}
+
+/// Removes the files of the given hashes that are no longer referenced by any archived attachment.
+pub async fn remove_unreferenced_files(
+    pool: &PgPool,
+    config: &AttachmentArchiveConfig,
+    mut hashes: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    hashes.sort();
+    hashes.dedup();
+
+    let referenced = sqlx::query!(
+        "SELECT DISTINCT hash FROM archived_attachments WHERE hash = ANY($1)",
+        &hashes
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|i| i.hash)
+    .collect::<Vec<String>>();
+
+    for hash in hashes.iter().filter(|i| !referenced.contains(i)) {
+        if let Err(why) = tokio::fs::remove_file(archive_path(config, hash)).await {
+            warn!("Error removing archived attachment {}: {}", hash, why);
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the archived attachments older than the configured retention.
+pub async fn prune_archive(
+    pool: &PgPool,
+    config: &AttachmentArchiveConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let hashes = sqlx::query!(
+        "DELETE FROM archived_attachments WHERE archived_at < now() - make_interval(days => $1) RETURNING hash",
+        config.retention_days
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|i| i.hash)
+    .collect::<Vec<String>>();
+
+    debug!("Pruned {} archived attachments.", hashes.len());
+
+    remove_unreferenced_files(pool, config, hashes).await
+}
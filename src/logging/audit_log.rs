@@ -0,0 +1,201 @@
+use crate::global_data::CachePool;
+
+use std::time::Duration;
+
+use serenity::{
+    builder::CreateEmbed,
+    model::{
+        guild::AuditLogEntry,
+        id::{ChannelId, GuildId},
+        user::User,
+    },
+    prelude::{Context, Mentionable},
+};
+
+// The audit log action types, as documented on
+// https://discord.com/developers/docs/resources/audit-log#audit-log-entry-object-audit-log-events
+pub const CHANNEL_DELETE: u8 = 12;
+pub const MEMBER_KICK: u8 = 20;
+pub const MEMBER_BAN_ADD: u8 = 22;
+pub const MEMBER_BAN_REMOVE: u8 = 23;
+pub const ROLE_DELETE: u8 = 32;
+pub const MESSAGE_DELETE: u8 = 72;
+
+// Discord may take a moment to write the audit log entry after the gateway event is sent.
+// Most events have no entry at all, like members leaving or deleting their own messages,
+// so it's only retried once to not spend seconds and requests on every one of them.
+const ATTEMPTS: u8 = 2;
+const RETRY_DELAY: Duration = Duration::from_millis(1500);
+// How old an entry can be to still be considered the cause of an event.
+const MAX_ENTRY_AGE_MS: i64 = 15_000;
+
+/// Who caused a logged event, and why.
+pub struct Attribution {
+    pub moderator: User,
+    pub reason: Option<String>,
+}
+
+impl Attribution {
+    /// Adds the "Responsible moderator" and "Reason" fields to a logging embed.
+    pub fn add_fields(&self, e: &mut CreateEmbed) {
+        e.field(
+            "Responsible moderator",
+            format!("{} ({})", self.moderator.mention(), self.moderator.tag()),
+            false,
+        );
+        e.field(
+            "Reason",
+            self.reason.as_deref().unwrap_or("No reason provided."),
+            false,
+        );
+    }
+}
+
+// Obtains the creation time in milliseconds of an audit log entry from it's snowflake.
+fn entry_age_ms(entry: &AuditLogEntry) -> i64 {
+    let created_at = ((entry.id.0 >> 22) + 1_420_070_400_000) as i64;
+    chrono::offset::Utc::now().timestamp_millis() - created_at
+}
+
+/// Finds the audit log entry responsible of an action done to `target_id`.
+///
+/// This retries once, as the entry may not be available yet when the event is received.
+/// Returns None if the bot is missing the `View Audit Log` permission or no entry was found.
+#[instrument(skip(ctx))]
+pub async fn find_responsible(
+    ctx: &Context,
+    guild_id: GuildId,
+    action: u8,
+    target_id: u64,
+) -> Option<Attribution> {
+    for attempt in 0..ATTEMPTS {
+        let audit_logs = match guild_id
+            .audit_logs(ctx, Some(action), None, None, Some(10))
+            .await
+        {
+            Ok(x) => x,
+            Err(why) => {
+                debug!("Unable to obtain the audit logs of {}: {}", guild_id, why);
+                return None;
+            }
+        };
+
+        let entry = audit_logs.entries.values().find(|entry| {
+            entry.target_id == Some(target_id) && entry_age_ms(entry) <= MAX_ENTRY_AGE_MS
+        });
+
+        if let Some(entry) = entry {
+            let moderator = if let Some(x) = audit_logs.users.iter().find(|u| u.id == entry.user_id)
+            {
+                x.clone()
+            } else {
+                entry.user_id.to_user(ctx).await.ok()?
+            };
+
+            return Some(Attribution {
+                moderator,
+                reason: entry.reason.clone(),
+            });
+        }
+
+        if attempt + 1 < ATTEMPTS {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+
+    None
+}
+
+/// Finds who deleted a message of `author_id` on `channel_id`.
+///
+/// Discord does not create an entry when the author deletes their own message, and it groups
+/// consecutive deletions of the same moderator into a single entry with a counter, so the
+/// counters are cached to know when an old entry has been reused.
+#[instrument(skip(ctx))]
+pub async fn find_message_deleter(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    author_id: u64,
+) -> Option<Attribution> {
+    let redis_pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<CachePool>().unwrap().clone()
+    };
+
+    for attempt in 0..ATTEMPTS {
+        let audit_logs = match guild_id
+            .audit_logs(ctx, Some(MESSAGE_DELETE), None, None, Some(25))
+            .await
+        {
+            Ok(x) => x,
+            Err(why) => {
+                debug!("Unable to obtain the audit logs of {}: {}", guild_id, why);
+                return None;
+            }
+        };
+
+        let mut redis = redis_pool.get().await;
+
+        for entry in audit_logs.entries.values() {
+            if entry.target_id != Some(author_id) {
+                continue;
+            }
+
+            let (entry_channel, count) = if let Some(options) = &entry.options {
+                (options.channel_id, options.count.unwrap_or(1))
+            } else {
+                continue;
+            };
+
+            if entry_channel != Some(channel_id) {
+                continue;
+            }
+
+            let key = format!("audit_log_message_delete:{}", entry.id.0);
+
+            let cached_count = match redis.get(&key).await {
+                Ok(Some(x)) => String::from_utf8(x)
+                    .ok()
+                    .and_then(|i| i.parse::<u64>().ok()),
+                Ok(None) => None,
+                Err(why) => {
+                    error!("Error getting audit log data from redis: {}", why);
+                    None
+                }
+            };
+
+            if let Err(why) = redis
+                .set_and_expire_seconds(&key, count.to_string(), 3600)
+                .await
+            {
+                error!("Error sending audit log data to redis: {}", why);
+            }
+
+            let is_new = match cached_count {
+                Some(cached) => count > cached,
+                None => entry_age_ms(entry) <= MAX_ENTRY_AGE_MS,
+            };
+
+            if is_new {
+                let moderator =
+                    if let Some(x) = audit_logs.users.iter().find(|u| u.id == entry.user_id) {
+                        x.clone()
+                    } else {
+                        entry.user_id.to_user(ctx).await.ok()?
+                    };
+
+                return Some(Attribution {
+                    moderator,
+                    reason: entry.reason.clone(),
+                });
+            }
+        }
+
+        if attempt + 1 < ATTEMPTS {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+
+    None
+}
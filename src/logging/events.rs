@@ -36,6 +36,8 @@ impl RawEventHandler for RawHandler {
                     }
 
                     messages::log_message(Arc::clone(&ctx), &data).await;
+
+                    attachments::archive_attachments(Arc::clone(&ctx), message).await;
                 }
                 Event::MessageUpdate(data) => {
                     if data.guild_id.is_none() {
@@ -51,6 +53,8 @@ impl RawEventHandler for RawHandler {
                         return;
                     }
 
+                    messages::log_delete(Arc::clone(&ctx), &[data.message_id]).await;
+
                     senders::send_message_delete(&ctx, &data).await;
                 }
                 Event::MessageDeleteBulk(data) => {
@@ -58,6 +62,8 @@ impl RawEventHandler for RawHandler {
                         return;
                     }
 
+                    messages::log_delete(Arc::clone(&ctx), &data.ids).await;
+
                     senders::send_message_delete_bulk(&ctx, &data).await;
                 }
                 Event::GuildMemberAdd(data) => {
@@ -270,3 +270,23 @@ pub async fn log_edit(ctx: Arc<Context>, data: &MessageUpdateEvent) {
         error!("Error updating message from edit to database: {}", why);
     };
 }
+
+/// Marks the logged messages as deleted, so they can be searched for with the `history` command.
+pub async fn log_delete(ctx: Arc<Context>, message_ids: &[MessageId]) {
+    let message_ids = message_ids.iter().map(|i| i.0 as i64).collect::<Vec<i64>>();
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    if let Err(why) = sqlx::query!(
+        "UPDATE log_messages SET deleted_timestamp = now() WHERE id = ANY($1) AND deleted_timestamp IS NULL",
+        &message_ids
+    )
+    .execute(&pool)
+    .await
+    {
+        error!("Error marking deleted messages on database: {}", why);
+    };
+}
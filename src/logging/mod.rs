@@ -1,3 +1,5 @@
+pub mod attachments;
+pub mod audit_log;
 pub mod events;
 pub mod messages;
 pub mod senders;
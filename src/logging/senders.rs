@@ -1,7 +1,11 @@
-use crate::global_data::DatabasePool;
+use crate::global_data::{DatabasePool, Tokens};
+use crate::logging::{attachments, audit_log};
 use crate::utils::logging::{guild_has_logging, LoggingEvents};
 
+use std::borrow::Cow;
+
 use serenity::{
+    http::AttachmentType,
     model::{
         channel::{Channel, Embed, PermissionOverwriteType, ReactionType},
         event::*,
@@ -132,6 +136,63 @@ pub async fn send_message_delete(ctx: &Context, data: &MessageDeleteEvent) {
                 return;
             };
 
+            let attribution = audit_log::find_message_deleter(
+                ctx,
+                data.guild_id.unwrap(),
+                data.channel_id,
+                author.id.0,
+            )
+            .await;
+
+            let archive_config = {
+                let data_read = ctx.data.read().await;
+                data_read
+                    .get::<Tokens>()
+                    .unwrap()
+                    .attachment_archive
+                    .clone()
+            };
+
+            let archived = attachments::archived_attachments(&pool, data.message_id.0).await;
+
+            // Without a public url for the web server, the archived files get reuploaded to the
+            // webhook, as long as they fit on the upload limit.
+            let mut files = Vec::new();
+            let mut not_uploaded = Vec::new();
+
+            if archive_config.public_url.is_none() {
+                let mut total_size = 0;
+
+                for attachment in &archived {
+                    if total_size + attachment.size > 8_000_000 {
+                        not_uploaded.push(attachment.filename.to_string());
+                        continue;
+                    }
+
+                    match tokio::fs::read(attachments::archive_path(
+                        &archive_config,
+                        &attachment.hash,
+                    ))
+                    .await
+                    {
+                        Ok(data) => {
+                            total_size += attachment.size;
+                            files.push(AttachmentType::Bytes {
+                                data: Cow::from(data),
+                                filename: attachment.filename.to_string(),
+                            });
+                        }
+                        Err(why) => {
+                            error!(
+                                "Error reading archived attachment {}: {}",
+                                attachment.hash, why
+                            );
+                            not_uploaded.push(attachment.filename.to_string());
+                        }
+                    }
+                }
+            }
+
             let embed = Embed::fake(|e| {
                 e.title("Message Deleted");
                 e.description(format!(
@@ -161,9 +222,41 @@ pub async fn send_message_delete(ctx: &Context, data: &MessageDeleteEvent) {
                     e.field("Webhook ID", id, false);
                 }
 
-                if let Some(attachments) = &msg.attachments {
-                    for attachment in attachments {
-                        e.field("Attachment", attachment, false);
+                if let Some(attribution) = &attribution {
+                    attribution.add_fields(e);
+                }
+
+                if archived.is_empty() {
+                    if let Some(attachments) = &msg.attachments {
+                        for attachment in attachments {
+                            e.field("Attachment", attachment, false);
+                        }
+                    }
+                } else {
+                    for attachment in &archived {
+                        if let Some(url) = attachments::archive_url(&archive_config, attachment) {
+                            e.field(
+                                "Archived Attachment",
+                                format!("[{}]({})", attachment.filename, url),
+                                false,
+                            );
+                        }
+                    }
+
+                    if !files.is_empty() {
+                        e.field(
+                            "Archived Attachments",
+                            "Reuploaded with this message.",
+                            false,
+                        );
+                    }
+
+                    if !not_uploaded.is_empty() {
+                        e.field(
+                            "Archived Attachments Not Reuploaded",
+                            not_uploaded.join(", "),
+                            false,
+                        );
                     }
                 }
 
@@ -195,7 +288,10 @@ pub async fn send_message_delete(ctx: &Context, data: &MessageDeleteEvent) {
             match &ctx.http.get_webhook_with_token(id, token).await {
                 Ok(hook) => {
                     if let Err(why) = hook
-                        .execute(&ctx.http, false, |m| m.embeds(vec![embed]))
+                        .execute(&ctx.http, false, |m| {
+                            m.embeds(vec![embed]);
+                            m.add_files(files)
+                        })
                         .await
                     {
                         error!("Error Sending Hook: {}", why)
@@ -270,8 +366,16 @@ pub async fn send_guild_member_remove(ctx: &Context, data: &GuildMemberRemoveEve
     if let Some(channel_data) =
         guild_has_logging(&pool, LoggingEvents::GuildMemberRemove, data.guild_id).await
     {
+        let attribution =
+            audit_log::find_responsible(ctx, data.guild_id, audit_log::MEMBER_KICK, data.user.id.0)
+                .await;
+
         let embed = Embed::fake(|e| {
-            e.title("Member Left");
+            if attribution.is_some() {
+                e.title("Member Kicked");
+            } else {
+                e.title("Member Left");
+            }
             e.author(|a| {
                 a.icon_url(data.user.face());
                 a.name(data.user.tag())
@@ -279,6 +383,10 @@ pub async fn send_guild_member_remove(ctx: &Context, data: &GuildMemberRemoveEve
             e.field("Created at", &data.user.created_at().to_rfc2822(), false);
             e.field("ID", &data.user.id.0, false);
 
+            if let Some(attribution) = &attribution {
+                attribution.add_fields(e);
+            }
+
             e
         });
 
@@ -404,9 +512,17 @@ pub async fn send_guild_role_delete(ctx: &Context, data: &GuildRoleDeleteEvent)
     if let Some(channel_data) =
         guild_has_logging(&pool, LoggingEvents::GuildRoleDelete, data.guild_id).await
     {
+        let attribution =
+            audit_log::find_responsible(ctx, data.guild_id, audit_log::ROLE_DELETE, data.role_id.0)
+                .await;
+
         let embed = Embed::fake(|e| {
             e.title("Role Deleted");
             e.field("ID", &data.role_id.0, false);
+
+            if let Some(attribution) = &attribution {
+                attribution.add_fields(e);
+            }
             e.timestamp(&chrono::offset::Utc::now());
             e.footer(|f| f.text("Deleted"));
 
@@ -940,6 +1056,28 @@ pub async fn send_channel_delete(ctx: &Context, data: &ChannelDeleteEvent) {
         _ => return,
     };
 
+    let attribution = match &data.channel {
+        Channel::Guild(channel) => {
+            audit_log::find_responsible(
+                ctx,
+                channel.guild_id,
+                audit_log::CHANNEL_DELETE,
+                channel.id.0,
+            )
+            .await
+        }
+        Channel::Category(category) => {
+            audit_log::find_responsible(
+                ctx,
+                category.guild_id,
+                audit_log::CHANNEL_DELETE,
+                category.id.0,
+            )
+            .await
+        }
+        _ => None,
+    };
+
     let embed = match &data.channel {
         Channel::Guild(channel) => {
             let category_name = if let Some(category) = channel.category_id {
@@ -1015,6 +1153,10 @@ pub async fn send_channel_delete(ctx: &Context, data: &ChannelDeleteEvent) {
                     e.field("NSFW?", "Yes", false);
                 }
 
+                if let Some(attribution) = &attribution {
+                    attribution.add_fields(e);
+                }
+
                 e.timestamp(&chrono::offset::Utc::now());
                 e.footer(|f| f.text("Deleted"));
 
@@ -1077,6 +1219,10 @@ pub async fn send_channel_delete(ctx: &Context, data: &ChannelDeleteEvent) {
                 }
                 e.fields(fields);
 
+                if let Some(attribution) = &attribution {
+                    attribution.add_fields(e);
+                }
+
                 e.timestamp(&chrono::offset::Utc::now());
                 e.footer(|f| f.text("Deleted"));
 
@@ -1358,6 +1504,14 @@ pub async fn send_guild_ban_add(ctx: &Context, data: &GuildBanAddEvent) {
     if let Some(channel_data) =
         guild_has_logging(&pool, LoggingEvents::GuildBanAdd, data.guild_id).await
     {
+        let attribution = audit_log::find_responsible(
+            ctx,
+            data.guild_id,
+            audit_log::MEMBER_BAN_ADD,
+            data.user.id.0,
+        )
+        .await;
+
         let embed = Embed::fake(|e| {
             e.title("User Banned");
             e.field("ID", data.user.id.0, false);
@@ -1371,6 +1525,10 @@ pub async fn send_guild_ban_add(ctx: &Context, data: &GuildBanAddEvent) {
                 e.description("User is a BOT account.");
             }
 
+            if let Some(attribution) = &attribution {
+                attribution.add_fields(e);
+            }
+
             e.timestamp(&chrono::offset::Utc::now());
             e.footer(|f| f.text("Banned"));
 
@@ -1408,6 +1566,14 @@ pub async fn send_guild_ban_remove(ctx: &Context, data: &GuildBanRemoveEvent) {
     if let Some(channel_data) =
         guild_has_logging(&pool, LoggingEvents::GuildBanRemove, data.guild_id).await
     {
+        let attribution = audit_log::find_responsible(
+            ctx,
+            data.guild_id,
+            audit_log::MEMBER_BAN_REMOVE,
+            data.user.id.0,
+        )
+        .await;
+
         let embed = Embed::fake(|e| {
             e.title("User Unbanned");
             e.field("ID", data.user.id.0, false);
@@ -1421,6 +1587,10 @@ pub async fn send_guild_ban_remove(ctx: &Context, data: &GuildBanRemoveEvent) {
                 e.description("User is a BOT account.");
             }
 
+            if let Some(attribution) = &attribution {
+                attribution.add_fields(e);
+            }
+
             e.timestamp(&chrono::offset::Utc::now());
             e.footer(|f| f.text("Unbanned"));
 
@@ -32,9 +32,12 @@ use crate::framework_methods::*;
 use crate::global_data::*;
 
 use utils::database::*; // Obtain the get_database function from the utilities. // Obtain the capitalize_first function from the utilities.
+use utils::lavalink::LavalinkPool;
+use utils::music::AutoLeave;
+use utils::osu_api::OsuApi;
 
 use std::{
-    collections::HashSet, // Low cost indexable lists.
+    collections::{HashMap, HashSet}, // Low cost indexable lists.
     // For saving / reading files
     fs::File,
     io::prelude::*,
@@ -54,7 +57,7 @@ use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
 use lavalink_rs::LavalinkClient;
 use reqwest::header;
-use songbird::SerenityInit;
+use songbird::{SerenityInit, Songbird};
 
 // Serenity! what make's the bot function. Discord API wrapper.
 use serenity::{
@@ -176,7 +179,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .group(&META_GROUP) // Load `Meta` command group
         .group(&FUN_GROUP) // Load `Fun` command group
         .group(&MUSIC_GROUP) // Load `music` command group
+        .group(&PLAYLISTS_GROUP) // Load `playlists` command group
         .group(&MOD_GROUP) // Load `moderation` command group
+        .group(&OSUTRACKING_GROUP) // Load `osu! Tracking` command group
         .group(&OSU_GROUP) // Load `osu!` command group
         .group(&NEWOSU_GROUP) // Load `new osu!` command group
         .group(&SANKAKU_GROUP) // Load `SankakuComplex` command group
@@ -185,15 +190,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .group(&GAMES_GROUP) // Load `games` command group
         .group(&SERENITYDOCS_GROUP) // Load `serenity_docs` command group
         .group(&CONFIGURATION_GROUP) // Load `Configuration` command group
+        .group(&PRIVACY_GROUP) // Load `Privacy` command group
         .help(&MY_HELP); // Load the custom help command.
 
+    let songbird = Songbird::serenity();
+
     let mut client = ClientBuilder::new(&bot_token)
         .event_handler(Handler {
             run_loops: Mutex::new(true),
         })
         .raw_event_handler(logging::events::RawHandler)
         .framework(std_framework)
-        .register_songbird()
+        .register_songbird_with(Arc::clone(&songbird))
         .application_id(bot_id.0)
         .intents({
             let mut intents = GatewayIntents::all();
@@ -215,7 +223,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         data.insert::<DatabasePool>(pg_pool.clone());
 
         let redis_pool = obtain_redis_pool().await?;
-        data.insert::<CachePool>(redis_pool);
+        data.insert::<CachePool>(redis_pool.clone());
 
         // Add the shard manager to the data.
         data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
@@ -229,19 +237,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         data.insert::<Uptime>(Arc::new(Instant::now()));
 
         {
-            // T 0 D 0: get the real shard amount.
-            let host = configuration.lavalink.host;
-            let port = configuration.lavalink.port;
-            let password = configuration.lavalink.password;
-
-            let lava_client = LavalinkClient::builder(bot_id.0)
-                .set_host(host.to_string())
-                .set_password(password.to_string())
-                .set_port(port)
-                .build(LavalinkHandler)
-                .await?;
+            let shard_count = client.cache_and_http.http.get_bot_gateway().await?.shards;
+
+            let music_states = Arc::new(RwLock::new(HashMap::new()));
+            let lavalink = LavalinkPool::default();
+
+            let auto_leave = AutoLeave {
+                http: Arc::clone(&client.cache_and_http.http),
+                songbird: Arc::clone(&songbird),
+                pool: pg_pool.clone(),
+                redis_pool: redis_pool.clone(),
+                music_states: Arc::clone(&music_states),
+                lavalink: lavalink.clone(),
+                config: configuration.music.clone(),
+            };
+
+            // A node being down only makes the bot use the other ones.
+            for node in configuration.lavalink.all_nodes() {
+                let node_index = lavalink.node_count().await;
+
+                let lava_client = LavalinkClient::builder(bot_id.0)
+                    .set_host(node.host.to_string())
+                    .set_password(node.password.to_string())
+                    .set_port(node.port)
+                    .set_shard_count(shard_count)
+                    .build(LavalinkHandler {
+                        node_index,
+                        music_states: Arc::clone(&music_states),
+                        http: Arc::clone(&client.cache_and_http.http),
+                        auto_leave: auto_leave.clone(),
+                    })
+                    .await;
+
+                match lava_client {
+                    Ok(x) => lavalink.add_node(&node, x).await,
+                    Err(why) => error!(
+                        "Unable to connect to the lavalink node {}: {}",
+                        node.name, why
+                    ),
+                }
+            }
+
+            if lavalink.node_count().await == 0 {
+                return Err("Unable to connect to any lavalink node.".into());
+            }
 
-            data.insert::<Lavalink>(lava_client);
+            data.insert::<Lavalink>(lavalink);
+            data.insert::<MusicStates>(music_states);
         }
 
         {
@@ -318,7 +360,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 .default_headers(headers)
                 .build()?;
 
-            data.insert::<OsuHttpClient>(Arc::new(RwLock::new(client)));
+            data.insert::<OsuHttpClient>(OsuApi::new(client));
         }
     }
 
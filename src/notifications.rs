@@ -1,9 +1,16 @@
+use crate::logging::attachments::prune_archive;
 use crate::utils::booru::{SAFE_BANLIST, UNSAFE_BANLIST};
 
 use crate::global_data::*;
+use crate::utils::osu::{get_mods_short, mode_accuracy, mode_hits};
+use crate::utils::osu_api::{OsuApi, ScoreKind};
+use crate::utils::osu_model::{GameMode, Score};
 use crate::{OsuTokenRecv, OsuTokenSend};
 
+use sqlx::PgPool;
+
 use std::{
+    collections::HashSet,
     sync::Arc,
     //collections::HashMap,
     time::Duration,
@@ -12,9 +19,13 @@ use std::{
 use reqwest::{header::*, Client as ReqwestClient, Url};
 use serde::Deserialize;
 
+use num_format::{Locale, ToFormattedString};
+
 use serenity::{
+    builder::CreateEmbed,
     model::{channel::Embed, id::ChannelId},
     prelude::{Context, RwLock},
+    utils::Colour,
 };
 
 #[derive(Deserialize)]
@@ -64,13 +75,13 @@ async fn update_osu_token(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::E
         data_read.get::<Tokens>().unwrap().clone()
     };
 
-    let old_client = {
+    let api = {
         let data_read = ctx.data.read().await;
-        let client_lock = data_read.get::<OsuHttpClient>().unwrap().clone();
-        let client_read = client_lock.read().await;
-        client_read.clone()
+        data_read.get::<OsuHttpClient>().unwrap().clone()
     };
 
+    let old_client = api.http_client().await;
+
     let send_data = OsuTokenSend {
         client_id: configuration.osu.client_id,
         client_secret: configuration.osu.client_secret.to_string(),
@@ -98,12 +109,7 @@ async fn update_osu_token(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::E
         .default_headers(headers)
         .build()?;
 
-    {
-        let data_read = ctx.data.read().await;
-        let client_lock = data_read.get::<OsuHttpClient>().unwrap().clone();
-        let mut client_write = client_lock.write().await;
-        *client_write = client;
-    }
+    api.set_http_client(client).await;
 
     Ok(())
 }
@@ -489,37 +495,6 @@ async fn check_twitch_livestreams(ctx: Arc<Context>) -> Result<(), Box<dyn std::
     Ok(())
 }
 
-//async fn _check_empty_vc(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error>> {
-//    let manager_lock = ctx.data.read().await
-//        .get::<VoiceManager>().cloned().expect("Expected VoiceManager in ShareMap.");
-//    let user_id = ctx.cache.current_user().await.id;
-//
-//    for guild_id in &ctx.cache.guilds().await {
-//        let mut manager = manager_lock.lock().await;
-//        let has_handler = manager.get(guild_id).is_some();
-//
-//        if has_handler {
-//            let guild = ctx.cache.guild(guild_id).await.unwrap();
-//            if let Some(channel) = guild.voice_states.get(&user_id)
-//                .and_then(|v| v.channel_id) {
-//                    let guild_channel = ctx.cache.guild_channel(channel).await.unwrap();
-//
-//                    if let Ok(members) = guild_channel.members(&ctx).await {
-//                        if members.len() == 1 {
-//                            manager.remove(guild_id);
-//
-//                            let data = ctx.data.read().await;
-//                            let lava_client = data.get::<Lavalink>().expect("Expected a lavalink client in TypeMap");
-//                            lava_client.write().await.destroy(guild_id).await?;
-//                        }
-//                    }
-//            };
-//        }
-//    }
-//
-//    Ok(())
-//}
-
 async fn reminder_check(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error>> {
     let pool = {
         let data_read = ctx.data.read().await;
@@ -668,10 +643,320 @@ async fn unmute_check(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
+async fn prune_log_messages(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let deleted = sqlx::query!("DELETE FROM log_messages USING log_retention WHERE log_messages.guild_id = log_retention.guild_id AND log_messages.creation_timestamp < now() - make_interval(days => log_retention.days)")
+        .execute(&pool)
+        .await?
+        .rows_affected();
+
+    debug!("Pruned {} logged messages.", deleted);
+
+    let archive_config = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Tokens>()
+            .unwrap()
+            .attachment_archive
+            .clone()
+    };
+
+    if let Err(why) = prune_archive(&pool, &archive_config).await {
+        error!(
+            "An error occurred while pruning the attachment archive >>> {}",
+            why
+        );
+    }
+
+    Ok(())
+}
+
+// Builds the announcement of a new top play of a tracked player.
+fn tracked_play_embed(
+    mode: GameMode,
+    play: &Score,
+    position: usize,
+    rank_change: &str,
+) -> CreateEmbed {
+    let beatmap = &play.beatmap;
+    let beatmapset = &play.beatmapset;
+
+    let mods = get_mods_short(&play.mods);
+
+    let mut e = CreateEmbed::default();
+
+    e.color(Colour::new(play.user.id as u32));
+    e.author(|a| {
+        a.name(format!(
+            "New #{} top play from \"{}\"",
+            position, play.user.username
+        ));
+        a.icon_url(&play.user.avatar_url);
+        a.url(format!("https://osu.ppy.sh/u/{}", play.user.id))
+    });
+    e.title(format!(
+        "{} - {} [**{}**]\nby {}",
+        beatmapset.artist, beatmapset.title, beatmap.version, beatmapset.creator
+    ));
+    e.url(format!("https://osu.ppy.sh/b/{}", beatmap.id));
+    e.description(format!(
+        "**{:.2}pp** ┇ **+{}** ┇ **{}**\n**{}** ┇ **x{}** ┇ **{:.2}%**\n{}\n\n{}",
+        play.pp.unwrap_or_default(),
+        mods,
+        play.rank.to_uppercase(),
+        play.score.to_formatted_string(&Locale::en),
+        play.max_combo,
+        mode_accuracy(mode, &play.statistics),
+        mode_hits(mode, &play.statistics),
+        rank_change,
+    ));
+    e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", beatmapset.id));
+    e.timestamp(&play.created_at);
+    e.footer(|f| {
+        f.text(format!(
+            "{:.2}* | {}",
+            beatmap.difficulty_rating,
+            mode.display_name()
+        ));
+        f.icon_url(format!(
+            "https://s.ppy.sh/images/{}.png",
+            play.rank.to_uppercase()
+        ))
+    });
+
+    e
+}
+
+// The users saved on each run, so the snapshots don't take most of the 60 osu! requests per minute.
+const SNAPSHOT_BATCH_SIZE: i64 = 20;
+
+/// Saves the global rank and pp of the linked osu! users once a day, for the `osu history` graphs.
+async fn snapshot_osu_users(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+    let api = OsuApi::from_context(&ctx).await.background();
+
+    // Only the users without a snapshot of today, so the job can run often without repeating requests.
+    let users = sqlx::query!("SELECT DISTINCT u.osu_id, u.mode FROM osu_user u WHERE u.osu_id <> 0 AND NOT EXISTS (SELECT 1 FROM osu_user_snapshots s WHERE s.osu_id = u.osu_id AND s.day = CURRENT_DATE AND (u.mode IS NULL OR s.mode = u.mode)) LIMIT $1", SNAPSHOT_BATCH_SIZE)
+        .fetch_all(&pool)
+        .await?;
+
+    for linked_user in users {
+        let user = match api
+            .user_by_id(
+                linked_user.osu_id as u64,
+                linked_user.mode.map(GameMode::from_id),
+            )
+            .await
+        {
+            Ok(Some(x)) => x,
+            Ok(None) => {
+                // The deleted and restricted users are saved without pp, so they are skipped until tomorrow.
+                sqlx::query!(
+                    "INSERT INTO osu_user_snapshots (osu_id, mode, pp) VALUES ($1, $2, NULL) ON CONFLICT DO NOTHING",
+                    linked_user.osu_id as i64,
+                    linked_user.mode.unwrap_or_default(),
+                )
+                .execute(&pool)
+                .await?;
+                continue;
+            }
+            Err(why) => {
+                error!(
+                    "Error obtaining the osu! user {}: {}",
+                    linked_user.osu_id, why
+                );
+                continue;
+            }
+        };
+
+        let mode = linked_user
+            .mode
+            .map(GameMode::from_id)
+            .unwrap_or(user.playmode);
+
+        sqlx::query!(
+            "INSERT INTO osu_user_snapshots (osu_id, mode, global_rank, pp) VALUES ($1, $2, $3, $4) ON CONFLICT (osu_id, mode, day) DO UPDATE SET global_rank = $3, pp = $4",
+            user.id as i64,
+            mode.id(),
+            user.statistics.global_rank.map(|i| i as i64),
+            user.statistics.pp,
+        )
+        .execute(&pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// The players checked on each run, so the tracking only uses a small part of the osu! requests.
+const TRACKING_BATCH_SIZE: i64 = 10;
+
+async fn check_osu_top_plays(ctx: Arc<Context>) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+    let api = OsuApi::from_context(&ctx).await.background();
+
+    let players = sqlx::query_as!(
+        TrackedPlayer,
+        "SELECT osu_id, mode, osu_username, global_rank, pp FROM osu_tracked_players ORDER BY last_checked LIMIT $1",
+        TRACKING_BATCH_SIZE
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    // An error with a player doesn't stop the check of the rest of them.
+    for player in players {
+        sqlx::query!(
+            "UPDATE osu_tracked_players SET last_checked = now() WHERE osu_id = $1 AND mode = $2",
+            player.osu_id,
+            player.mode
+        )
+        .execute(&pool)
+        .await?;
+
+        if let Err(why) = check_player_top_plays(&ctx, &pool, &api, &player).await {
+            error!(
+                "Error checking the top plays of {}: {}",
+                player.osu_username, why
+            );
+        }
+    }
+
+    Ok(())
+}
+
+struct TrackedPlayer {
+    osu_id: i64,
+    mode: i32,
+    osu_username: String,
+    global_rank: Option<i64>,
+    pp: f64,
+}
+
+// Announces the new top plays of a tracked player on the channels that track them.
+async fn check_player_top_plays(
+    ctx: &Context,
+    pool: &PgPool,
+    api: &OsuApi,
+    player: &TrackedPlayer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = GameMode::from_id(player.mode);
+
+    let top_plays = api
+        .user_scores(player.osu_id as u64, ScoreKind::Best, Some(mode), 100)
+        .await?;
+
+    let ids = top_plays.iter().map(|i| i.id as i64).collect::<Vec<i64>>();
+
+    let seen = sqlx::query!(
+        "SELECT score_id FROM osu_tracked_scores WHERE mode = $1 AND score_id = ANY($2)",
+        player.mode,
+        &ids
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|i| i.score_id)
+    .collect::<HashSet<i64>>();
+
+    let new_plays = top_plays
+        .iter()
+        .enumerate()
+        .filter(|(_, play)| !seen.contains(&(play.id as i64)))
+        .collect::<Vec<(usize, &Score)>>();
+
+    if new_plays.is_empty() {
+        return Ok(());
+    }
+
+    // Marked as seen before announcing them, so an error doesn't announce them twice.
+    sqlx::query!(
+        "INSERT INTO osu_tracked_scores (osu_id, mode, score_id) SELECT $1, $2, * FROM UNNEST($3::bigint[]) ON CONFLICT DO NOTHING",
+        player.osu_id,
+        player.mode,
+        &new_plays.iter().map(|(_, i)| i.id as i64).collect::<Vec<i64>>(),
+    )
+    .execute(pool)
+    .await?;
+
+    let rank_change = if let Some(user) = api.user_by_id(player.osu_id as u64, Some(mode)).await? {
+        let new_rank = user.statistics.global_rank.map(|i| i as i64);
+
+        sqlx::query!(
+            "UPDATE osu_tracked_players SET osu_username = $3, global_rank = $4, pp = $5 WHERE osu_id = $1 AND mode = $2",
+            player.osu_id,
+            player.mode,
+            &user.username,
+            new_rank,
+            user.statistics.pp,
+        )
+        .execute(pool)
+        .await?;
+
+        let rank = match (player.global_rank, new_rank) {
+            (Some(old), Some(new)) if old != new => format!(
+                "#{} → #{} ({:+})",
+                old.to_formatted_string(&Locale::en),
+                new.to_formatted_string(&Locale::en),
+                old - new
+            ),
+            (_, Some(new)) => format!("#{}", new.to_formatted_string(&Locale::en)),
+            (_, None) => "Unranked".to_string(),
+        };
+
+        format!(
+            "{} ┇ {:.2}pp ({:+.2}pp)",
+            rank,
+            user.statistics.pp,
+            user.statistics.pp - player.pp
+        )
+    } else {
+        String::new()
+    };
+
+    let channels = sqlx::query!(
+        "SELECT channel_id FROM osu_tracked_channels WHERE osu_id = $1 AND mode = $2",
+        player.osu_id,
+        player.mode
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for (index, play) in new_plays {
+        let embed = tracked_play_embed(mode, play, index + 1, &rank_change);
+
+        for channel in &channels {
+            if let Err(why) = ChannelId(channel.channel_id as u64)
+                .send_message(ctx, |m| m.set_embed(embed.clone()))
+                .await
+            {
+                warn!(
+                    "Unable to announce a top play on {}: {}",
+                    channel.channel_id, why
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn notification_loop(ctx: Arc<Context>) {
     let ctx = Arc::clone(&ctx);
     let ctx_clone = Arc::clone(&ctx);
     let ctx_clone_clone = Arc::clone(&ctx);
+    let ctx_clone_prune = Arc::clone(&ctx);
+    let ctx_clone_osu = Arc::clone(&ctx);
+    let ctx_clone_osu_history = Arc::clone(&ctx);
 
     tokio::spawn(async move {
         loop {
@@ -698,13 +983,6 @@ pub async fn notification_loop(ctx: Arc<Context>) {
                 }
             });
 
-            //let ctx3 = Arc::clone(&ctx);
-            //tokio::spawn(async move {
-            //    if let Err(why) = check_empty_vc(Arc::clone(&ctx3)).await {
-            //        error!("check_empty_vc :: {}", why);
-            //        eprintln!("An error occurred while running check_empty_vc() >>> {}", why);
-            //    }
-            //});
             debug!("Notification loop finished.");
 
             tokio::time::sleep(Duration::from_secs(120)).await;
@@ -750,4 +1028,52 @@ pub async fn notification_loop(ctx: Arc<Context>) {
             tokio::time::sleep(Duration::from_secs(21600)).await;
         }
     });
+
+    tokio::spawn(async move {
+        loop {
+            let ctx = Arc::clone(&ctx_clone_prune);
+
+            if let Err(why) = prune_log_messages(ctx).await {
+                error!(
+                    "An error occurred while running prune_log_messages() >>> {}",
+                    why
+                );
+            }
+
+            // once an hour
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let ctx = Arc::clone(&ctx_clone_osu);
+
+            if let Err(why) = check_osu_top_plays(ctx).await {
+                error!(
+                    "An error occurred while running check_osu_top_plays() >>> {}",
+                    why
+                );
+            }
+
+            // every 5 minutes, a batch of the players checked the longest ago
+            tokio::time::sleep(Duration::from_secs(300)).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let ctx = Arc::clone(&ctx_clone_osu_history);
+
+            if let Err(why) = snapshot_osu_users(ctx).await {
+                error!(
+                    "An error occurred while running snapshot_osu_users() >>> {}",
+                    why
+                );
+            }
+
+            // every 5 minutes, a batch of the users not saved today
+            tokio::time::sleep(Duration::from_secs(300)).await;
+        }
+    });
 }
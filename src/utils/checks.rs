@@ -1,3 +1,6 @@
+use crate::global_data::DatabasePool;
+use crate::utils::music::MusicPermissions;
+
 use serenity::{
     framework::standard::{macros::check, Reason},
     model::channel::Message,
@@ -50,3 +53,22 @@ pub async fn bot_has_manage_roles_check(ctx: &Context, msg: &Message) -> Result<
         Ok(())
     }
 }
+
+#[check]
+#[name = "dj"]
+pub async fn dj_check(ctx: &Context, msg: &Message) -> Result<(), Reason> {
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    let permissions = MusicPermissions::load(&pool, msg.guild_id.unwrap().0).await;
+
+    if permissions.can_control(ctx, msg).await {
+        Ok(())
+    } else {
+        Err(Reason::User(
+            "Only DJs and whoever requested the current track can do that.".to_string(),
+        ))
+    }
+}
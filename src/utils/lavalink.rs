@@ -0,0 +1,277 @@
+use crate::config::LavalinkNodeConfig;
+use crate::utils::music::{AutoLeave, QueueSnapshot};
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use lavalink_rs::{model::Stats, LavalinkClient};
+use tokio::sync::RwLock;
+
+// Lavalink sends the stats of the node every minute,
+// so a node that hasn't sent them for longer is considered disconnected.
+const STATS_TIMEOUT: Duration = Duration::from_secs(90);
+// How often the players of disconnected nodes are looked for.
+const FAILOVER_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Node {
+    name: String,
+    regions: Vec<String>,
+    client: LavalinkClient,
+    /// The players the node reported on the last stats.
+    playing_players: usize,
+    /// The penalty for the cpu load of the node, from the last stats.
+    cpu_penalty: f64,
+    last_stats: Instant,
+    disconnected: bool,
+}
+
+impl Node {
+    fn is_healthy(&self) -> bool {
+        self.last_stats.elapsed() < STATS_TIMEOUT
+    }
+
+    fn serves_region(&self, region: Option<&str>) -> bool {
+        region
+            .map(|region| self.regions.iter().any(|i| i.eq_ignore_ascii_case(region)))
+            .unwrap_or(false)
+    }
+}
+
+/// The node a guild is playing on.
+struct Player {
+    node: usize,
+    region: Option<String>,
+}
+
+/// All the configured lavalink nodes, and which node each guild is playing on.
+#[derive(Clone, Default)]
+pub struct LavalinkPool {
+    nodes: Arc<RwLock<Vec<Node>>>,
+    players: Arc<RwLock<HashMap<u64, Player>>>,
+}
+
+impl LavalinkPool {
+    pub async fn add_node(&self, config: &LavalinkNodeConfig, client: LavalinkClient) {
+        self.nodes.write().await.push(Node {
+            name: config.name.clone(),
+            regions: config.regions.clone(),
+            client,
+            playing_players: 0,
+            cpu_penalty: 0.0,
+            // Nodes are considered healthy until they had the time to send their first stats.
+            last_stats: Instant::now(),
+            disconnected: false,
+        });
+    }
+
+    pub async fn node_count(&self) -> usize {
+        self.nodes.read().await.len()
+    }
+
+    /// Records the stats a node sent, which are used for load balancing and health checks.
+    pub async fn update_stats(&self, index: usize, stats: &Stats) {
+        let mut nodes = self.nodes.write().await;
+
+        if let Some(node) = nodes.get_mut(index) {
+            if node.disconnected {
+                info!("Lavalink node {} is available again", node.name);
+                node.disconnected = false;
+            }
+
+            // The same penalty lavalink clients usually use, it grows exponentially with the load.
+            let load = stats.cpu.system_load / (stats.cpu.cores as f64).max(1.0);
+
+            node.playing_players = stats.playing_players as usize;
+            node.cpu_penalty = 1.05f64.powf(100.0 * load) * 10.0 - 10.0;
+            node.last_stats = Instant::now();
+        }
+    }
+
+    /// Picks the healthy node with the lowest load, preferring the nodes tagged with the region.
+    async fn best_node(&self, region: Option<&str>) -> Option<usize> {
+        let nodes = self.nodes.read().await;
+        let players = self.players.read().await;
+
+        nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.is_healthy())
+            .map(|(index, node)| {
+                // Players that were just assigned are not on the stats yet.
+                let assigned = players.values().filter(|i| i.node == index).count();
+                let penalty = node.playing_players.max(assigned) as f64 + node.cpu_penalty;
+
+                (index, node.serves_region(region), penalty)
+            })
+            .min_by(|a, b| {
+                b.1.cmp(&a.1)
+                    .then(a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal))
+            })
+            .map(|(index, _, _)| index)
+    }
+
+    async fn node_client(&self, index: usize) -> LavalinkClient {
+        self.nodes.read().await[index].client.clone()
+    }
+
+    /// The client of the node a guild is playing on.
+    ///
+    /// Guilds without a player get the best node, without assigning it to them.
+    pub async fn client(&self, guild_id: u64) -> LavalinkClient {
+        let assigned = self.players.read().await.get(&guild_id).map(|i| i.node);
+
+        let index = match assigned {
+            Some(x) => x,
+            None => self.best_node(None).await.unwrap_or(0),
+        };
+
+        self.node_client(index).await
+    }
+
+    /// Assigns the player of a guild to the best node for the voice region,
+    /// keeping the current node if it's still healthy.
+    pub async fn assign(&self, guild_id: u64, region: Option<&str>) -> LavalinkClient {
+        let assigned = self.players.read().await.get(&guild_id).map(|i| i.node);
+
+        if let Some(index) = assigned {
+            if self.nodes.read().await[index].is_healthy() {
+                return self.node_client(index).await;
+            }
+        }
+
+        let index = self.best_node(region).await.unwrap_or(0);
+
+        self.players.write().await.insert(
+            guild_id,
+            Player {
+                node: index,
+                region: region.map(|i| i.to_string()),
+            },
+        );
+
+        self.node_client(index).await
+    }
+
+    /// Forgets the node of a guild, once its player has been destroyed.
+    pub async fn release(&self, guild_id: u64) {
+        self.players.write().await.remove(&guild_id);
+    }
+
+    /// The guilds playing on nodes that stopped responding, with the node and their region.
+    async fn stranded_players(&self) -> Vec<(u64, usize, Option<String>)> {
+        let mut nodes = self.nodes.write().await;
+
+        for node in nodes.iter_mut() {
+            if !node.is_healthy() && !node.disconnected {
+                warn!(
+                    "Lavalink node {} stopped responding, moving its players",
+                    node.name
+                );
+                node.disconnected = true;
+            }
+        }
+
+        self.players
+            .read()
+            .await
+            .iter()
+            .filter(|(_, player)| nodes[player.node].disconnected)
+            .map(|(guild_id, player)| (*guild_id, player.node, player.region.clone()))
+            .collect()
+    }
+}
+
+/// Moves the players of the nodes that stopped responding to a healthy node, periodically.
+pub async fn failover_loop(auto_leave: AutoLeave) {
+    loop {
+        tokio::time::sleep(FAILOVER_INTERVAL).await;
+
+        for (guild_id, from, region) in auto_leave.lavalink.stranded_players().await {
+            let to = if let Some(x) = auto_leave.lavalink.best_node(region.as_deref()).await {
+                x
+            } else {
+                warn!("There are no healthy lavalink nodes to move the players to");
+                break;
+            };
+
+            if let Err(why) = migrate_player(&auto_leave, guild_id, from, to, region).await {
+                error!(
+                    "Error moving the player of guild {} to another node: {}",
+                    guild_id, why
+                );
+            }
+        }
+    }
+}
+
+/// Moves the player of a guild to another node, resuming the queue where it was.
+async fn migrate_player(
+    auto_leave: &AutoLeave,
+    guild_id: u64,
+    from: usize,
+    to: usize,
+    region: Option<String>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let old_client = auto_leave.lavalink.node_client(from).await;
+    let new_client = auto_leave.lavalink.node_client(to).await;
+
+    // The old node keeps the queue on memory, even if it can no longer play it.
+    let snapshot = QueueSnapshot::take(
+        &auto_leave.songbird,
+        &old_client,
+        &auto_leave.music_states,
+        guild_id,
+    )
+    .await;
+
+    old_client.nodes().await.remove(&guild_id);
+    old_client.loops().await.remove(&guild_id);
+
+    let snapshot = if let Some(x) = snapshot {
+        x
+    } else {
+        // The bot is no longer on a voice channel there.
+        auto_leave.lavalink.release(guild_id).await;
+        return Ok(());
+    };
+
+    let connection_info = {
+        let call = auto_leave
+            .songbird
+            .get(guild_id)
+            .ok_or("The voice connection was lost")?;
+        let call = call.lock().await;
+        call.current_connection()
+            .cloned()
+            .ok_or("The voice connection is not ready")?
+    };
+
+    auto_leave
+        .lavalink
+        .players
+        .write()
+        .await
+        .insert(guild_id, Player { node: to, region });
+
+    // The new player starts with the default volume and no filters.
+    if let Some(state) = auto_leave.music_states.write().await.get_mut(&guild_id) {
+        state.audio_applied = false;
+    }
+
+    new_client.create_session(&connection_info).await?;
+    snapshot
+        .enqueue(&new_client, &auto_leave.music_states)
+        .await?;
+
+    info!(
+        "Moved {} queued tracks of guild {} to another lavalink node",
+        snapshot.tracks.len(),
+        guild_id
+    );
+
+    Ok(())
+}
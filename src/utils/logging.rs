@@ -36,18 +36,112 @@ bitflags! {
     }
 }
 
+/// Every loggable event, paired with the name users can refer to it by.
+pub const LOGGING_EVENT_NAMES: [(&str, LoggingEvents); 24] = [
+    ("ChannelCreate", LoggingEvents::ChannelCreate),
+    ("ChannelDelete", LoggingEvents::ChannelDelete),
+    ("ChannelPinsUpdate", LoggingEvents::ChannelPinsUpdate),
+    ("ChannelUpdate", LoggingEvents::ChannelUpdate),
+    ("GuildBanAdd", LoggingEvents::GuildBanAdd),
+    ("GuildBanRemove", LoggingEvents::GuildBanRemove),
+    ("GuildEmojisUpdate", LoggingEvents::GuildEmojisUpdate),
+    (
+        "GuildIntegrationsUpdate",
+        LoggingEvents::GuildIntegrationsUpdate,
+    ),
+    ("GuildMemberAdd", LoggingEvents::GuildMemberAdd),
+    ("GuildMemberRemove", LoggingEvents::GuildMemberRemove),
+    ("GuildMemberUpdate", LoggingEvents::GuildMemberUpdate),
+    ("GuildRoleCreate", LoggingEvents::GuildRoleCreate),
+    ("GuildRoleDelete", LoggingEvents::GuildRoleDelete),
+    ("GuildRoleUpdate", LoggingEvents::GuildRoleUpdate),
+    ("GuildUpdate", LoggingEvents::GuildUpdate),
+    ("MessageDelete", LoggingEvents::MessageDelete),
+    ("MessageDeleteBulk", LoggingEvents::MessageDeleteBulk),
+    ("MessageUpdate", LoggingEvents::MessageUpdate),
+    ("ReactionAdd", LoggingEvents::ReactionAdd),
+    ("ReactionRemove", LoggingEvents::ReactionRemove),
+    ("ReactionRemoveAll", LoggingEvents::ReactionRemoveAll),
+    ("VoiceStateUpdate", LoggingEvents::VoiceStateUpdate),
+    ("VoiceServerUpdate", LoggingEvents::VoiceServerUpdate),
+    ("WebhookUpdate", LoggingEvents::WebhookUpdate),
+];
+
+impl LoggingEvents {
+    /// Obtains the events of a named category, like `messages` or `roles`.
+    pub fn from_category(name: &str) -> Option<Self> {
+        let events = match name.to_lowercase().as_str() {
+            "messages" | "message" => {
+                Self::MessageDelete | Self::MessageDeleteBulk | Self::MessageUpdate
+            }
+            "members" | "member" => {
+                Self::GuildMemberAdd
+                    | Self::GuildMemberRemove
+                    | Self::GuildMemberUpdate
+                    | Self::GuildBanAdd
+                    | Self::GuildBanRemove
+            }
+            "roles" | "role" => {
+                Self::GuildRoleCreate | Self::GuildRoleDelete | Self::GuildRoleUpdate
+            }
+            "channels" | "channel" => {
+                Self::ChannelCreate
+                    | Self::ChannelDelete
+                    | Self::ChannelUpdate
+                    | Self::ChannelPinsUpdate
+                    | Self::WebhookUpdate
+            }
+            "reactions" | "reaction" => {
+                Self::ReactionAdd | Self::ReactionRemove | Self::ReactionRemoveAll
+            }
+            "voice" => Self::VoiceStateUpdate | Self::VoiceServerUpdate,
+            "guild" | "server" => {
+                Self::GuildUpdate | Self::GuildEmojisUpdate | Self::GuildIntegrationsUpdate
+            }
+            "all" | "everything" => Self::all(),
+            _ => return None,
+        };
+
+        Some(events)
+    }
+
+    /// Obtains a single event from it's name, case and `_` insensitive.
+    /// `message_delete`, `messagedelete` and `MessageDelete` are all the same event.
+    pub fn from_event_name(name: &str) -> Option<Self> {
+        let name = name.replace(&['_', '-'][..], "").to_lowercase();
+
+        LOGGING_EVENT_NAMES
+            .iter()
+            .find(|(event_name, _)| event_name.to_lowercase() == name)
+            .map(|(_, event)| *event)
+    }
+
+    /// Obtains events from either a category or an event name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::from_category(name).or_else(|| Self::from_event_name(name))
+    }
+
+    /// The names of all the events contained.
+    pub fn names(self) -> Vec<&'static str> {
+        LOGGING_EVENT_NAMES
+            .iter()
+            .filter(|(_, event)| self.contains(*event))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
 pub struct LoggingChannels {
     pub guild_id: i64,
     pub bitwise: i64,
     pub webhook_url: String,
 }
 
-pub async fn guild_has_logging(
+pub async fn guild_logging_channel(
     pool: &PgPool,
-    event: LoggingEvents,
     guild_id: impl Into<GuildId>,
 ) -> Option<LoggingChannels> {
-    let query = match sqlx::query_as!(
+    match sqlx::query_as!(
         LoggingChannels,
         "SELECT * FROM logging_channels WHERE guild_id = $1",
         guild_id.into().0 as i64
@@ -58,9 +152,17 @@ pub async fn guild_has_logging(
         Ok(x) => x,
         Err(why) => {
             error!("Error quering Database: {}", why);
-            return None;
+            None
         }
-    }?;
+    }
+}
+
+pub async fn guild_has_logging(
+    pool: &PgPool,
+    event: LoggingEvents,
+    guild_id: impl Into<GuildId>,
+) -> Option<LoggingChannels> {
+    let query = guild_logging_channel(pool, guild_id).await?;
 
     let log_events = LoggingEvents::from_bits_truncate(query.bitwise as u64);
     if log_events.contains(event) {
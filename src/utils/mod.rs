@@ -2,6 +2,12 @@ pub mod basic_functions;
 pub mod booru;
 pub mod checks;
 pub mod database;
+pub mod lavalink;
 pub mod logging;
+pub mod music;
 pub mod osu;
+pub mod osu_api;
+pub mod osu_images;
 pub mod osu_model;
+pub mod osu_preview;
+pub mod osu_replay;
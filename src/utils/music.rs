@@ -0,0 +1,1058 @@
+use crate::config::MusicConfig;
+use crate::global_data::{CachePool, DatabasePool, Lavalink, MusicStates, Tokens};
+use crate::utils::lavalink::LavalinkPool;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use darkredis::ConnectionPool as RedisPool;
+use lavalink_rs::{
+    model::{Band, Filters, Karaoke, Rotation, Timescale, Track, TrackQueue, Tremolo, Vibrato},
+    LavalinkClient,
+};
+use songbird::Songbird;
+use sqlx::PgPool;
+use tokio::sync::RwLock;
+
+use serenity::{
+    http::Http,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, RoleId, UserId},
+    },
+    prelude::Context,
+};
+
+/// What happens to a track once it finishes playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopMode {
+    Off,
+    Track,
+    Queue,
+}
+
+impl Default for LoopMode {
+    fn default() -> Self {
+        LoopMode::Off
+    }
+}
+
+impl fmt::Display for LoopMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopMode::Off => write!(f, "Off"),
+            LoopMode::Track => write!(f, "Track"),
+            LoopMode::Queue => write!(f, "Queue"),
+        }
+    }
+}
+
+impl LoopMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" | "none" | "disable" => Some(LoopMode::Off),
+            "track" | "song" | "one" | "single" => Some(LoopMode::Track),
+            "queue" | "all" | "playlist" => Some(LoopMode::Queue),
+            _ => None,
+        }
+    }
+}
+
+/// The music state of a guild that is not kept by the lavalink node.
+#[derive(Debug, Clone, Default)]
+pub struct GuildMusicState {
+    pub loop_mode: LoopMode,
+    /// The track that is currently playing, as the node forgets it before the track end event.
+    pub current: Option<TrackQueue>,
+    /// The channel each queued track was requested from, by encoded track.
+    pub request_channels: HashMap<String, u64>,
+    /// The last channel a track was requested from.
+    pub last_request_channel: Option<u64>,
+    /// The last position reported by the node, and when it was received.
+    pub position: Option<(u64, Instant)>,
+    /// Since when the bot is alone on the voice channel.
+    pub alone_since: Option<Instant>,
+    /// Since when the queue is empty and nothing is playing.
+    pub idle_since: Option<Instant>,
+    /// If the saved volume and filters have been sent to the current player.
+    pub audio_applied: bool,
+    /// The listeners that voted to skip the current track.
+    pub skip_votes: HashSet<u64>,
+    /// The listeners that voted to stop the player.
+    pub stop_votes: HashSet<u64>,
+}
+
+impl GuildMusicState {
+    /// The channel to send announcements about a track to.
+    pub fn request_channel(&self, track: &str) -> Option<u64> {
+        self.request_channels
+            .get(track)
+            .copied()
+            .or(self.last_request_channel)
+    }
+
+    /// Records the channel a track was requested from.
+    pub fn set_request_channel(&mut self, track: &str, channel_id: u64) {
+        self.request_channels.insert(track.to_string(), channel_id);
+        self.last_request_channel = Some(channel_id);
+    }
+
+    /// The estimated position in milliseconds of the current track.
+    /// The node only reports the position every few seconds, so the time since then is added.
+    pub fn current_position(&self, is_paused: bool) -> Option<u64> {
+        self.position.map(|(position, received)| {
+            if is_paused {
+                position
+            } else {
+                position + received.elapsed().as_millis() as u64
+            }
+        })
+    }
+}
+
+pub type MusicStateMap = Arc<RwLock<HashMap<u64, GuildMusicState>>>;
+
+/// The named audio filters that can be applied to the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterPreset {
+    Off,
+    Nightcore,
+    Vaporwave,
+    Bassboost,
+    EightD,
+    Karaoke,
+    Tremolo,
+    Vibrato,
+}
+
+pub const FILTER_PRESETS: [FilterPreset; 8] = [
+    FilterPreset::Off,
+    FilterPreset::Nightcore,
+    FilterPreset::Vaporwave,
+    FilterPreset::Bassboost,
+    FilterPreset::EightD,
+    FilterPreset::Karaoke,
+    FilterPreset::Tremolo,
+    FilterPreset::Vibrato,
+];
+
+impl Default for FilterPreset {
+    fn default() -> Self {
+        FilterPreset::Off
+    }
+}
+
+impl fmt::Display for FilterPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FilterPreset {
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterPreset::Off => "off",
+            FilterPreset::Nightcore => "nightcore",
+            FilterPreset::Vaporwave => "vaporwave",
+            FilterPreset::Bassboost => "bassboost",
+            FilterPreset::EightD => "8d",
+            FilterPreset::Karaoke => "karaoke",
+            FilterPreset::Tremolo => "tremolo",
+            FilterPreset::Vibrato => "vibrato",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name
+            .to_lowercase()
+            .replace(&['-', '_', ' '][..], "")
+            .as_str()
+        {
+            "off" | "none" | "reset" | "clear" => Some(FilterPreset::Off),
+            "nightcore" | "nc" => Some(FilterPreset::Nightcore),
+            "vaporwave" | "vw" | "slowed" => Some(FilterPreset::Vaporwave),
+            "bassboost" | "bass" | "bb" => Some(FilterPreset::Bassboost),
+            "8d" | "eightd" | "rotation" => Some(FilterPreset::EightD),
+            "karaoke" => Some(FilterPreset::Karaoke),
+            "tremolo" => Some(FilterPreset::Tremolo),
+            "vibrato" => Some(FilterPreset::Vibrato),
+            _ => None,
+        }
+    }
+
+    /// The lavalink filters of the preset, on top of the equalizer gains.
+    pub fn filters(self, equalizer: &[f64; 15]) -> Filters {
+        let mut gains = *equalizer;
+
+        if self == FilterPreset::Bassboost {
+            for (gain, boost) in gains.iter_mut().zip(&[0.2, 0.15, 0.1, 0.05]) {
+                *gain = (*gain + boost).min(1.0);
+            }
+        }
+
+        let mut filters = Filters {
+            equalizer: Some(
+                gains
+                    .iter()
+                    .enumerate()
+                    .map(|(band, gain)| Band {
+                        band: band as u8,
+                        gain: *gain,
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+
+        match self {
+            FilterPreset::Nightcore => {
+                filters.timescale = Some(Timescale {
+                    speed: 1.2,
+                    pitch: 1.2,
+                    rate: 1.0,
+                })
+            }
+            FilterPreset::Vaporwave => {
+                filters.timescale = Some(Timescale {
+                    speed: 0.85,
+                    pitch: 0.8,
+                    rate: 1.0,
+                })
+            }
+            FilterPreset::EightD => filters.rotation = Some(Rotation { rotation_hz: 0.2 }),
+            FilterPreset::Karaoke => {
+                filters.karaoke = Some(Karaoke {
+                    level: 1.0,
+                    mono_level: 1.0,
+                    filter_band: 220.0,
+                    filter_width: 100.0,
+                })
+            }
+            FilterPreset::Tremolo => {
+                filters.tremolo = Some(Tremolo {
+                    frequency: 4.0,
+                    depth: 0.75,
+                })
+            }
+            FilterPreset::Vibrato => {
+                filters.vibrato = Some(Vibrato {
+                    frequency: 4.0,
+                    depth: 0.75,
+                })
+            }
+            FilterPreset::Off | FilterPreset::Bassboost => (),
+        }
+
+        filters
+    }
+}
+
+/// The volume and filters of a guild, which are kept between sessions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioSettings {
+    pub volume: u16,
+    pub equalizer: [f64; 15],
+    pub filter: FilterPreset,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            volume: 100,
+            equalizer: [0.0; 15],
+            filter: FilterPreset::Off,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub async fn load(pool: &PgPool, guild_id: u64) -> Self {
+        let row = match sqlx::query!(
+            "SELECT volume, equalizer, filter FROM music_settings WHERE guild_id = $1",
+            guild_id as i64
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(Some(x)) => x,
+            Ok(None) => return Self::default(),
+            Err(why) => {
+                error!("Error quering Database: {}", why);
+                return Self::default();
+            }
+        };
+
+        let mut equalizer = [0.0; 15];
+        if let Some(gains) = row.equalizer {
+            for (band, gain) in equalizer.iter_mut().zip(gains) {
+                *band = gain;
+            }
+        }
+
+        Self {
+            volume: row.volume.max(0).min(1000) as u16,
+            equalizer,
+            filter: row
+                .filter
+                .and_then(|i| FilterPreset::from_name(&i))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub async fn save(&self, pool: &PgPool, guild_id: u64) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO music_settings (guild_id, volume, equalizer, filter) VALUES ($1, $2, $3, $4) ON CONFLICT (guild_id) DO UPDATE SET volume = $2, equalizer = $3, filter = $4",
+            guild_id as i64,
+            self.volume as i32,
+            &self.equalizer[..],
+            self.filter.name(),
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sends the volume and filters to the player of the guild.
+    pub async fn apply(
+        &self,
+        lava_client: &LavalinkClient,
+        guild_id: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        lava_client.volume(GuildId(guild_id), self.volume).await?;
+        lava_client
+            .filters(GuildId(guild_id), self.filter.filters(&self.equalizer))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Who can control the playback of a guild, and how much can be queued.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicPermissions {
+    /// Without a DJ role, everyone can control the playback.
+    pub dj_role: Option<u64>,
+    /// The percentage of listeners that need to vote to skip or stop.
+    pub vote_ratio: u8,
+    pub max_user_tracks: Option<usize>,
+    /// The max length of a track in milliseconds.
+    pub max_track_length: Option<u64>,
+}
+
+impl Default for MusicPermissions {
+    fn default() -> Self {
+        Self {
+            dj_role: None,
+            vote_ratio: 50,
+            max_user_tracks: None,
+            max_track_length: None,
+        }
+    }
+}
+
+impl MusicPermissions {
+    pub async fn load(pool: &PgPool, guild_id: u64) -> Self {
+        match sqlx::query!(
+            "SELECT dj_role, vote_ratio, max_user_tracks, max_track_length FROM music_settings WHERE guild_id = $1",
+            guild_id as i64
+        )
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(Some(row)) => Self {
+                dj_role: row.dj_role.map(|i| i as u64),
+                vote_ratio: row.vote_ratio.max(1).min(100) as u8,
+                max_user_tracks: row.max_user_tracks.map(|i| i.max(0) as usize),
+                max_track_length: row.max_track_length.map(|i| i.max(0) as u64 * 1000),
+            },
+            Ok(None) => Self::default(),
+            Err(why) => {
+                error!("Error quering Database: {}", why);
+                Self::default()
+            }
+        }
+    }
+
+    /// Checks if the author of a message is a DJ, having the DJ role or the `Manage Server` permission.
+    pub async fn is_dj(&self, ctx: &Context, msg: &Message) -> bool {
+        let member = match msg.member(ctx).await {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+
+        if let Some(dj_role) = self.dj_role {
+            if member.roles.contains(&RoleId(dj_role)) {
+                return true;
+            }
+        }
+
+        member
+            .permissions(ctx)
+            .await
+            .map(|i| i.manage_guild())
+            .unwrap_or(false)
+    }
+
+    /// Checks if the author of a message can control the playback without voting.
+    ///
+    /// That's DJs and whoever requested the current track, or everyone if there's no DJ role.
+    pub async fn can_control(&self, ctx: &Context, msg: &Message) -> bool {
+        if self.dj_role.is_none() {
+            return true;
+        }
+
+        let guild_id = msg.guild_id.unwrap().0;
+
+        let lava_client = {
+            let data_read = ctx.data.read().await;
+            data_read.get::<Lavalink>().unwrap().client(guild_id).await
+        };
+
+        let requester = if let Some(node) = lava_client.nodes().await.get(&guild_id) {
+            node.now_playing.as_ref().and_then(|i| i.requester)
+        } else {
+            None
+        };
+
+        requester.map(|i| i.0) == Some(msg.author.id.0) || self.is_dj(ctx, msg).await
+    }
+
+    /// Keeps the tracks the user is allowed to queue, due to their length and how many tracks
+    /// the user already has on the queue.
+    pub fn allowed_tracks(
+        &self,
+        queue: &[TrackQueue],
+        user_id: u64,
+        tracks: Vec<Track>,
+    ) -> Vec<Track> {
+        let queued = queue
+            .iter()
+            .filter(|i| i.requester.map(|r| r.0) == Some(user_id))
+            .count();
+        let available = self
+            .max_user_tracks
+            .map(|i| i.saturating_sub(queued))
+            .unwrap_or(usize::MAX);
+
+        tracks
+            .into_iter()
+            .filter(|track| match (self.max_track_length, track.info.as_ref()) {
+                (Some(max), Some(info)) => !info.is_stream && info.length <= max,
+                _ => true,
+            })
+            .take(available)
+            .collect()
+    }
+
+    /// Describes the queue limits, to let users know why their tracks were not queued.
+    pub fn limits_description(&self) -> String {
+        let mut limits = vec![];
+
+        if let Some(x) = self.max_user_tracks {
+            limits.push(format!("{} queued tracks per user", x));
+        }
+
+        if let Some(x) = self.max_track_length {
+            limits.push(format!("tracks up to {} long", format_duration(x)));
+        }
+
+        if limits.is_empty() {
+            "no limits".to_string()
+        } else {
+            limits.join(" and ")
+        }
+    }
+}
+
+/// Keeps the tracks the author of a message is allowed to queue, DJs have no limits.
+///
+/// Returns the description of the limits too if any track was left out.
+pub async fn queueable_tracks(
+    ctx: &Context,
+    msg: &Message,
+    tracks: Vec<Track>,
+) -> (Vec<Track>, Option<String>) {
+    let guild_id = msg.guild_id.unwrap().0;
+
+    let (pool, lava_client) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read.get::<DatabasePool>().unwrap().clone(),
+            data_read.get::<Lavalink>().unwrap().client(guild_id).await,
+        )
+    };
+
+    let permissions = MusicPermissions::load(&pool, guild_id).await;
+
+    if permissions.is_dj(ctx, msg).await {
+        return (tracks, None);
+    }
+
+    let queue = if let Some(node) = lava_client.nodes().await.get(&guild_id) {
+        node.queue.clone()
+    } else {
+        vec![]
+    };
+
+    let total = tracks.len();
+    let allowed = permissions.allowed_tracks(&queue, msg.author.id.0, tracks);
+
+    if allowed.len() < total {
+        (allowed, Some(permissions.limits_description()))
+    } else {
+        (allowed, None)
+    }
+}
+
+/// What a vote is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteKind {
+    Skip,
+    Stop,
+}
+
+/// The result of voting to skip or stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vote {
+    /// The voter is not listening on the voice channel of the bot.
+    NotListening,
+    Pending {
+        votes: usize,
+        required: usize,
+    },
+    Passed,
+}
+
+/// Adds the vote of the author of a message, counted among the members on the voice channel.
+pub async fn vote(ctx: &Context, msg: &Message, kind: VoteKind, ratio: u8) -> Vote {
+    let guild = match msg.guild(ctx).await {
+        Some(x) => x,
+        None => return Vote::NotListening,
+    };
+
+    let bot_id = ctx.cache.current_user_id().await;
+
+    let bot_channel = if let Some(x) = guild
+        .voice_states
+        .get(&bot_id)
+        .and_then(|voice_state| voice_state.channel_id)
+    {
+        x
+    } else {
+        return Vote::NotListening;
+    };
+
+    let listeners = guild
+        .voice_states
+        .values()
+        .filter(|voice_state| voice_state.channel_id == Some(bot_channel))
+        .filter(|voice_state| {
+            guild
+                .members
+                .get(&voice_state.user_id)
+                .map(|member| !member.user.bot)
+                .unwrap_or(voice_state.user_id != bot_id)
+        })
+        .map(|voice_state| voice_state.user_id.0)
+        .collect::<HashSet<u64>>();
+
+    if !listeners.contains(&msg.author.id.0) {
+        return Vote::NotListening;
+    }
+
+    let required = ((listeners.len() * ratio as usize + 99) / 100).max(1);
+
+    let music_states = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<MusicStates>().unwrap().clone()
+    };
+
+    let mut music_states = music_states.write().await;
+    let state = music_states.entry(guild.id.0).or_default();
+
+    let votes = match kind {
+        VoteKind::Skip => &mut state.skip_votes,
+        VoteKind::Stop => &mut state.stop_votes,
+    };
+
+    // Votes of listeners that already left don't count.
+    votes.retain(|i| listeners.contains(i));
+    votes.insert(msg.author.id.0);
+
+    if votes.len() >= required {
+        votes.clear();
+        Vote::Passed
+    } else {
+        Vote::Pending {
+            votes: votes.len(),
+            required,
+        }
+    }
+}
+
+/// Why the bot would leave a voice channel by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    Alone,
+    Idle,
+}
+
+/// Checks if a guild has 24/7 mode enabled.
+pub async fn stays_connected(pool: &PgPool, guild_id: u64) -> bool {
+    match sqlx::query!(
+        "SELECT stay_connected FROM music_settings WHERE guild_id = $1",
+        guild_id as i64
+    )
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(x) => x.map(|i| i.stay_connected).unwrap_or(false),
+        Err(why) => {
+            error!("Error quering Database: {}", why);
+            false
+        }
+    }
+}
+
+/// Disconnects from the voice channel of a guild, destroying the lavalink player.
+pub async fn leave_voice(
+    manager: &Songbird,
+    lavalink: &LavalinkPool,
+    music_states: &MusicStateMap,
+    redis_pool: &RedisPool,
+    guild_id: u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if manager.get(guild_id).is_some() {
+        manager.remove(guild_id).await?;
+    }
+
+    delete_queue_snapshot(redis_pool, guild_id).await;
+
+    let lava_client = lavalink.client(guild_id).await;
+    lava_client.destroy(GuildId(guild_id)).await?;
+    lava_client.nodes().await.remove(&guild_id);
+    lava_client.loops().await.remove(&guild_id);
+    lavalink.release(guild_id).await;
+
+    if let Some(state) = music_states.write().await.get_mut(&guild_id) {
+        state.current = None;
+        state.position = None;
+        state.request_channels.clear();
+        state.alone_since = None;
+        state.idle_since = None;
+        state.audio_applied = false;
+        state.skip_votes.clear();
+        state.stop_votes.clear();
+    }
+
+    Ok(())
+}
+
+/// Everything needed to leave voice channels once nobody is listening.
+#[derive(Clone)]
+pub struct AutoLeave {
+    pub http: Arc<Http>,
+    pub songbird: Arc<Songbird>,
+    pub pool: PgPool,
+    pub redis_pool: RedisPool,
+    pub music_states: MusicStateMap,
+    pub lavalink: LavalinkPool,
+    pub config: MusicConfig,
+}
+
+impl AutoLeave {
+    pub async fn from_context(ctx: &Context) -> Self {
+        let data_read = ctx.data.read().await;
+
+        Self {
+            http: Arc::clone(&ctx.http),
+            songbird: songbird::get(ctx).await.unwrap(),
+            pool: data_read.get::<DatabasePool>().unwrap().clone(),
+            redis_pool: data_read.get::<CachePool>().unwrap().clone(),
+            music_states: data_read.get::<MusicStates>().unwrap().clone(),
+            lavalink: data_read.get::<Lavalink>().unwrap().clone(),
+            config: data_read.get::<Tokens>().unwrap().music.clone(),
+        }
+    }
+
+    /// Leaves the voice channel once the timeout of the reason has passed,
+    /// if the guild is still alone or idle by then and doesn't have 24/7 mode enabled.
+    pub fn schedule(&self, guild_id: u64, reason: LeaveReason) {
+        let auto_leave = self.clone();
+
+        tokio::spawn(async move {
+            let timeout = Duration::from_secs(match reason {
+                LeaveReason::Alone => auto_leave.config.alone_timeout,
+                LeaveReason::Idle => auto_leave.config.idle_timeout,
+            });
+
+            tokio::time::sleep(timeout).await;
+
+            let (timed_out, channel_id) = {
+                let music_states = auto_leave.music_states.read().await;
+                if let Some(state) = music_states.get(&guild_id) {
+                    let since = match reason {
+                        LeaveReason::Alone => state.alone_since,
+                        LeaveReason::Idle => state.idle_since,
+                    };
+
+                    (
+                        since.map(|i| i.elapsed() >= timeout).unwrap_or(false),
+                        state.last_request_channel,
+                    )
+                } else {
+                    (false, None)
+                }
+            };
+
+            if !timed_out
+                || auto_leave.songbird.get(guild_id).is_none()
+                || stays_connected(&auto_leave.pool, guild_id).await
+            {
+                return;
+            }
+
+            if let Err(why) = leave_voice(
+                &auto_leave.songbird,
+                &auto_leave.lavalink,
+                &auto_leave.music_states,
+                &auto_leave.redis_pool,
+                guild_id,
+            )
+            .await
+            {
+                error!("Error leaving an inactive voice channel: {}", why);
+                return;
+            }
+
+            if let Some(channel_id) = channel_id {
+                let content = match reason {
+                    LeaveReason::Alone => "Left the voice channel, as nobody was listening.",
+                    LeaveReason::Idle => "Left the voice channel, as the queue ended.",
+                };
+
+                let _ = ChannelId(channel_id).say(&auto_leave.http, content).await;
+            }
+        });
+    }
+}
+
+// The redis hash the queue snapshots are stored on, by guild id.
+const QUEUE_SNAPSHOTS_KEY: &str = "music_queue_snapshots";
+// The position of the first track, updated often, so it's kept apart from the snapshots.
+const QUEUE_POSITIONS_KEY: &str = "music_queue_positions";
+
+/// A queued track, with who and where it was requested from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotTrack {
+    pub track: Track,
+    pub requester: Option<u64>,
+    pub channel_id: Option<u64>,
+}
+
+/// The queue of a guild, saved to redis so it can be resumed after a restart.
+///
+/// The volume and filters are already kept on the database,
+/// and they get applied again once the first restored track starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub guild_id: u64,
+    pub voice_channel: u64,
+    /// The queued tracks, the first one being the track that was playing.
+    pub tracks: Vec<SnapshotTrack>,
+    /// The position in milliseconds of the first track.
+    pub position: u64,
+    pub paused: bool,
+    pub loop_mode: LoopMode,
+}
+
+impl QueueSnapshot {
+    /// Takes a snapshot of the queue of a guild, if the bot is on a voice channel there.
+    pub async fn take(
+        manager: &Songbird,
+        lava_client: &LavalinkClient,
+        music_states: &MusicStateMap,
+        guild_id: u64,
+    ) -> Option<Self> {
+        let voice_channel = manager.get(guild_id)?.lock().await.current_channel()?.0;
+
+        let (queue, paused) = if let Some(node) = lava_client.nodes().await.get(&guild_id) {
+            (node.queue.clone(), node.is_paused)
+        } else {
+            (vec![], false)
+        };
+
+        let music_states = music_states.read().await;
+        let state = music_states.get(&guild_id).cloned().unwrap_or_default();
+
+        // The node removes the current track from the queue when it ends,
+        // so the loop may have to put it back on the queue while it's still playing.
+        let queue = if queue.is_empty() {
+            state.current.iter().cloned().collect()
+        } else {
+            queue
+        };
+
+        Some(Self {
+            guild_id,
+            voice_channel,
+            tracks: queue
+                .into_iter()
+                .map(|i| SnapshotTrack {
+                    channel_id: state.request_channel(&i.track.track),
+                    requester: i.requester.map(|i| i.0),
+                    track: i.track,
+                })
+                .collect(),
+            position: state.current_position(paused).unwrap_or(0),
+            paused,
+            loop_mode: state.loop_mode,
+        })
+    }
+
+    /// Joins the voice channel again and queues all the tracks,
+    /// starting the first one where it was left.
+    pub async fn restore(
+        &self,
+        manager: &Songbird,
+        lava_client: &LavalinkClient,
+        music_states: &MusicStateMap,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guild_id = GuildId(self.guild_id);
+
+        let (_, handler) = manager
+            .join_gateway(guild_id, ChannelId(self.voice_channel))
+            .await;
+        lava_client.create_session(&handler?).await?;
+
+        self.enqueue(lava_client, music_states).await
+    }
+
+    /// Queues all the tracks on a player that is already connected,
+    /// starting the first one where it was left.
+    pub async fn enqueue(
+        &self,
+        lava_client: &LavalinkClient,
+        music_states: &MusicStateMap,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guild_id = GuildId(self.guild_id);
+
+        {
+            let mut music_states = music_states.write().await;
+            let state = music_states.entry(self.guild_id).or_default();
+            state.loop_mode = self.loop_mode;
+
+            for track in &self.tracks {
+                if let Some(channel_id) = track.channel_id {
+                    state.set_request_channel(&track.track.track, channel_id);
+                }
+            }
+        }
+
+        for (index, track) in self.tracks.iter().enumerate() {
+            let mut play = lava_client.play(guild_id, track.track.clone());
+
+            if let Some(requester) = track.requester {
+                play = play.requester(UserId(requester));
+            }
+
+            if index == 0 && self.position > 0 {
+                play = play.start_time(Duration::from_millis(self.position));
+            }
+
+            play.queue().await?;
+        }
+
+        if self.paused {
+            lava_client.set_pause(guild_id, true).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Saves the queue of a guild to redis, or removes it if the bot is no longer on a voice channel.
+pub async fn save_queue_snapshot(
+    manager: &Songbird,
+    lava_client: &LavalinkClient,
+    music_states: &MusicStateMap,
+    redis_pool: &RedisPool,
+    guild_id: u64,
+) {
+    let snapshot =
+        if let Some(x) = QueueSnapshot::take(manager, lava_client, music_states, guild_id).await {
+            x
+        } else {
+            delete_queue_snapshot(redis_pool, guild_id).await;
+            return;
+        };
+
+    let data = match serde_json::to_vec(&snapshot) {
+        Ok(x) => x,
+        Err(why) => {
+            error!("Error serializing the queue snapshot: {}", why);
+            return;
+        }
+    };
+
+    let mut redis = redis_pool.get().await;
+
+    if let Err(why) = redis
+        .hset(QUEUE_SNAPSHOTS_KEY, guild_id.to_string(), data)
+        .await
+    {
+        error!("Error sending the queue snapshot to redis: {}", why);
+    }
+
+    // The position on the new snapshot is newer than the saved one.
+    if let Err(why) = redis.hdel(QUEUE_POSITIONS_KEY, guild_id.to_string()).await {
+        error!("Error removing the queue position from redis: {}", why);
+    }
+}
+
+/// Saves the position of the track that is playing on a guild, without the rest of the queue.
+pub async fn save_queue_position(redis_pool: &RedisPool, guild_id: u64, position: u64) {
+    let mut redis = redis_pool.get().await;
+
+    if let Err(why) = redis
+        .hset(
+            QUEUE_POSITIONS_KEY,
+            guild_id.to_string(),
+            position.to_string(),
+        )
+        .await
+    {
+        error!("Error sending the queue position to redis: {}", why);
+    }
+}
+
+/// Saves the queue of a guild after a command modified it.
+pub async fn save_guild_queue(ctx: &Context, guild_id: u64) {
+    let auto_leave = AutoLeave::from_context(ctx).await;
+    let lava_client = auto_leave.lavalink.client(guild_id).await;
+
+    save_queue_snapshot(
+        &auto_leave.songbird,
+        &lava_client,
+        &auto_leave.music_states,
+        &auto_leave.redis_pool,
+        guild_id,
+    )
+    .await;
+}
+
+pub async fn delete_queue_snapshot(redis_pool: &RedisPool, guild_id: u64) {
+    let mut redis = redis_pool.get().await;
+
+    if let Err(why) = redis.hdel(QUEUE_SNAPSHOTS_KEY, guild_id.to_string()).await {
+        error!("Error removing the queue snapshot from redis: {}", why);
+    }
+
+    if let Err(why) = redis.hdel(QUEUE_POSITIONS_KEY, guild_id.to_string()).await {
+        error!("Error removing the queue position from redis: {}", why);
+    }
+}
+
+// The last position saved by the player updates, if it was saved after the snapshot.
+async fn saved_queue_position(redis_pool: &RedisPool, guild_id: u64) -> Option<u64> {
+    let mut redis = redis_pool.get().await;
+
+    match redis.hget(QUEUE_POSITIONS_KEY, guild_id.to_string()).await {
+        Ok(x) => String::from_utf8(x?).ok()?.parse().ok(),
+        Err(why) => {
+            error!("Error getting the queue position from redis: {}", why);
+            None
+        }
+    }
+}
+
+/// Resumes the queues that were playing before the bot restarted.
+///
+/// Guilds with an empty queue are only joined again if they have 24/7 mode enabled.
+pub async fn restore_queue_snapshots(ctx: &Context) {
+    let auto_leave = AutoLeave::from_context(ctx).await;
+
+    let snapshots = {
+        let mut redis = auto_leave.redis_pool.get().await;
+
+        match redis.hvals(QUEUE_SNAPSHOTS_KEY).await {
+            Ok(x) => x,
+            Err(why) => {
+                error!("Error getting the queue snapshots from redis: {}", why);
+                return;
+            }
+        }
+    };
+
+    for data in snapshots {
+        let mut snapshot = match serde_json::from_slice::<QueueSnapshot>(&data) {
+            Ok(x) => x,
+            Err(why) => {
+                warn!("Invalid queue snapshot: {}", why);
+                continue;
+            }
+        };
+
+        if let Some(position) =
+            saved_queue_position(&auto_leave.redis_pool, snapshot.guild_id).await
+        {
+            snapshot.position = position;
+        }
+
+        if snapshot.tracks.is_empty() && !stays_connected(&auto_leave.pool, snapshot.guild_id).await
+        {
+            delete_queue_snapshot(&auto_leave.redis_pool, snapshot.guild_id).await;
+            continue;
+        }
+
+        let region = ctx
+            .cache
+            .guild(snapshot.guild_id)
+            .await
+            .map(|guild| guild.region);
+        let lava_client = auto_leave
+            .lavalink
+            .assign(snapshot.guild_id, region.as_deref())
+            .await;
+
+        if let Err(why) = snapshot
+            .restore(&auto_leave.songbird, &lava_client, &auto_leave.music_states)
+            .await
+        {
+            error!(
+                "Error restoring the queue of guild {}: {}",
+                snapshot.guild_id, why
+            );
+            delete_queue_snapshot(&auto_leave.redis_pool, snapshot.guild_id).await;
+            auto_leave.lavalink.release(snapshot.guild_id).await;
+            continue;
+        }
+
+        info!(
+            "Restored {} queued tracks on guild {}",
+            snapshot.tracks.len(),
+            snapshot.guild_id
+        );
+    }
+}
+
+/// Formats a duration in milliseconds as `m:ss`, or `h:mm:ss` if it's an hour or longer.
+pub fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -1,5 +1,17 @@
 use std::intrinsics::log10f64;
 
+use crate::global_data::CachePool;
+use crate::utils::osu_model::{Beatmap, BeatmapAttributes, GameMode, Statistics};
+
+use reqwest::Url;
+
+use serenity::{model::id::ChannelId, prelude::Context};
+
+// How many messages are looked through to find the last beatmap of a channel.
+const LAST_BEATMAP_SEARCH_LIMIT: u64 = 50;
+// How long the last beatmap of a channel is remembered, a week.
+const LAST_BEATMAP_EXPIRE_SECONDS: u32 = 604800;
+
 fn min(x: f64, y: f64) -> f64 {
     x.min(y)
 }
@@ -10,12 +22,20 @@ fn max(x: f64, y: f64) -> f64 {
 
 #[derive(Debug, Default)]
 pub struct PpCalculation {
+    /// The ruleset the pp are calculated for, osu!std by default.
+    pub mode: GameMode,
+    pub score: f64,
     pub score_mods: Vec<String>,
     pub score_max_combo: f64,
     pub score_great: f64,
     pub score_good: f64,
     pub score_meh: f64,
     pub score_miss: f64,
+    /// The MAX judgements on osu!mania.
+    pub score_geki: f64,
+    /// The 200s on osu!mania, and the missed droplets on osu!catch.
+    pub score_katu: f64,
+    pub map_stars: f64,
     pub map_aim_strain: f64,
     pub map_speed_strain: f64,
     pub map_max_combo: f64,
@@ -25,6 +45,10 @@ pub struct PpCalculation {
     pub map_sliders: f64,
     pub map_spinners: f64,
     pub progress: f64,
+    /// The hit window of the 300s in milliseconds, with the mods applied. (osu!taiko and osu!mania)
+    pub map_great_hit_window: f64,
+    /// The score multiplier of the mods. (osu!mania)
+    pub map_score_multiplier: f64,
 
     map_hit_count: f64,
     total_hits: f64,
@@ -40,7 +64,63 @@ pub struct PpCalculation {
 }
 
 impl PpCalculation {
+    /// Sets up the calculation of a finished score,
+    /// with the difficulty attributes of the beatmap on the mode of the score.
+    pub fn from_score(
+        mode: GameMode,
+        beatmap: &Beatmap,
+        attributes: &BeatmapAttributes,
+        mods: &[String],
+        statistics: &Statistics,
+        max_combo: u64,
+        score: u128,
+    ) -> Self {
+        Self {
+            mode,
+            score: score as f64,
+            score_mods: mods.to_vec(),
+            score_max_combo: max_combo as f64,
+            score_great: statistics.count_300 as f64,
+            score_good: statistics.count_100 as f64,
+            score_meh: statistics.count_50 as f64,
+            score_miss: statistics.count_miss as f64,
+            score_geki: statistics.count_geki as f64,
+            score_katu: statistics.count_katu as f64,
+            map_stars: attributes.star_rating,
+            map_aim_strain: attributes.aim_difficulty.unwrap_or_default(),
+            map_speed_strain: attributes.speed_difficulty.unwrap_or_default(),
+            map_max_combo: attributes.max_combo as f64,
+            // osu!std applies the mods to the AR and OD by itself, osu!catch uses them as they are.
+            map_ar: if mode == GameMode::Fruits {
+                attributes.approach_rate.unwrap_or(beatmap.ar)
+            } else {
+                beatmap.ar
+            },
+            map_od: beatmap.accuracy,
+            map_circles: beatmap.count_circles as f64,
+            map_sliders: beatmap.count_sliders as f64,
+            map_spinners: beatmap.count_spinners as f64,
+            progress: 100.0,
+            map_great_hit_window: attributes.great_hit_window.unwrap_or_default(),
+            map_score_multiplier: attributes.score_multiplier.unwrap_or(1.0),
+            ..Default::default()
+        }
+    }
+
     pub fn calculate(&mut self) -> f64 {
+        match self.mode {
+            GameMode::Osu => self.calculate_osu(),
+            GameMode::Taiko => self.calculate_taiko(),
+            GameMode::Fruits => self.calculate_fruits(),
+            GameMode::Mania => self.calculate_mania(),
+        }
+    }
+
+    fn has_mod(&self, name: &str) -> bool {
+        self.score_mods.iter().any(|i| i == name)
+    }
+
+    fn calculate_osu(&mut self) -> f64 {
         self.map_hit_count = self.map_circles + self.map_sliders + self.map_spinners;
         self.total_hits = self.score_great + self.score_good + self.score_meh + self.score_miss;
         self.total_successful_hits = self.score_great + self.score_good + self.score_meh;
@@ -91,6 +171,166 @@ impl PpCalculation {
             * multiplier
     }
 
+    fn calculate_taiko(&self) -> f64 {
+        let total_hits = self.score_great + self.score_good + self.score_miss;
+
+        if total_hits == 0.0 {
+            return 0.0;
+        }
+
+        let accuracy = (self.score_great + self.score_good * 0.5) / total_hits;
+        let length_bonus = 1.0 + 0.1 * min(1.0, total_hits / 1500.0);
+
+        let mut strain_value = (5.0 * max(1.0, self.map_stars / 0.0075) - 4.0).powf(2.0) / 100000.0;
+
+        strain_value *= length_bonus;
+        strain_value *= 0.985_f64.powf(self.score_miss);
+
+        if self.has_mod("HD") {
+            strain_value *= 1.025;
+        }
+        if self.has_mod("FL") {
+            strain_value *= 1.05 * length_bonus;
+        }
+
+        strain_value *= accuracy;
+
+        let mut acc_value = if self.map_great_hit_window > 0.0 {
+            (150.0 / self.map_great_hit_window).powf(1.1)
+                * accuracy.powf(15.0)
+                * 22.0
+                * min(1.15, (total_hits / 1500.0).powf(0.3))
+        } else {
+            0.0
+        };
+
+        if self.has_mod("HD") && self.has_mod("FL") {
+            acc_value *= max(1.05, 1.075 * length_bonus);
+        }
+
+        let mut multiplier = 1.1_f64;
+
+        if self.has_mod("NF") {
+            multiplier *= 0.90;
+        }
+        if self.has_mod("HD") {
+            multiplier *= 1.10;
+        }
+
+        (strain_value.powf(1.1) + acc_value.powf(1.1)).powf(1.0 / 1.1) * multiplier
+    }
+
+    fn calculate_fruits(&self) -> f64 {
+        // The fruits and droplets that give combo.
+        let combo_hits = self.score_great + self.score_good + self.score_miss;
+        let total_hits = combo_hits + self.score_meh + self.score_katu;
+
+        if total_hits == 0.0 {
+            return 0.0;
+        }
+
+        let accuracy = (self.score_great + self.score_good + self.score_meh) / total_hits;
+
+        let mut value = (5.0 * max(1.0, self.map_stars / 0.0049) - 4.0).powf(2.0) / 100000.0;
+
+        let mut length_bonus = 0.95 + 0.3 * min(1.0, combo_hits / 2500.0);
+        if combo_hits > 2500.0 {
+            length_bonus += (combo_hits / 2500.0).log10() * 0.475;
+        }
+
+        value *= length_bonus;
+        value *= 0.97_f64.powf(self.score_miss);
+
+        if self.map_max_combo > 0.0 {
+            value *= min(
+                1.0,
+                self.score_max_combo.powf(0.8) / self.map_max_combo.powf(0.8),
+            );
+        }
+
+        let mut ar_factor = 1.0;
+
+        if self.map_ar > 9.0 {
+            ar_factor += 0.1 * (self.map_ar - 9.0);
+        }
+        if self.map_ar > 10.0 {
+            ar_factor += 0.1 * (self.map_ar - 10.0);
+        } else if self.map_ar < 8.0 {
+            ar_factor += 0.025 * (8.0 - self.map_ar);
+        }
+
+        value *= ar_factor;
+
+        if self.has_mod("HD") {
+            if self.map_ar <= 10.0 {
+                value *= 1.05 + 0.075 * (10.0 - self.map_ar);
+            } else {
+                value *= 1.01 + 0.04 * (11.0 - min(11.0, self.map_ar));
+            }
+        }
+
+        if self.has_mod("FL") {
+            value *= 1.35 * length_bonus;
+        }
+
+        value *= accuracy.powf(5.5);
+
+        if self.has_mod("NF") {
+            value *= 0.90;
+        }
+
+        value
+    }
+
+    fn calculate_mania(&self) -> f64 {
+        let total_hits = self.score_geki
+            + self.score_great
+            + self.score_katu
+            + self.score_good
+            + self.score_meh
+            + self.score_miss;
+
+        // The score without the multiplier of the mods.
+        let scaled_score = if self.map_score_multiplier > 0.0 {
+            self.score / self.map_score_multiplier
+        } else {
+            self.score
+        };
+
+        let mut strain_value = (5.0 * max(1.0, self.map_stars / 0.2) - 4.0).powf(2.2) / 135.0;
+
+        strain_value *= 1.0 + 0.1 * min(1.0, total_hits / 1500.0);
+
+        strain_value *= if scaled_score <= 500000.0 {
+            0.0
+        } else if scaled_score <= 600000.0 {
+            (scaled_score - 500000.0) / 100000.0 * 0.3
+        } else if scaled_score <= 700000.0 {
+            0.3 + (scaled_score - 600000.0) / 100000.0 * 0.25
+        } else if scaled_score <= 800000.0 {
+            0.55 + (scaled_score - 700000.0) / 100000.0 * 0.20
+        } else if scaled_score <= 900000.0 {
+            0.75 + (scaled_score - 800000.0) / 100000.0 * 0.15
+        } else {
+            0.90 + (scaled_score - 900000.0) / 100000.0 * 0.1
+        };
+
+        let acc_value = max(0.0, 0.2 - (self.map_great_hit_window - 34.0) * 0.006667)
+            * strain_value
+            * (max(0.0, scaled_score - 960000.0) / 40000.0).powf(1.1);
+
+        let mut multiplier = 0.8_f64;
+
+        if self.has_mod("NF") {
+            multiplier *= 0.90;
+        }
+        if self.has_mod("EZ") {
+            multiplier *= 0.50;
+        }
+
+        (strain_value.powf(1.1) + acc_value.powf(1.1)).powf(1.0 / 1.1) * multiplier
+    }
+
     pub fn compute_aim_value(&self) -> f64 {
         let mut aim_value =
             (5.0 * max(1.0, self.map_aim_strain / 0.0675) - 4.0).powf(3.0) / 100000.0;
@@ -281,6 +521,357 @@ impl PpCalculation {
         println!("DT2 {}", ppnum);
     }
 }
+
+// The mods that can be used on the pp calculations, by their short name.
+pub const PP_MODS: [&str; 22] = [
+    "NF", "EZ", "TD", "HD", "HR", "SD", "DT", "RX", "HT", "NC", "FL", "SO", "PF", "V2", "FI", "MR",
+    "4K", "5K", "6K", "7K", "8K", "9K",
+];
+
+/// Splits mod combinations like `HDDT` or `+HD,DT` into the short names of the mods.
+///
+/// Returns the first unknown mod as the error.
+pub fn parse_mods(input: &[&str]) -> Result<Vec<String>, String> {
+    let mut mods = vec![];
+
+    for part in input {
+        let part = part
+            .trim_start_matches('+')
+            .replace(&[',', '|'][..], "")
+            .to_uppercase();
+
+        if part == "NM" {
+            continue;
+        }
+
+        for chunk in part.chars().collect::<Vec<char>>().chunks(2) {
+            let name = chunk.iter().collect::<String>();
+
+            if !PP_MODS.contains(&name.as_str()) {
+                return Err(name);
+            }
+
+            if !mods.contains(&name) {
+                mods.push(name);
+            }
+        }
+    }
+
+    Ok(mods)
+}
+
+/// The 300s, 100s and 50s that give the closest accuracy to the provided percentage.
+pub fn accuracy_hits(objects: u32, accuracy: f64, misses: u32) -> (u32, u32, u32) {
+    let misses = misses.min(objects);
+    let max_300 = objects - misses;
+    let accuracy = (accuracy / 100.0).max(0.0).min(1.0);
+    let missing = (accuracy - 1.0) * objects as f64 + misses as f64;
+
+    let n100 = (-3.0 * missing * 0.5).round().max(0.0) as u32;
+
+    if n100 <= max_300 {
+        return (max_300 - n100, n100, 0);
+    }
+
+    // The accuracy is too low to be reached with 100s, so 50s are used instead.
+    let n50 = ((-6.0 * missing * 0.2).round().max(0.0) as u32).min(max_300);
+    (max_300 - n50, 0, n50)
+}
+
+/// The 300s, 100s and misses of an osu!taiko score that give the closest accuracy to the provided percentage.
+pub fn taiko_accuracy_hits(objects: u32, accuracy: f64, misses: u32) -> (u32, u32) {
+    let misses = misses.min(objects);
+    let accuracy = (accuracy / 100.0).max(0.0).min(1.0);
+
+    // Every 100 is worth half of a 300.
+    let n100 = (2.0 * ((objects - misses) as f64 - accuracy * objects as f64))
+        .round()
+        .max(0.0) as u32;
+    let n100 = n100.min(objects - misses);
+
+    (objects - misses - n100, n100)
+}
+
+/// The hits of a score with the accuracy percentage and misses, on a beatmap with that amount of
+/// objects, being the ones that give combo on osu!taiko and osu!catch.
+///
+/// osu!mania scores are always perfect, as their pp depend on the score.
+pub fn accuracy_statistics(mode: GameMode, objects: u64, accuracy: f64, misses: u64) -> Statistics {
+    let misses = misses.min(objects);
+    let mut stats = Statistics {
+        count_300: objects - misses,
+        count_100: 0,
+        count_50: 0,
+        count_geki: 0,
+        count_katu: 0,
+        count_miss: misses,
+    };
+
+    match mode {
+        GameMode::Osu => {
+            // Every 100 takes 4/6 of a hit from the accuracy.
+            let hit = (objects - misses) as f64;
+            let n100 = (1.5 * (hit - objects as f64 * accuracy / 100.0))
+                .round()
+                .max(0.0)
+                .min(hit) as u64;
+            stats.count_300 -= n100;
+            stats.count_100 = n100;
+        }
+        GameMode::Taiko => {
+            let (n300, n100) = taiko_accuracy_hits(objects as u32, accuracy, misses as u32);
+            stats.count_300 = n300 as u64;
+            stats.count_100 = n100 as u64;
+        }
+        GameMode::Fruits => {
+            // The amount of droplets is unknown, so it's assumed there's one per fruit,
+            // and the accuracy is lowered with missed droplets.
+            let accuracy = (accuracy / 100.0).max(0.01).min(1.0);
+            let hit = (objects - misses + objects) as f64;
+            stats.count_50 = objects;
+            stats.count_katu = (hit / accuracy - (objects * 2) as f64).round().max(0.0) as u64;
+        }
+        GameMode::Mania => (),
+    }
+
+    stats
+}
+
+/// Takes the `-m <mode>` flag out of the arguments of a command, returning the rest of them.
+///
+/// The flag is left untouched if it's not followed by a mode, as `-m` are the mods on `beatmap_pp`.
+pub fn take_mode_flag(input: &str) -> (Option<GameMode>, String) {
+    let words = input.split_whitespace().collect::<Vec<&str>>();
+
+    let mut mode = None;
+    let mut rest = vec![];
+    let mut index = 0;
+
+    while index < words.len() {
+        let word = words[index];
+
+        if mode.is_none() && (word == "-m" || word == "--mode") {
+            if let Some(x) = words.get(index + 1).and_then(|i| GameMode::from_name(i)) {
+                mode = Some(x);
+                index += 2;
+                continue;
+            }
+        }
+
+        rest.push(word);
+        index += 1;
+    }
+
+    (mode, rest.join(" "))
+}
+
+/// The mods of a score joined as they are shown, like `HDDT`, or `NM` without mods.
+pub fn get_mods_short(mods: &[String]) -> String {
+    if mods.is_empty() {
+        "NM".to_string()
+    } else {
+        mods.join("")
+    }
+}
+
+/// The accuracy percentage of the hits of a score on the mode.
+pub fn mode_accuracy(mode: GameMode, stats: &Statistics) -> f64 {
+    let (hit, total) = match mode {
+        GameMode::Osu => (
+            (stats.count_300 * 6 + stats.count_100 * 2 + stats.count_50) as f64,
+            ((stats.count_300 + stats.count_100 + stats.count_50 + stats.count_miss) * 6) as f64,
+        ),
+        GameMode::Taiko => (
+            (stats.count_300 * 2 + stats.count_100) as f64,
+            ((stats.count_300 + stats.count_100 + stats.count_miss) * 2) as f64,
+        ),
+        GameMode::Fruits => (
+            (stats.count_300 + stats.count_100 + stats.count_50) as f64,
+            (stats.count_300
+                + stats.count_100
+                + stats.count_50
+                + stats.count_katu
+                + stats.count_miss) as f64,
+        ),
+        GameMode::Mania => (
+            ((stats.count_geki + stats.count_300) * 6
+                + stats.count_katu * 4
+                + stats.count_100 * 2
+                + stats.count_50) as f64,
+            ((stats.count_geki
+                + stats.count_300
+                + stats.count_katu
+                + stats.count_100
+                + stats.count_50
+                + stats.count_miss)
+                * 6) as f64,
+        ),
+    };
+
+    if total == 0.0 {
+        0.0
+    } else {
+        hit / total * 100.0
+    }
+}
+
+/// The hit counts of a score, in the same order the game shows them.
+pub fn mode_hits(mode: GameMode, stats: &Statistics) -> String {
+    match mode {
+        GameMode::Osu => format!(
+            "{} - {} - {} - {}",
+            stats.count_300, stats.count_100, stats.count_50, stats.count_miss
+        ),
+        GameMode::Taiko => format!(
+            "{} - {} - {}",
+            stats.count_300, stats.count_100, stats.count_miss
+        ),
+        GameMode::Fruits => format!(
+            "{} - {} - {} - {} - {}",
+            stats.count_300, stats.count_100, stats.count_50, stats.count_katu, stats.count_miss
+        ),
+        GameMode::Mania => format!(
+            "{} - {} - {} - {} - {} - {}",
+            stats.count_geki,
+            stats.count_300,
+            stats.count_katu,
+            stats.count_100,
+            stats.count_50,
+            stats.count_miss
+        ),
+    }
+}
+
+/// The percentage of the beatmap a score got through, from the judged hits.
+///
+/// Converted beatmaps and osu!catch have no object counts that match the hits, so there's no progress for them.
+pub fn mode_progress(mode: GameMode, beatmap: &Beatmap, stats: &Statistics) -> Option<f64> {
+    if beatmap.mode != mode.name() {
+        return None;
+    }
+
+    let (hits, objects) = match mode {
+        GameMode::Osu => (
+            stats.count_300 + stats.count_100 + stats.count_50 + stats.count_miss,
+            beatmap.count_circles + beatmap.count_sliders + beatmap.count_spinners,
+        ),
+        // Drum rolls and swells don't have judgements.
+        GameMode::Taiko => (
+            stats.count_300 + stats.count_100 + stats.count_miss,
+            beatmap.count_circles,
+        ),
+        GameMode::Mania => (
+            stats.count_geki
+                + stats.count_300
+                + stats.count_katu
+                + stats.count_100
+                + stats.count_50
+                + stats.count_miss,
+            beatmap.count_circles + beatmap.count_sliders,
+        ),
+        GameMode::Fruits => return None,
+    };
+
+    if objects == 0 {
+        None
+    } else {
+        Some(hits as f64 / objects as f64 * 100.0)
+    }
+}
+
+/// Obtains the beatmap id from an id or a beatmap link, like
+/// `https://osu.ppy.sh/beatmapsets/1#osu/2` or `https://osu.ppy.sh/b/2`.
+pub fn parse_beatmap_id(input: &str) -> Option<u64> {
+    let input = input.trim_matches(|c| c == '<' || c == '>');
+
+    if let Ok(x) = input.parse() {
+        return Some(x);
+    }
+
+    let url = Url::parse(input).ok()?;
+
+    if !url.host_str()?.ends_with("ppy.sh") {
+        return None;
+    }
+
+    let segments = url.path_segments()?.collect::<Vec<&str>>();
+
+    match segments.as_slice() {
+        ["beatmapsets", _, ..] => url.fragment()?.rsplit('/').next()?.parse().ok(),
+        ["b", id, ..] | ["beatmaps", id, ..] => id.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Remembers the last beatmap shown or linked on a channel, for the commands that default to it.
+pub async fn set_channel_beatmap(ctx: &Context, channel_id: ChannelId, beatmap_id: u64) {
+    let redis_pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<CachePool>().unwrap().clone()
+    };
+
+    let mut redis = redis_pool.get().await;
+
+    if let Err(why) = redis
+        .set_and_expire_seconds(
+            format!("osu_last_beatmap:{}", channel_id.0),
+            beatmap_id.to_string(),
+            LAST_BEATMAP_EXPIRE_SECONDS,
+        )
+        .await
+    {
+        error!(
+            "Error sending the last beatmap of a channel to redis: {}",
+            why
+        );
+    }
+}
+
+/// Obtains the last beatmap of a channel.
+///
+/// The beatmaps remembered with `set_channel_beatmap` are used first, falling back to the
+/// pasted links or the embeds of the osu! commands on the last messages of the channel.
+pub async fn last_channel_beatmap(ctx: &Context, channel_id: ChannelId) -> Option<u64> {
+    let redis_pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<CachePool>().unwrap().clone()
+    };
+
+    let cached = {
+        let mut redis = redis_pool.get().await;
+        redis
+            .get(format!("osu_last_beatmap:{}", channel_id.0))
+            .await
+    };
+
+    match cached {
+        Ok(Some(x)) => {
+            if let Some(beatmap_id) = String::from_utf8(x).ok().and_then(|i| i.parse().ok()) {
+                return Some(beatmap_id);
+            }
+        }
+        Ok(None) => (),
+        Err(why) => error!(
+            "Error getting the last beatmap of a channel from redis: {}",
+            why
+        ),
+    }
+
+    let messages = channel_id
+        .messages(ctx, |m| m.limit(LAST_BEATMAP_SEARCH_LIMIT))
+        .await
+        .ok()?;
+
+    messages.iter().find_map(|message| {
+        message
+            .embeds
+            .iter()
+            .filter_map(|i| i.url.as_deref())
+            .chain(message.content.split_whitespace())
+            .filter(|i| i.contains("ppy.sh"))
+            .find_map(parse_beatmap_id)
+    })
+}
 /*
    class diff_calc:
    """
@@ -0,0 +1,371 @@
+//! A typed client for the osu! API v2.
+
+use crate::global_data::OsuHttpClient;
+use crate::utils::osu_model::*;
+
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::RETRY_AFTER, Client as ReqwestClient, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tokio::sync::{Mutex, RwLock};
+
+use serenity::prelude::Context;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const API_URL: &str = "https://osu.ppy.sh/api/v2";
+
+// peppy asks to stay under 60 requests per minute, the hard limit being 1200.
+const REQUESTS_PER_MINUTE: usize = 60;
+// The share of the background jobs and previews, so the commands always have requests left.
+const BACKGROUND_REQUESTS_PER_MINUTE: usize = 20;
+// How many times a request is retried when the API rate limits it anyways.
+const MAX_RETRIES: u8 = 3;
+
+/// The kind of scores of a user that can be requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreKind {
+    Best,
+    Recent,
+    Firsts,
+}
+
+impl ScoreKind {
+    fn path(self) -> &'static str {
+        match self {
+            ScoreKind::Best => "best",
+            ScoreKind::Recent => "recent",
+            ScoreKind::Firsts => "firsts",
+        }
+    }
+}
+
+/// The osu! API v2 client, shared by every osu! command.
+///
+/// The http client is replaced whenever the oauth token is renewed.
+#[derive(Clone)]
+pub struct OsuApi {
+    client: Arc<RwLock<ReqwestClient>>,
+    /// When the requests of the last minute were sent, and if they were from the background.
+    requests: Arc<Mutex<VecDeque<(Instant, bool)>>>,
+    background: bool,
+}
+
+impl OsuApi {
+    /// Creates the API client from an http client with the oauth token on the default headers.
+    pub fn new(client: ReqwestClient) -> Self {
+        Self {
+            client: Arc::new(RwLock::new(client)),
+            requests: Arc::new(Mutex::new(VecDeque::new())),
+            background: false,
+        }
+    }
+
+    /// The same client for the requests nobody is waiting on, like the ones of scheduled jobs.
+    /// Its requests only use a share of the requests per minute, so they never delay a command.
+    pub fn background(&self) -> Self {
+        Self {
+            background: true,
+            ..self.clone()
+        }
+    }
+
+    pub async fn from_context(ctx: &Context) -> Self {
+        let data_read = ctx.data.read().await;
+        data_read.get::<OsuHttpClient>().unwrap().clone()
+    }
+
+    pub async fn http_client(&self) -> ReqwestClient {
+        self.client.read().await.clone()
+    }
+
+    pub async fn set_http_client(&self, client: ReqwestClient) {
+        *self.client.write().await = client;
+    }
+
+    // Waits until a request can be sent without going over the requests per minute.
+    // The lock isn't held while waiting, so a background request doesn't block the commands.
+    async fn wait_ratelimit(&self) {
+        loop {
+            let wait = {
+                let mut requests = self.requests.lock().await;
+
+                while let Some((sent, _)) = requests.front() {
+                    if sent.elapsed() >= Duration::from_secs(60) {
+                        requests.pop_front();
+                    } else {
+                        break;
+                    }
+                }
+
+                // The oldest request that has to expire before this one can be sent.
+                let blocking = if requests.len() >= REQUESTS_PER_MINUTE {
+                    requests.front()
+                } else if self.background
+                    && requests.iter().filter(|(_, i)| *i).count() >= BACKGROUND_REQUESTS_PER_MINUTE
+                {
+                    requests.iter().find(|(_, i)| *i)
+                } else {
+                    requests.push_back((Instant::now(), self.background));
+                    return;
+                };
+
+                blocking
+                    .and_then(|(sent, _)| Duration::from_secs(60).checked_sub(sent.elapsed()))
+                    .unwrap_or_default()
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // Sends a request, retrying it if the API rate limits it.
+    // Returns None if the requested resource doesn't exist.
+    async fn send<F>(&self, request: F) -> Result<Option<Response>, Error>
+    where
+        F: Fn(&ReqwestClient) -> RequestBuilder,
+    {
+        for _ in 0..MAX_RETRIES {
+            self.wait_ratelimit().await;
+
+            let response = {
+                let client = self.client.read().await;
+                request(&client).send().await?
+            };
+
+            match response.status() {
+                StatusCode::NOT_FOUND => return Ok(None),
+                StatusCode::TOO_MANY_REQUESTS => {
+                    let wait = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|i| i.to_str().ok())
+                        .and_then(|i| i.parse::<u64>().ok())
+                        .unwrap_or(1);
+
+                    warn!("Rate limited by the osu! API, retrying in {}s", wait);
+                    tokio::time::sleep(Duration::from_secs(wait)).await;
+                }
+                _ => return Ok(Some(response.error_for_status()?)),
+            }
+        }
+
+        Err("The osu! API is rate limiting the requests, try again later.".into())
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Option<T>, Error> {
+        let url = format!("{}{}", API_URL, path);
+
+        match self.send(|client| client.get(&url).query(query)).await? {
+            Some(response) => Ok(Some(response.json::<T>().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn user(
+        &self,
+        user: &str,
+        key: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Option<UserExtended>, Error> {
+        let path = match mode {
+            Some(mode) => format!("/users/{}/{}", user, mode.name()),
+            None => format!("/users/{}", user),
+        };
+
+        self.get(&path, &[("key", key.to_string())]).await
+    }
+
+    /// Obtains a user with the statistics of the mode, or of their default mode.
+    pub async fn user_by_id(
+        &self,
+        user_id: u64,
+        mode: Option<GameMode>,
+    ) -> Result<Option<UserExtended>, Error> {
+        self.user(&user_id.to_string(), "id", mode).await
+    }
+
+    /// Obtains a user with the statistics of the mode, or of their default mode.
+    pub async fn user_by_name(
+        &self,
+        username: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Option<UserExtended>, Error> {
+        let username = url_escape(username.trim());
+
+        if username.is_empty() {
+            return Ok(None);
+        }
+
+        self.user(&username, "username", mode).await
+    }
+
+    /// Obtains up to 100 of the best, recent or first place scores of a user.
+    pub async fn user_scores(
+        &self,
+        user_id: u64,
+        kind: ScoreKind,
+        mode: Option<GameMode>,
+        limit: u8,
+    ) -> Result<Vec<Score>, Error> {
+        let mut query = vec![("limit", limit.min(100).to_string())];
+
+        if let Some(mode) = mode {
+            query.push(("mode", mode.name().to_string()));
+        }
+
+        if kind == ScoreKind::Recent {
+            query.push(("include_fails", "1".to_string()));
+        }
+
+        Ok(self
+            .get(
+                &format!("/users/{}/scores/{}", user_id, kind.path()),
+                &query,
+            )
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Obtains a score, with its beatmap and user.
+    pub async fn score(&self, mode: GameMode, score_id: u64) -> Result<Option<Score>, Error> {
+        self.get(&format!("/scores/{}/{}", mode.name(), score_id), &[])
+            .await
+    }
+
+    pub async fn beatmap(&self, beatmap_id: u64) -> Result<Option<BeatmapWithSet>, Error> {
+        self.get(&format!("/beatmaps/{}", beatmap_id), &[]).await
+    }
+
+    /// Obtains a beatmap from the md5 hash of its file, like the one of the replays.
+    pub async fn beatmap_by_checksum(
+        &self,
+        checksum: &str,
+    ) -> Result<Option<BeatmapWithSet>, Error> {
+        self.get("/beatmaps/lookup", &[("checksum", checksum.to_string())])
+            .await
+    }
+
+    /// Obtains the difficulty attributes of a beatmap with the mods applied.
+    pub async fn beatmap_attributes(
+        &self,
+        beatmap_id: u64,
+        mods: &[String],
+        mode: Option<GameMode>,
+    ) -> Result<Option<BeatmapAttributes>, Error> {
+        let url = format!("{}/beatmaps/{}/attributes", API_URL, beatmap_id);
+
+        let mut body = json!({ "mods": mods });
+        if let Some(mode) = mode {
+            body["ruleset"] = json!(mode.name());
+        }
+
+        match self.send(|client| client.post(&url).json(&body)).await? {
+            Some(response) => Ok(Some(
+                response.json::<BeatmapAttributesData>().await?.attributes,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Obtains the best score of a user on a beatmap.
+    pub async fn beatmap_user_score(
+        &self,
+        beatmap_id: u64,
+        user_id: u64,
+        mode: Option<GameMode>,
+    ) -> Result<Option<BeatmapUserScore>, Error> {
+        let query = mode
+            .map(|i| vec![("mode", i.name().to_string())])
+            .unwrap_or_default();
+
+        self.get(
+            &format!("/beatmaps/{}/scores/users/{}", beatmap_id, user_id),
+            &query,
+        )
+        .await
+    }
+
+    /// Obtains every score of a user on a beatmap, the best one of each mod combination.
+    pub async fn beatmap_user_scores(
+        &self,
+        beatmap_id: u64,
+        user_id: u64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<BeatmapScore>, Error> {
+        let query = mode
+            .map(|i| vec![("mode", i.name().to_string())])
+            .unwrap_or_default();
+
+        Ok(self
+            .get::<BeatmapUserScores>(
+                &format!("/beatmaps/{}/scores/users/{}/all", beatmap_id, user_id),
+                &query,
+            )
+            .await?
+            .map(|i| i.scores)
+            .unwrap_or_default())
+    }
+
+    pub async fn beatmapset(
+        &self,
+        beatmapset_id: u64,
+    ) -> Result<Option<BeatmapsetWithMaps>, Error> {
+        self.get(&format!("/beatmapsets/{}", beatmapset_id), &[])
+            .await
+    }
+
+    /// Obtains a page of 50 users of the performance rankings, globally or of a country.
+    pub async fn rankings(
+        &self,
+        mode: GameMode,
+        country: Option<&str>,
+        page: u32,
+    ) -> Result<Rankings, Error> {
+        let mut query = vec![("cursor[page]", page.max(1).to_string())];
+
+        if let Some(country) = country {
+            query.push(("country", country.to_uppercase()));
+        }
+
+        self.get(&format!("/rankings/{}/performance", mode.name()), &query)
+            .await?
+            .ok_or_else(|| "The rankings were not found.".into())
+    }
+
+    /// Downloads the .osu file of a beatmap.
+    pub async fn beatmap_file(&self, beatmap_id: u64) -> Result<String, Error> {
+        let url = format!("https://osu.ppy.sh/web/maps/{}", beatmap_id);
+
+        // The files are not on the API, so they don't count for its requests per minute.
+        let response = self.http_client().await.get(&url).send().await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(format!("The beatmap {} does not exist.", beatmap_id).into());
+        }
+
+        Ok(response.error_for_status()?.text().await?)
+    }
+}
+
+// Escapes the characters of a username that can't be on the path of an url.
+fn url_escape(input: &str) -> String {
+    input
+        .bytes()
+        .map(|i| match i {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'[' | b']' => {
+                (i as char).to_string()
+            }
+            _ => format!("%{:02X}", i),
+        })
+        .collect()
+}
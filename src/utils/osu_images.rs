@@ -0,0 +1,648 @@
+//! The images drawn for the osu! commands, like the profile cards and the history graphs.
+
+use crate::global_data::CachePool;
+use crate::utils::osu_api::Error;
+use crate::utils::osu_model::GameMode;
+
+use chrono::{Datelike, NaiveDate};
+use image::{imageops, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use imageproc::{
+    drawing::{
+        draw_antialiased_line_segment_mut, draw_filled_circle_mut, draw_filled_rect_mut,
+        draw_text_mut,
+    },
+    pixelops::interpolate,
+    rect::Rect,
+};
+use num_format::{Locale, ToFormattedString};
+use photon_rs::{
+    transform::{resize, SamplingFilter},
+    PhotonImage,
+};
+use rusttype::{point, Font, Scale};
+use serenity::prelude::Context;
+
+static FONT_REGULAR: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+static FONT_BOLD: &[u8] = include_bytes!("../../fonts/DejaVuSans-Bold.ttf");
+
+// The avatar url changes when it's replaced, so the avatars can be kept for a day.
+const AVATAR_EXPIRE_SECONDS: u32 = 86400;
+
+const CARD_WIDTH: u32 = 900;
+const CARD_HEIGHT: u32 = 430;
+const AVATAR_SIZE: u32 = 160;
+
+const CHART_WIDTH: u32 = 900;
+const CHART_HEIGHT: u32 = 520;
+// The space on the left is for the values of the series.
+const CHART_LEFT: u32 = 110;
+const CHART_RIGHT: u32 = 870;
+
+/// The colours of the images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CardTheme {
+    Dark,
+    Light,
+    Pink,
+}
+
+impl Default for CardTheme {
+    fn default() -> Self {
+        CardTheme::Dark
+    }
+}
+
+struct ThemeColours {
+    background: Rgba<u8>,
+    panel: Rgba<u8>,
+    text: Rgba<u8>,
+    muted: Rgba<u8>,
+    accent: Rgba<u8>,
+}
+
+impl CardTheme {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "dark" | "black" => Some(CardTheme::Dark),
+            "light" | "white" => Some(CardTheme::Light),
+            "pink" | "osu" => Some(CardTheme::Pink),
+            _ => None,
+        }
+    }
+
+    fn colours(self) -> ThemeColours {
+        match self {
+            CardTheme::Dark => ThemeColours {
+                background: Rgba([35, 39, 42, 255]),
+                panel: Rgba([47, 49, 54, 255]),
+                text: Rgba([255, 255, 255, 255]),
+                muted: Rgba([153, 170, 181, 255]),
+                accent: Rgba([255, 102, 170, 255]),
+            },
+            CardTheme::Light => ThemeColours {
+                background: Rgba([245, 245, 245, 255]),
+                panel: Rgba([255, 255, 255, 255]),
+                text: Rgba([35, 39, 42, 255]),
+                muted: Rgba([110, 110, 120, 255]),
+                accent: Rgba([230, 60, 140, 255]),
+            },
+            CardTheme::Pink => ThemeColours {
+                background: Rgba([46, 28, 38, 255]),
+                panel: Rgba([68, 42, 56, 255]),
+                text: Rgba([255, 255, 255, 255]),
+                muted: Rgba([220, 170, 190, 255]),
+                accent: Rgba([255, 102, 170, 255]),
+            },
+        }
+    }
+}
+
+/// Takes the `--card [theme]` flag out of the arguments of a command, returning the rest of them.
+///
+/// The theme defaults to dark when the flag is not followed by one.
+pub fn take_card_flag(input: &str) -> (Option<CardTheme>, String) {
+    let words = input.split_whitespace().collect::<Vec<&str>>();
+
+    let mut theme = None;
+    let mut rest = vec![];
+    let mut index = 0;
+
+    while index < words.len() {
+        let word = words[index];
+
+        if theme.is_none() && word == "--card" {
+            if let Some(x) = words.get(index + 1).and_then(|i| CardTheme::from_name(i)) {
+                theme = Some(x);
+                index += 2;
+            } else {
+                theme = Some(CardTheme::default());
+                index += 1;
+            }
+            continue;
+        }
+
+        rest.push(word);
+        index += 1;
+    }
+
+    (theme, rest.join(" "))
+}
+
+/// Downloads the avatar of an osu! user, keeping it on the cache.
+pub async fn cached_avatar(ctx: &Context, url: &str) -> Result<Vec<u8>, Error> {
+    let redis_pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<CachePool>().unwrap().clone()
+    };
+
+    let key = format!("osu_avatar:{}", url);
+
+    let cached = {
+        let mut redis = redis_pool.get().await;
+        redis.get(&key).await
+    };
+
+    if let Ok(Some(x)) = cached {
+        return Ok(x);
+    }
+
+    let bytes = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec();
+
+    let mut redis = redis_pool.get().await;
+    if let Err(why) = redis
+        .set_and_expire_seconds(&key, &bytes, AVATAR_EXPIRE_SECONDS)
+        .await
+    {
+        error!("Error caching an osu! avatar: {}", why);
+    }
+
+    Ok(bytes)
+}
+
+/// The data shown on the profile card of an osu! user.
+pub struct ProfileCard {
+    pub username: String,
+    pub mode: GameMode,
+    pub country_code: String,
+    pub global_rank: Option<u64>,
+    pub country_rank: Option<u64>,
+    /// None when the user chose to hide their pp.
+    pub pp: Option<f64>,
+    pub accuracy: f64,
+    pub play_count: u64,
+    /// The amount of SSH, SS, SH, S and A grades.
+    pub grades: [i64; 5],
+    pub level: u32,
+    pub level_progress: u32,
+    /// The pp and the name of the top plays, best first.
+    pub top_plays: Vec<(f64, String)>,
+    pub avatar: Option<Vec<u8>>,
+    pub theme: CardTheme,
+}
+
+impl ProfileCard {
+    /// Draws the card as a png.
+    pub fn draw(&self) -> Result<Vec<u8>, Error> {
+        let colours = self.theme.colours();
+        let regular = Font::try_from_bytes(FONT_REGULAR).ok_or("Unable to load the font.")?;
+        let bold = Font::try_from_bytes(FONT_BOLD).ok_or("Unable to load the font.")?;
+
+        let mut image = RgbaImage::from_pixel(CARD_WIDTH, CARD_HEIGHT, colours.background);
+
+        // The header, the avatar goes over its bottom edge.
+        draw_filled_rect_mut(
+            &mut image,
+            Rect::at(0, 0).of_size(CARD_WIDTH, 110),
+            colours.panel,
+        );
+        draw_filled_rect_mut(
+            &mut image,
+            Rect::at(0, 110).of_size(CARD_WIDTH, 4),
+            colours.accent,
+        );
+
+        if let Some(avatar) = self.avatar.as_deref().and_then(round_avatar) {
+            imageops::overlay(&mut image, &avatar, 30, 30);
+        }
+
+        draw_text(
+            &mut image,
+            &bold,
+            40.0,
+            colours.text,
+            220,
+            22,
+            &self.username,
+        );
+        draw_text(
+            &mut image,
+            &regular,
+            22.0,
+            colours.muted,
+            220,
+            72,
+            &format!("{}  •  {}", self.mode.display_name(), self.country_code),
+        );
+
+        let format_rank = |rank: Option<u64>| {
+            rank.map(|i| format!("#{}", i.to_formatted_string(&Locale::en)))
+                .unwrap_or_else(|| "-".to_string())
+        };
+
+        let stats = [
+            ("Global rank", format_rank(self.global_rank)),
+            ("Country rank", format_rank(self.country_rank)),
+            (
+                "pp",
+                self.pp
+                    .map(|i| (i.round() as u64).to_formatted_string(&Locale::en))
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            ("Accuracy", format!("{:.2}%", self.accuracy)),
+            (
+                "Play count",
+                self.play_count.to_formatted_string(&Locale::en),
+            ),
+        ];
+
+        for (index, (label, value)) in stats.iter().enumerate() {
+            let x = 220 + index as u32 * 136;
+            draw_text(&mut image, &regular, 16.0, colours.muted, x, 130, label);
+            draw_text(&mut image, &bold, 24.0, colours.text, x, 152, value);
+        }
+
+        let grades = [
+            ("SSH", Rgba([214, 220, 230, 255])),
+            ("SS", Rgba([255, 204, 34, 255])),
+            ("SH", Rgba([170, 190, 210, 255])),
+            ("S", Rgba([255, 170, 0, 255])),
+            ("A", Rgba([136, 218, 32, 255])),
+        ];
+
+        for (index, ((grade, colour), count)) in grades.iter().zip(&self.grades).enumerate() {
+            let x = 220 + index as u32 * 136;
+            draw_text(&mut image, &bold, 22.0, *colour, x, 200, grade);
+            draw_text(
+                &mut image,
+                &regular,
+                20.0,
+                colours.text,
+                x + text_width(&bold, Scale::uniform(22.0), grade) + 8,
+                202,
+                &count.to_formatted_string(&Locale::en),
+            );
+        }
+
+        // The level, with the progress to the next one as a bar under the avatar.
+        let progress = format!("{}%", self.level_progress);
+        draw_text(
+            &mut image,
+            &bold,
+            20.0,
+            colours.text,
+            30,
+            200,
+            &format!("Lv. {}", self.level),
+        );
+        draw_text(
+            &mut image,
+            &regular,
+            16.0,
+            colours.muted,
+            30 + AVATAR_SIZE - text_width(&regular, Scale::uniform(16.0), &progress),
+            203,
+            &progress,
+        );
+        draw_filled_rect_mut(
+            &mut image,
+            Rect::at(30, 228).of_size(AVATAR_SIZE, 10),
+            colours.panel,
+        );
+        if self.level_progress > 0 {
+            draw_filled_rect_mut(
+                &mut image,
+                Rect::at(30, 228).of_size(AVATAR_SIZE * self.level_progress.min(100) / 100, 10),
+                colours.accent,
+            );
+        }
+
+        draw_filled_rect_mut(
+            &mut image,
+            Rect::at(30, 255).of_size(CARD_WIDTH - 60, 2),
+            colours.panel,
+        );
+        draw_text(&mut image, &bold, 20.0, colours.text, 30, 266, "Top plays");
+
+        if self.top_plays.is_empty() {
+            draw_text(
+                &mut image,
+                &regular,
+                18.0,
+                colours.muted,
+                30,
+                300,
+                "No top plays yet.",
+            );
+        }
+
+        let scale = Scale::uniform(18.0);
+        for (index, (pp, name)) in self.top_plays.iter().enumerate() {
+            let y = 300 + index as u32 * 24;
+            let pp = format!("{:.0}pp", pp);
+
+            draw_text(
+                &mut image,
+                &bold,
+                18.0,
+                colours.accent,
+                130 - text_width(&bold, scale, &pp).min(100),
+                y,
+                &pp,
+            );
+            draw_text(
+                &mut image,
+                &regular,
+                18.0,
+                colours.text,
+                145,
+                y,
+                &fit_text(&regular, scale, name, CARD_WIDTH - 175),
+            );
+        }
+
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut bytes, ImageOutputFormat::Png)?;
+
+        Ok(bytes)
+    }
+}
+
+/// A line graph of the global rank and pp of an osu! user over time.
+pub struct HistoryChart {
+    pub title: String,
+    /// The global rank of each day, oldest first.
+    pub ranks: Vec<(NaiveDate, u64)>,
+    /// The pp of each day, oldest first.
+    pub pp: Vec<(NaiveDate, f64)>,
+    pub theme: CardTheme,
+}
+
+impl HistoryChart {
+    /// Draws the graph as a png, with the rank on top and the pp below.
+    pub fn draw(&self) -> Result<Vec<u8>, Error> {
+        let colours = self.theme.colours();
+        let regular = Font::try_from_bytes(FONT_REGULAR).ok_or("Unable to load the font.")?;
+        let bold = Font::try_from_bytes(FONT_BOLD).ok_or("Unable to load the font.")?;
+
+        let mut image = RgbaImage::from_pixel(CHART_WIDTH, CHART_HEIGHT, colours.background);
+
+        draw_text(&mut image, &bold, 24.0, colours.text, 30, 16, &self.title);
+
+        let days = self
+            .ranks
+            .iter()
+            .map(|(day, _)| *day)
+            .chain(self.pp.iter().map(|(day, _)| *day))
+            .collect::<Vec<NaiveDate>>();
+
+        let (first_day, last_day) = match (days.iter().min(), days.iter().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Err("There's no history to draw.".into()),
+        };
+
+        let ranks = self
+            .ranks
+            .iter()
+            .map(|(day, rank)| (*day, *rank as f64))
+            .collect::<Vec<(NaiveDate, f64)>>();
+
+        let chart = ChartArea {
+            regular: &regular,
+            colours: &colours,
+            first_day,
+            last_day,
+        };
+
+        chart.draw_series(
+            &mut image,
+            70,
+            180,
+            "Global rank",
+            &ranks,
+            colours.accent,
+            true,
+            |i| format!("#{}", (i.round() as u64).to_formatted_string(&Locale::en)),
+        );
+        chart.draw_series(
+            &mut image,
+            310,
+            160,
+            "pp",
+            &self.pp,
+            Rgba([102, 204, 255, 255]),
+            false,
+            |i| format!("{:.0}pp", i),
+        );
+
+        // The dates of the start, middle and end of the graph.
+        let middle_day = first_day + (last_day - first_day) / 2;
+        let scale = Scale::uniform(14.0);
+
+        for day in &[first_day, middle_day, last_day] {
+            let label = day.format("%Y-%m-%d").to_string();
+            let x = chart.day_x(*day) as u32;
+            let width = text_width(&regular, scale, &label);
+
+            draw_text(
+                &mut image,
+                &regular,
+                14.0,
+                colours.muted,
+                (x.saturating_sub(width / 2)).min(CHART_WIDTH - width - 10),
+                488,
+                &label,
+            );
+        }
+
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut bytes, ImageOutputFormat::Png)?;
+
+        Ok(bytes)
+    }
+}
+
+// The dates and the style shared by the series of the history graph.
+struct ChartArea<'a> {
+    regular: &'a Font<'a>,
+    colours: &'a ThemeColours,
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+}
+
+impl<'a> ChartArea<'a> {
+    fn day_x(&self, day: NaiveDate) -> f32 {
+        let days = (self.last_day.num_days_from_ce() - self.first_day.num_days_from_ce()).max(1);
+        let elapsed = day.num_days_from_ce() - self.first_day.num_days_from_ce();
+
+        CHART_LEFT as f32 + (CHART_RIGHT - CHART_LEFT) as f32 * elapsed as f32 / days as f32
+    }
+
+    // Draws the line of a series on its own space, with the highest and lowest values on the side.
+    // `lower_on_top` is for the ranks, where the lower the better.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_series(
+        &self,
+        image: &mut RgbaImage,
+        top: u32,
+        height: u32,
+        label: &str,
+        points: &[(NaiveDate, f64)],
+        colour: Rgba<u8>,
+        lower_on_top: bool,
+        format_value: impl Fn(f64) -> String,
+    ) {
+        draw_text(
+            image,
+            self.regular,
+            16.0,
+            self.colours.muted,
+            CHART_LEFT,
+            top - 26,
+            label,
+        );
+
+        // The lines of the top, middle and bottom of the space.
+        for y in &[top, top + height / 2, top + height] {
+            draw_filled_rect_mut(
+                image,
+                Rect::at(CHART_LEFT as i32, *y as i32).of_size(CHART_RIGHT - CHART_LEFT, 1),
+                self.colours.panel,
+            );
+        }
+
+        if points.is_empty() {
+            draw_text(
+                image,
+                self.regular,
+                16.0,
+                self.colours.muted,
+                CHART_LEFT + 10,
+                top + height / 2 - 24,
+                "No data yet.",
+            );
+            return;
+        }
+
+        let lowest = points.iter().map(|(_, i)| *i).fold(f64::MAX, f64::min);
+        let highest = points.iter().map(|(_, i)| *i).fold(f64::MIN, f64::max);
+
+        let y = |value: f64| {
+            let progress = if highest > lowest {
+                (value - lowest) / (highest - lowest)
+            } else {
+                0.5
+            };
+            let progress = if lower_on_top {
+                progress
+            } else {
+                1.0 - progress
+            };
+
+            top as f32 + height as f32 * progress as f32
+        };
+
+        let (top_value, bottom_value) = if lower_on_top {
+            (lowest, highest)
+        } else {
+            (highest, lowest)
+        };
+        let scale = Scale::uniform(14.0);
+
+        for (value, label_y) in &[(top_value, top), (bottom_value, top + height)] {
+            let text = format_value(*value);
+            draw_text(
+                image,
+                self.regular,
+                14.0,
+                self.colours.text,
+                (CHART_LEFT - 10).saturating_sub(text_width(self.regular, scale, &text)),
+                label_y.saturating_sub(8),
+                &text,
+            );
+        }
+
+        let coordinates = points
+            .iter()
+            .map(|(day, value)| (self.day_x(*day) as i32, y(*value) as i32))
+            .collect::<Vec<(i32, i32)>>();
+
+        // The lines are drawn twice to make them thicker.
+        for pair in coordinates.windows(2) {
+            for offset in 0..2 {
+                draw_antialiased_line_segment_mut(
+                    image,
+                    (pair[0].0, pair[0].1 + offset),
+                    (pair[1].0, pair[1].1 + offset),
+                    colour,
+                    interpolate,
+                );
+            }
+        }
+
+        if let Some(last) = coordinates.last() {
+            draw_filled_circle_mut(image, *last, 4, colour);
+        }
+    }
+}
+
+// Resizes the avatar with photon, and cuts it as a circle.
+fn round_avatar(bytes: &[u8]) -> Option<RgbaImage> {
+    let avatar = image::load_from_memory(bytes).ok()?.into_rgba8();
+    let (width, height) = avatar.dimensions();
+
+    let photon_image = PhotonImage::new(avatar.into_raw(), width, height);
+    let resized = resize(
+        &photon_image,
+        AVATAR_SIZE,
+        AVATAR_SIZE,
+        SamplingFilter::Lanczos3,
+    );
+
+    let mut avatar = RgbaImage::from_raw(AVATAR_SIZE, AVATAR_SIZE, resized.get_raw_pixels())?;
+    let radius = AVATAR_SIZE as f32 / 2.0;
+
+    for (x, y, pixel) in avatar.enumerate_pixels_mut() {
+        let distance =
+            ((x as f32 + 0.5 - radius).powi(2) + (y as f32 + 0.5 - radius).powi(2)).sqrt();
+
+        if distance > radius {
+            pixel.0[3] = 0;
+        }
+    }
+
+    Some(avatar)
+}
+
+fn draw_text(
+    image: &mut RgbaImage,
+    font: &Font,
+    size: f32,
+    colour: Rgba<u8>,
+    x: u32,
+    y: u32,
+    text: &str,
+) {
+    draw_text_mut(image, colour, x, y, Scale::uniform(size), font, text);
+}
+
+fn text_width(font: &Font, scale: Scale, text: &str) -> u32 {
+    font.layout(text, scale, point(0.0, 0.0))
+        .filter_map(|i| i.pixel_bounding_box())
+        .map(|i| i.max.x)
+        .max()
+        .unwrap_or(0)
+        .max(0) as u32
+}
+
+// Cuts the text with an ellipsis, so it fits on the width.
+fn fit_text(font: &Font, scale: Scale, text: &str, max_width: u32) -> String {
+    if text_width(font, scale, text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut chars = text.chars().collect::<Vec<char>>();
+    while chars.pop().is_some() {
+        let cut = format!("{}…", chars.iter().collect::<String>().trim_end());
+
+        if text_width(font, scale, &cut) <= max_width {
+            return cut;
+        }
+    }
+
+    String::new()
+}
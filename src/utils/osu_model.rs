@@ -1,15 +1,153 @@
 use chrono::{DateTime, Utc};
 
+/// The osu! game modes, by their API v2 name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Osu,
+    Taiko,
+    Fruits,
+    Mania,
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Osu
+    }
+}
+
+impl GameMode {
+    /// The mode from its numeric id, which is how it's stored on the database.
+    pub fn from_id(id: i32) -> Self {
+        match id {
+            1 => GameMode::Taiko,
+            2 => GameMode::Fruits,
+            3 => GameMode::Mania,
+            _ => GameMode::Osu,
+        }
+    }
+
+    /// The mode from any of its common names, like `ctb` or `std`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "0" | "osu" | "std" | "standard" => Some(GameMode::Osu),
+            "1" | "taiko" => Some(GameMode::Taiko),
+            "2" | "fruits" | "catch" | "ctb" => Some(GameMode::Fruits),
+            "3" | "mania" => Some(GameMode::Mania),
+            _ => None,
+        }
+    }
+
+    /// The numeric id of the mode, which is how it's stored on the database.
+    pub fn id(self) -> i32 {
+        match self {
+            GameMode::Osu => 0,
+            GameMode::Taiko => 1,
+            GameMode::Fruits => 2,
+            GameMode::Mania => 3,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Osu => "osu",
+            GameMode::Taiko => "taiko",
+            GameMode::Fruits => "fruits",
+            GameMode::Mania => "mania",
+        }
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            GameMode::Osu => "osu!std",
+            GameMode::Taiko => "osu!taiko",
+            GameMode::Fruits => "osu!catch",
+            GameMode::Mania => "osu!mania",
+        }
+    }
+}
+
+/// A user with its statistics, as returned when requesting a single user.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct OsuUser {
+pub struct UserExtended {
     pub id: u64,
     pub username: String,
+    pub avatar_url: String,
+    pub country_code: String,
+    pub country: Option<Country>,
+    pub cover_url: Option<String>,
+    pub is_supporter: bool,
+    pub join_date: DateTime<Utc>,
+    pub playmode: GameMode,
+    pub profile_colour: Option<String>,
+    pub statistics: UserStatistics,
+    pub rank_history: Option<RankHistory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Country {
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserStatistics {
+    pub level: UserLevel,
+    pub global_rank: Option<u64>,
+    pub country_rank: Option<u64>,
+    pub pp: f64,
+    pub ranked_score: u64,
+    pub hit_accuracy: f64,
+    pub play_count: u64,
+    pub play_time: Option<u64>, // seconds
+    pub total_score: u64,
+    pub total_hits: u64,
+    pub maximum_combo: u64,
+    pub is_ranked: bool,
+    pub grade_counts: GradeCounts,
+    pub count_300: u64,
+    pub count_100: u64,
+    pub count_50: u64,
+    pub count_miss: u64,
 }
 
-pub type Recent = Vec<RecentElement>;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserLevel {
+    pub current: u32,
+    pub progress: u32, // percentage to the next level
+}
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct RecentElement {
+pub struct GradeCounts {
+    pub ss: i64,
+    pub ssh: i64,
+    pub s: i64,
+    pub sh: i64,
+    pub a: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RankHistory {
+    pub mode: GameMode,
+    pub data: Vec<u64>, // the global rank of the last 90 days
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rankings {
+    pub ranking: Vec<RankingEntry>,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RankingEntry {
+    #[serde(flatten)]
+    pub statistics: UserStatistics,
+    pub user: User,
+}
+
+/// A score of a user, as returned on the user scores.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Score {
     pub id: u64,
     pub user_id: u64,
     pub accuracy: f64,
@@ -28,6 +166,48 @@ pub struct RecentElement {
     pub beatmap: Beatmap,
     pub beatmapset: Beatmapset,
     pub user: User,
+    /// How much the score counts towards the pp of the user, only on the best scores.
+    pub weight: Option<ScoreWeight>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScoreWeight {
+    pub percentage: f64,
+    pub pp: f64,
+}
+
+/// The best score of a user on a beatmap.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BeatmapUserScore {
+    pub position: u64,
+    pub score: BeatmapScore,
+}
+
+/// Every score of a user on a beatmap.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BeatmapUserScores {
+    pub scores: Vec<BeatmapScore>,
+}
+
+/// A score on a beatmap leaderboard, which doesn't include the beatmap.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BeatmapScore {
+    pub id: u64,
+    pub user_id: u64,
+    pub accuracy: f64,
+    pub mods: Vec<String>,
+    pub score: u128,
+    pub max_combo: u64,
+    pub perfect: bool,
+    pub statistics: Statistics,
+    pub rank: String,
+    pub created_at: DateTime<Utc>,
+    pub best_id: Option<u64>,
+    pub pp: Option<f64>,
+    pub mode: String,
+    pub replay: bool,
+    /// Missing when requesting every score of a user.
+    pub user: Option<User>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -61,6 +241,47 @@ pub struct Beatmap {
     pub checksum: String,
 }
 
+/// A beatmapset along with its beatmaps, as returned when requesting a single beatmapset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BeatmapsetWithMaps {
+    #[serde(flatten)]
+    pub beatmapset: Beatmapset,
+    pub bpm: f64,
+    pub beatmaps: Vec<Beatmap>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BeatmapAttributesData {
+    pub attributes: BeatmapAttributes,
+}
+
+/// The difficulty attributes of a beatmap with some mods,
+/// the ones that are not from the mode of the beatmap are missing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BeatmapAttributes {
+    pub star_rating: f64,
+    pub max_combo: u64,
+    pub aim_difficulty: Option<f64>,
+    pub speed_difficulty: Option<f64>,
+    pub flashlight_difficulty: Option<f64>,
+    pub approach_rate: Option<f64>,
+    pub overall_difficulty: Option<f64>,
+    pub stamina_difficulty: Option<f64>,
+    pub rhythm_difficulty: Option<f64>,
+    pub colour_difficulty: Option<f64>,
+    pub great_hit_window: Option<f64>,
+    pub score_multiplier: Option<f64>,
+}
+
+/// A beatmap along with its beatmapset, as returned when requesting a single beatmap.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BeatmapWithSet {
+    #[serde(flatten)]
+    pub beatmap: Beatmap,
+    pub beatmapset: Beatmapset,
+    pub max_combo: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Beatmapset {
     pub artist: String,
@@ -0,0 +1,571 @@
+//! Previews of the osu! beatmap, beatmapset, user and score links sent on a channel,
+//! and of the osu! replay files attached to a message.
+
+use crate::framework_methods::dynamic_prefix;
+use crate::global_data::DatabasePool;
+use crate::utils::basic_functions::capitalize_first;
+use crate::utils::music::format_duration;
+use crate::utils::osu::{
+    accuracy_statistics, get_mods_short, mode_accuracy, mode_hits, parse_beatmap_id, PpCalculation,
+};
+use crate::utils::osu_api::{Error, OsuApi};
+use crate::utils::osu_model::GameMode;
+use crate::utils::osu_replay::{hit_errors, Replay};
+
+use num_format::{Locale, ToFormattedString};
+use reqwest::Url;
+use sqlx::PgPool;
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use serenity::{
+    builder::CreateEmbed,
+    http::AttachmentType,
+    model::channel::{Attachment, Message},
+    prelude::Context,
+    utils::Colour,
+};
+use tokio::task::spawn_blocking;
+
+// Only the first links get a preview, so a message full of links doesn't flood the channel.
+const MAX_PREVIEWS: usize = 3;
+
+/// Replays are a few hundred KB at most, so bigger files are not downloaded.
+pub const MAX_REPLAY_SIZE: u64 = 5 * 1024 * 1024;
+
+/// An osu! link that can be previewed.
+#[derive(Debug, Clone, PartialEq)]
+enum OsuLink {
+    Beatmap(u64),
+    Beatmapset(u64),
+    User(String),
+    Score(GameMode, u64),
+}
+
+impl OsuLink {
+    fn parse(input: &str) -> Option<Self> {
+        // Links inside `<>` had their embed suppressed on purpose.
+        let url = Url::parse(input).ok()?;
+
+        // Other subdomains are the images, thumbnails and audio previews.
+        if url.host_str()? != "osu.ppy.sh" {
+            return None;
+        }
+
+        if let Some(x) = parse_beatmap_id(input) {
+            return Some(OsuLink::Beatmap(x));
+        }
+
+        let segments = url
+            .path_segments()?
+            .filter(|i| !i.is_empty())
+            .collect::<Vec<&str>>();
+
+        match segments.as_slice() {
+            ["beatmapsets", id] | ["s", id] => id.parse().ok().map(OsuLink::Beatmapset),
+            ["users", user, ..] | ["u", user, ..] => Some(OsuLink::User(user.to_string())),
+            ["scores", mode, id] => {
+                Some(OsuLink::Score(GameMode::from_name(mode)?, id.parse().ok()?))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Checks if the osu! links sent on a channel get previews.
+/// The setting of the channel is used over the one of the guild, and they are enabled by default.
+pub async fn osu_previews_enabled(pool: &PgPool, guild_id: u64, channel_id: u64) -> bool {
+    match sqlx::query!(
+        "SELECT enabled FROM osu_link_previews WHERE target_id = $1 OR target_id = $2 ORDER BY is_guild LIMIT 1",
+        channel_id as i64,
+        guild_id as i64
+    )
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(x) => x.map(|i| i.enabled).unwrap_or(true),
+        Err(why) => {
+            error!("Error quering Database: {}", why);
+            false
+        }
+    }
+}
+
+/// Checks if a message invokes a command, with the prefix of the guild or a mention of the bot.
+/// Commands like `mapp <link>` already reply about the link, so they don't get a preview.
+pub async fn is_command(ctx: &Context, msg: &Message) -> bool {
+    let content = msg.content.to_lowercase();
+
+    if let Some(prefix) = dynamic_prefix(ctx, msg).await {
+        if content.starts_with(&prefix.to_lowercase()) {
+            return true;
+        }
+    }
+
+    let bot_id = ctx.cache.current_user_id().await;
+    content.starts_with(&format!("<@{}>", bot_id))
+        || content.starts_with(&format!("<@!{}>", bot_id))
+}
+
+/// Replies to a message with the previews of the osu! links it has.
+pub async fn preview_osu_links(ctx: &Context, msg: &Message) -> Result<(), Error> {
+    let guild_id = if let Some(x) = msg.guild_id {
+        x
+    } else {
+        return Ok(());
+    };
+
+    if is_command(ctx, msg).await {
+        return Ok(());
+    }
+
+    let mut links = Vec::new();
+    for link in msg.content.split_whitespace().filter_map(OsuLink::parse) {
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+
+    if links.is_empty() {
+        return Ok(());
+    }
+
+    let pool = {
+        let data_read = ctx.data.read().await;
+        data_read.get::<DatabasePool>().unwrap().clone()
+    };
+
+    if !osu_previews_enabled(&pool, guild_id.0, msg.channel_id.0).await {
+        return Ok(());
+    }
+
+    let api = OsuApi::from_context(ctx).await.background();
+
+    let mut embeds = Vec::new();
+    for link in links.into_iter().take(MAX_PREVIEWS) {
+        let embed = match &link {
+            OsuLink::Beatmap(id) => beatmap_preview(&api, *id).await,
+            OsuLink::Beatmapset(id) => beatmapset_preview(&api, *id).await,
+            OsuLink::User(user) => user_preview(&api, user).await,
+            OsuLink::Score(mode, id) => score_preview(&api, *mode, *id).await,
+        };
+
+        match embed {
+            Ok(Some(x)) => embeds.push(x),
+            Ok(None) => (),
+            // A failed link doesn't stop the previews of the rest of them.
+            Err(why) => warn!("Error previewing the osu! link {:?}: {}", link, why),
+        }
+    }
+
+    if embeds.is_empty() {
+        return Ok(());
+    }
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.set_embeds(embeds);
+            m.reference_message(msg);
+            m.allowed_mentions(|am| am.replied_user(false))
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn beatmap_preview(api: &OsuApi, beatmap_id: u64) -> Result<Option<CreateEmbed>, Error> {
+    let beatmap = if let Some(x) = api.beatmap(beatmap_id).await? {
+        x
+    } else {
+        return Ok(None);
+    };
+
+    let mode = GameMode::from_id(beatmap.beatmap.mode_int as i32);
+
+    let attributes = if let Some(x) = api.beatmap_attributes(beatmap_id, &[], Some(mode)).await? {
+        x
+    } else {
+        return Ok(None);
+    };
+
+    let map = &beatmap.beatmap;
+    let set = &beatmap.beatmapset;
+
+    // osu!mania pp are given by the score, so a 95% score is taken as 950,000.
+    let objects = match mode {
+        GameMode::Osu => map.count_circles + map.count_sliders + map.count_spinners,
+        GameMode::Mania => map.count_circles + map.count_sliders,
+        _ => attributes.max_combo,
+    };
+    let pp = |accuracy: f64, score: u128| {
+        PpCalculation::from_score(
+            mode,
+            map,
+            &attributes,
+            &[],
+            &accuracy_statistics(mode, objects, accuracy, 0),
+            attributes.max_combo,
+            score,
+        )
+        .calculate()
+    };
+
+    let mut e = CreateEmbed::default();
+
+    e.color(Colour::new(set.id as u32 & 0xFFFFFF));
+    e.author(|a| {
+        a.name(format!("Mapped by {}", set.creator));
+        a.icon_url(format!("https://a.ppy.sh/{}", set.user_id));
+        a.url(format!("https://osu.ppy.sh/u/{}", set.user_id))
+    });
+    e.title(format!("{} - {} [{}]", set.artist, set.title, map.version));
+    e.url(format!("https://osu.ppy.sh/b/{}", map.id));
+    e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", set.id));
+    e.description(format!(
+        "**{:.2}\\*** {} ┇ {}\n**{}** ┇ **{} BPM** ┇ **x{}**\nAR {:.1} | OD {:.1} | CS {:.1} | HP {:.1}\n**SS**: {:.2}pp ┇ **95%**: {:.2}pp",
+        attributes.star_rating,
+        mode.display_name(),
+        capitalize_first(&map.status),
+        format_duration(map.total_length * 1000),
+        map.bpm,
+        attributes.max_combo,
+        map.ar,
+        map.accuracy,
+        map.cs,
+        map.drain,
+        pp(100.0, 1_000_000),
+        pp(95.0, 950_000),
+    ));
+
+    Ok(Some(e))
+}
+
+async fn beatmapset_preview(
+    api: &OsuApi,
+    beatmapset_id: u64,
+) -> Result<Option<CreateEmbed>, Error> {
+    let set = if let Some(x) = api.beatmapset(beatmapset_id).await? {
+        x
+    } else {
+        return Ok(None);
+    };
+
+    let mut beatmaps = set.beatmaps.iter().collect::<Vec<_>>();
+    beatmaps.sort_by(|a, b| {
+        a.mode_int.cmp(&b.mode_int).then(
+            a.difficulty_rating
+                .partial_cmp(&b.difficulty_rating)
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
+    });
+
+    let mut difficulties = beatmaps
+        .iter()
+        .take(10)
+        .map(|i| {
+            format!(
+                "**{:.2}\\*** [{}](https://osu.ppy.sh/b/{}) ({})",
+                i.difficulty_rating,
+                i.version,
+                i.id,
+                GameMode::from_id(i.mode_int as i32).display_name()
+            )
+        })
+        .collect::<Vec<String>>();
+    if beatmaps.len() > 10 {
+        difficulties.push(format!("And {} more...", beatmaps.len() - 10));
+    }
+
+    let length = beatmaps.iter().map(|i| i.total_length).max().unwrap_or(0);
+    let info = &set.beatmapset;
+
+    let mut e = CreateEmbed::default();
+
+    e.color(Colour::new(info.id as u32 & 0xFFFFFF));
+    e.author(|a| {
+        a.name(format!("Mapped by {}", info.creator));
+        a.icon_url(format!("https://a.ppy.sh/{}", info.user_id));
+        a.url(format!("https://osu.ppy.sh/u/{}", info.user_id))
+    });
+    e.title(format!("{} - {}", info.artist, info.title));
+    e.url(format!("https://osu.ppy.sh/beatmapsets/{}", info.id));
+    e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", info.id));
+    e.description(format!(
+        "{} ┇ **{}** ┇ **{} BPM** ┇ ❤ {}\n\n{}",
+        capitalize_first(&info.status),
+        format_duration(length * 1000),
+        set.bpm,
+        info.favourite_count.to_formatted_string(&Locale::en),
+        difficulties.join("\n")
+    ));
+
+    Ok(Some(e))
+}
+
+async fn user_preview(api: &OsuApi, user: &str) -> Result<Option<CreateEmbed>, Error> {
+    let user = match user.parse::<u64>() {
+        Ok(id) => api.user_by_id(id, None).await?,
+        Err(_) => api.user_by_name(user, None).await?,
+    };
+
+    let user = if let Some(x) = user {
+        x
+    } else {
+        return Ok(None);
+    };
+
+    let stats = &user.statistics;
+    let format_rank = |rank: Option<u64>| {
+        rank.map(|i| format!("#{}", i.to_formatted_string(&Locale::en)))
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    let mut e = CreateEmbed::default();
+
+    e.color(Colour::new(user.id as u32 & 0xFFFFFF));
+    e.author(|a| {
+        a.name(format!(
+            "{} ({})",
+            user.username,
+            user.playmode.display_name()
+        ));
+        a.icon_url(format!("https://raw.githubusercontent.com/stevenrskelton/flag-icon/master/png/75/country-squared/{}.png", user.country_code.to_lowercase()));
+        a.url(format!("https://osu.ppy.sh/u/{}", user.id))
+    });
+    e.thumbnail(&user.avatar_url);
+    e.description(format!(
+        "**{:.2}pp** ┇ Global: **{}** ┇ {}: **{}**\n**{:.2}%** ┇ **{}** Plays ┇ **L{}**",
+        stats.pp,
+        format_rank(stats.global_rank),
+        user.country_code,
+        format_rank(stats.country_rank),
+        stats.hit_accuracy,
+        stats.play_count.to_formatted_string(&Locale::en),
+        stats.level.current,
+    ));
+    e.timestamp(&user.join_date);
+
+    Ok(Some(e))
+}
+
+async fn score_preview(
+    api: &OsuApi,
+    mode: GameMode,
+    score_id: u64,
+) -> Result<Option<CreateEmbed>, Error> {
+    let score = if let Some(x) = api.score(mode, score_id).await? {
+        x
+    } else {
+        return Ok(None);
+    };
+
+    let mods = get_mods_short(&score.mods);
+
+    let mut e = CreateEmbed::default();
+
+    e.color(Colour::new(score.user.id as u32 & 0xFFFFFF));
+    e.author(|a| {
+        a.name(format!("Score of {}", score.user.username));
+        a.icon_url(&score.user.avatar_url);
+        a.url(format!("https://osu.ppy.sh/u/{}", score.user.id))
+    });
+    e.title(format!(
+        "{} - {} [{}]",
+        score.beatmapset.artist, score.beatmapset.title, score.beatmap.version
+    ));
+    e.url(format!("https://osu.ppy.sh/b/{}", score.beatmap.id));
+    e.thumbnail(format!(
+        "https://b.ppy.sh/thumb/{}l.jpg",
+        score.beatmapset.id
+    ));
+    e.description(format!(
+        "**{}** ┇ **+{}** ┇ **{}**\n**{}** ┇ **x{}** ┇ **{:.2}%**\n{}",
+        score
+            .pp
+            .map(|i| format!("{:.2}pp", i))
+            .unwrap_or_else(|| "No pp".to_string()),
+        mods,
+        score.rank.to_uppercase(),
+        score.score.to_formatted_string(&Locale::en),
+        score.max_combo,
+        mode_accuracy(mode, &score.statistics),
+        mode_hits(mode, &score.statistics),
+    ));
+    e.footer(|f| {
+        f.text(format!(
+            "{:.2}* | {}",
+            score.beatmap.difficulty_rating,
+            mode.display_name()
+        ));
+        f.icon_url(format!(
+            "https://s.ppy.sh/images/{}.png",
+            score.rank.to_uppercase()
+        ))
+    });
+    e.timestamp(&score.created_at);
+
+    Ok(Some(e))
+}
+
+/// Checks if an attachment has the extension of an osu! replay.
+pub fn is_osu_replay(attachment: &Attachment) -> bool {
+    attachment.filename.to_lowercase().ends_with(".osr")
+}
+
+/// Replies to a message with the summary of the osu! replays attached to it.
+pub async fn preview_osu_replays(ctx: &Context, msg: &Message) -> Result<(), Error> {
+    let replays = msg
+        .attachments
+        .iter()
+        .filter(|i| is_osu_replay(i) && i.size <= MAX_REPLAY_SIZE)
+        .take(MAX_PREVIEWS)
+        .collect::<Vec<&Attachment>>();
+
+    // The replays attached to the `replay` command are read by the command.
+    if replays.is_empty() || is_command(ctx, msg).await {
+        return Ok(());
+    }
+
+    if let Some(guild_id) = msg.guild_id {
+        let pool = {
+            let data_read = ctx.data.read().await;
+            data_read.get::<DatabasePool>().unwrap().clone()
+        };
+
+        if !osu_previews_enabled(&pool, guild_id.0, msg.channel_id.0).await {
+            return Ok(());
+        }
+    }
+
+    let api = OsuApi::from_context(ctx).await.background();
+
+    for attachment in replays {
+        let data = attachment.download().await?;
+        send_replay_summary(ctx, msg, &api, data).await?;
+    }
+
+    Ok(())
+}
+
+/// Replies to a message with the summary of an osu! replay file,
+/// with its pp, unstable rate and the histogram of its hit errors.
+pub async fn send_replay_summary(
+    ctx: &Context,
+    msg: &Message,
+    api: &OsuApi,
+    data: Vec<u8>,
+) -> Result<(), Error> {
+    let replay = Arc::new(spawn_blocking(move || Replay::parse(&data)).await??);
+
+    let mode = replay.mode;
+    let mods = replay.mod_names();
+    let short_mods = get_mods_short(&mods);
+
+    // The names can have spaces, so they are percent encoded on the profile url.
+    let mut profile_url = Url::parse("https://osu.ppy.sh/users")?;
+    if let Ok(mut segments) = profile_url.path_segments_mut() {
+        segments.push(&replay.player_name);
+    }
+
+    let mut e = CreateEmbed::default();
+    let mut histogram = None;
+
+    e.author(|a| {
+        a.name(format!(
+            "Replay of {} ({})",
+            replay.player_name,
+            mode.display_name()
+        ));
+        a.url(profile_url)
+    });
+    e.timestamp(&replay.timestamp);
+
+    let mut summary = format!(
+        "**+{}** ┇ **{}**\n**x{}** ┇ **{:.2}%**\n{}",
+        short_mods,
+        replay.score.to_formatted_string(&Locale::en),
+        replay.max_combo,
+        mode_accuracy(mode, &replay.statistics),
+        mode_hits(mode, &replay.statistics),
+    );
+
+    // Replays of unsubmitted beatmaps can't be looked up, so only the replay itself is shown.
+    if let Some(beatmap) = api.beatmap_by_checksum(&replay.beatmap_hash).await? {
+        let map = &beatmap.beatmap;
+        let set = &beatmap.beatmapset;
+
+        e.color(Colour::new(set.id as u32 & 0xFFFFFF));
+        e.title(format!("{} - {} [{}]", set.artist, set.title, map.version));
+        e.url(format!("https://osu.ppy.sh/b/{}", map.id));
+        e.thumbnail(format!("https://b.ppy.sh/thumb/{}l.jpg", set.id));
+
+        if let Some(attributes) = api.beatmap_attributes(map.id, &mods, Some(mode)).await? {
+            let pp = PpCalculation::from_score(
+                mode,
+                map,
+                &attributes,
+                &mods,
+                &replay.statistics,
+                replay.max_combo as u64,
+                replay.score as u128,
+            )
+            .calculate();
+
+            summary = format!(
+                "**{:.2}pp** ┇ {:.2}\\* ┇ max x{}\n{}",
+                pp, attributes.star_rating, attributes.max_combo, summary
+            );
+        }
+
+        if mode == GameMode::Osu {
+            let file = api.beatmap_file(map.id).await?;
+            let hits_replay = Arc::clone(&replay);
+
+            let hits = spawn_blocking(move || -> Result<_, Error> {
+                match hit_errors(&hits_replay, &file) {
+                    Some(hits) => Ok(Some((hits.unstable_rate(), hits.mean(), hits.histogram()?))),
+                    None => Ok(None),
+                }
+            })
+            .await??;
+
+            if let Some((unstable_rate, mean, image)) = hits {
+                summary = format!(
+                    "{}\n**{:.2}** UR ┇ {:+.2}ms mean",
+                    summary, unstable_rate, mean
+                );
+                histogram = Some(image);
+            }
+        }
+    } else {
+        e.title("Unknown beatmap");
+    }
+
+    if let Some(health) = replay.lowest_health() {
+        summary = format!("{}\nLowest HP: **{:.0}%**", summary, health * 100.0);
+    }
+
+    e.description(summary);
+
+    if histogram.is_some() {
+        e.image("attachment://hit_errors.png");
+        e.footer(|f| f.text("Early hits on the left, late hits on the right"));
+    }
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            if let Some(bytes) = histogram {
+                m.add_file(AttachmentType::Bytes {
+                    data: Cow::from(bytes),
+                    filename: "hit_errors.png".to_string(),
+                });
+            }
+            m.set_embed(e);
+            m.reference_message(msg);
+            m.allowed_mentions(|am| am.replied_user(false))
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,541 @@
+//! A parser for the osu! replay files (.osr), and the analysis of the hits of a replay.
+
+use crate::utils::osu::bitwhise_mods::ShortMods;
+use crate::utils::osu_api::Error;
+use crate::utils::osu_model::{GameMode, Statistics};
+
+use std::io::{self, Cursor, Write};
+
+use chrono::{DateTime, TimeZone, Utc};
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgba};
+
+// The ticks of .NET start on the year 1, and there's 10 million of them per second.
+const TICKS_UNIX_EPOCH_SECONDS: i64 = 62_135_596_800;
+const TICKS_PER_SECOND: i64 = 10_000_000;
+
+// The first version of the game where the online score id is a long.
+const LONG_SCORE_ID_VERSION: i32 = 20140721;
+
+// The last frame holds the seed of the random number generator instead of a position.
+const SEED_FRAME_DELTA: i64 = -12345;
+
+// The frames of the longest replays are a few MB once decompressed.
+const MAX_FRAMES_SIZE: usize = 64 * 1024 * 1024;
+
+// The names of the mods on the API, which don't always match the names of the flags.
+const MOD_ACRONYMS: [(ShortMods, &str); 31] = [
+    (ShortMods::NF, "NF"),
+    (ShortMods::EZ, "EZ"),
+    (ShortMods::TD, "TD"),
+    (ShortMods::HD, "HD"),
+    (ShortMods::HR, "HR"),
+    (ShortMods::SD, "SD"),
+    (ShortMods::DT, "DT"),
+    (ShortMods::RX, "RX"),
+    (ShortMods::HT, "HT"),
+    (ShortMods::NC, "NC"),
+    (ShortMods::FL, "FL"),
+    (ShortMods::AT, "AT"),
+    (ShortMods::SO, "SO"),
+    (ShortMods::AP, "AP"),
+    (ShortMods::PF, "PF"),
+    (ShortMods::K4, "4K"),
+    (ShortMods::K5, "5K"),
+    (ShortMods::K6, "6K"),
+    (ShortMods::K7, "7K"),
+    (ShortMods::K8, "8K"),
+    (ShortMods::FI, "FI"),
+    (ShortMods::RD, "RD"),
+    (ShortMods::CN, "CN"),
+    (ShortMods::TP, "TP"),
+    (ShortMods::K9, "9K"),
+    (ShortMods::CO, "CP"),
+    (ShortMods::K1, "1K"),
+    (ShortMods::K3, "3K"),
+    (ShortMods::K2, "2K"),
+    (ShortMods::V2, "V2"),
+    (ShortMods::MR, "MR"),
+];
+
+const HISTOGRAM_WIDTH: u32 = 640;
+const HISTOGRAM_HEIGHT: u32 = 240;
+const HISTOGRAM_BINS: u32 = 64;
+
+/// A parsed osu! replay file.
+#[derive(Debug)]
+pub struct Replay {
+    pub mode: GameMode,
+    pub version: i32,
+    pub beatmap_hash: String,
+    pub player_name: String,
+    pub replay_hash: String,
+    pub statistics: Statistics,
+    pub score: i32,
+    pub max_combo: u16,
+    pub perfect: bool,
+    pub mods: ShortMods,
+    /// The health of the player over time, as the milliseconds and the health from 0 to 1.
+    pub life_bar: Vec<(i32, f32)>,
+    pub timestamp: DateTime<Utc>,
+    pub frames: Vec<ReplayFrame>,
+    pub online_score_id: i64,
+}
+
+/// The position of the cursor and the pressed keys at a point of the replay.
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayFrame {
+    /// The milliseconds since the start of the beatmap.
+    pub time: i64,
+    pub x: f32,
+    pub y: f32,
+    /// The bitwise of the pressed keys, M1, M2, K1, K2 and Smoke.
+    pub keys: u32,
+}
+
+// Reads the little endian values and the strings of the game from the replay file.
+struct ReplayReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ReplayReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|i| *i <= self.data.len())
+            .ok_or("The replay file is incomplete.")?;
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn short(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn int(&mut self) -> Result<i32, Error> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    fn long(&mut self) -> Result<i64, Error> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn uleb128(&mut self) -> Result<usize, Error> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as usize) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+
+            shift += 7;
+            if shift >= 64 {
+                return Err("Invalid string length on the replay file.".into());
+            }
+        }
+    }
+
+    // The strings are either empty, or an UTF-8 string with its length.
+    fn string(&mut self) -> Result<String, Error> {
+        match self.byte()? {
+            0x00 => Ok(String::new()),
+            0x0b => {
+                let len = self.uleb128()?;
+                Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+            }
+            _ => Err("Invalid string on the replay file.".into()),
+        }
+    }
+}
+
+impl Replay {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = ReplayReader { data, position: 0 };
+
+        let mode = match reader.byte()? {
+            x @ 0..=3 => GameMode::from_id(x as i32),
+            _ => return Err("The file is not an osu! replay.".into()),
+        };
+
+        let version = reader.int()?;
+        let beatmap_hash = reader.string()?;
+        let player_name = reader.string()?;
+        let replay_hash = reader.string()?;
+
+        let count_300 = reader.short()? as u64;
+        let count_100 = reader.short()? as u64;
+        let count_50 = reader.short()? as u64;
+        let count_geki = reader.short()? as u64;
+        let count_katu = reader.short()? as u64;
+        let count_miss = reader.short()? as u64;
+
+        let score = reader.int()?;
+        let max_combo = reader.short()?;
+        let perfect = reader.byte()? != 0;
+        let mods = ShortMods::from_bits_truncate(reader.int()? as u32);
+        let life_bar = parse_life_bar(&reader.string()?);
+
+        let ticks = reader.long()?;
+        let timestamp = Utc
+            .timestamp_opt(ticks / TICKS_PER_SECOND - TICKS_UNIX_EPOCH_SECONDS, 0)
+            .single()
+            .ok_or("Invalid date on the replay file.")?;
+
+        let compressed_len = reader.int()?;
+        if compressed_len < 0 {
+            return Err("Invalid replay data length.".into());
+        }
+        let frames = parse_frames(reader.bytes(compressed_len as usize)?)?;
+
+        // Replays that were not submitted don't have an id.
+        let online_score_id = if version >= LONG_SCORE_ID_VERSION {
+            reader.long().unwrap_or_default()
+        } else {
+            reader.int().unwrap_or_default() as i64
+        };
+
+        Ok(Self {
+            mode,
+            version,
+            beatmap_hash,
+            player_name,
+            replay_hash,
+            statistics: Statistics {
+                count_50,
+                count_100,
+                count_300,
+                count_geki,
+                count_katu,
+                count_miss,
+            },
+            score,
+            max_combo,
+            perfect,
+            mods,
+            life_bar,
+            timestamp,
+            frames,
+            online_score_id,
+        })
+    }
+
+    /// The acronyms of the mods of the replay, as the API names them.
+    pub fn mod_names(&self) -> Vec<String> {
+        let mut mods = self.mods;
+
+        // Nightcore and Perfect are always along with the mods they are based on.
+        if mods.contains(ShortMods::NC) {
+            mods.remove(ShortMods::DT);
+        }
+        if mods.contains(ShortMods::PF) {
+            mods.remove(ShortMods::SD);
+        }
+
+        MOD_ACRONYMS
+            .iter()
+            .filter(|(x, _)| mods.contains(*x))
+            .map(|(_, acronym)| acronym.to_string())
+            .collect()
+    }
+
+    /// The speed the beatmap was played at.
+    pub fn rate(&self) -> f64 {
+        if self.mods.intersects(ShortMods::DT | ShortMods::NC) {
+            1.5
+        } else if self.mods.contains(ShortMods::HT) {
+            0.75
+        } else {
+            1.0
+        }
+    }
+
+    /// The lowest health the player had, from 0 to 1.
+    pub fn lowest_health(&self) -> Option<f32> {
+        self.life_bar
+            .iter()
+            .map(|(_, health)| *health)
+            .fold(None, |min, i| Some(min.map_or(i, |min: f32| min.min(i))))
+    }
+}
+
+fn parse_life_bar(input: &str) -> Vec<(i32, f32)> {
+    input
+        .split(',')
+        .filter_map(|i| {
+            let (time, health) = i.split_once('|')?;
+            Some((time.parse().ok()?, health.parse().ok()?))
+        })
+        .collect()
+}
+
+// Errors past `limit` bytes, so a small file can't decompress into all the memory.
+struct LimitedWriter {
+    data: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len() + buf.len() > self.limit {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "The replay data is too big.",
+            ));
+        }
+
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The frames are compressed with LZMA, as `time since the last frame|x|y|keys` separated by commas.
+fn parse_frames(compressed: &[u8]) -> Result<Vec<ReplayFrame>, Error> {
+    if compressed.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut decompressed = LimitedWriter {
+        data: Vec::new(),
+        limit: MAX_FRAMES_SIZE,
+    };
+    lzma_rs::lzma_decompress(&mut Cursor::new(compressed), &mut decompressed)
+        .map_err(|why| format!("Unable to decompress the replay data: {:?}", why))?;
+
+    let mut time = 0;
+    let mut frames = Vec::new();
+
+    for frame in String::from_utf8_lossy(&decompressed.data).split(',') {
+        let values = frame.split('|').collect::<Vec<&str>>();
+        if values.len() != 4 {
+            continue;
+        }
+
+        let delta = values[0].parse::<i64>().unwrap_or_default();
+        if delta == SEED_FRAME_DELTA {
+            continue;
+        }
+        time += delta;
+
+        frames.push(ReplayFrame {
+            time,
+            x: values[1].parse().unwrap_or_default(),
+            y: values[2].parse().unwrap_or_default(),
+            keys: values[3].parse::<f64>().unwrap_or_default() as u32,
+        });
+    }
+
+    Ok(frames)
+}
+
+/// How early or late the objects of a beatmap were hit on a replay.
+#[derive(Debug)]
+pub struct HitErrors {
+    /// The milliseconds between each object and the press that hit it.
+    pub errors: Vec<f64>,
+    pub hit_window_300: f64,
+    pub hit_window_100: f64,
+    pub hit_window_50: f64,
+    pub rate: f64,
+}
+
+impl HitErrors {
+    pub fn mean(&self) -> f64 {
+        self.errors.iter().sum::<f64>() / self.errors.len() as f64
+    }
+
+    /// The standard deviation of the hit errors times 10, on the real time of the playback rate.
+    pub fn unstable_rate(&self) -> f64 {
+        let mean = self.mean();
+        let variance =
+            self.errors.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / self.errors.len() as f64;
+
+        variance.sqrt() * 10.0 / self.rate
+    }
+
+    /// Draws the histogram of the hit errors as a png,
+    /// with the early hits on the left, and the colours of the hit windows.
+    pub fn histogram(&self) -> Result<Vec<u8>, Error> {
+        let mut image =
+            ImageBuffer::from_pixel(HISTOGRAM_WIDTH, HISTOGRAM_HEIGHT, Rgba([47, 49, 54, 255]));
+
+        let bin_size = self.hit_window_50 * 2.0 / HISTOGRAM_BINS as f64;
+        let mut bins = vec![0_u32; HISTOGRAM_BINS as usize];
+
+        for error in &self.errors {
+            let bin = ((error + self.hit_window_50) / bin_size).floor().max(0.0) as usize;
+            bins[bin.min(HISTOGRAM_BINS as usize - 1)] += 1;
+        }
+
+        let highest = bins.iter().copied().max().unwrap_or(0).max(1);
+        let bar_width = HISTOGRAM_WIDTH / HISTOGRAM_BINS;
+        let max_height = HISTOGRAM_HEIGHT - 20;
+
+        for (index, count) in bins.iter().enumerate() {
+            let center = (index as f64 + 0.5) * bin_size - self.hit_window_50;
+
+            let colour = if center.abs() <= self.hit_window_300 {
+                Rgba([102, 204, 255, 255])
+            } else if center.abs() <= self.hit_window_100 {
+                Rgba([136, 179, 0, 255])
+            } else {
+                Rgba([255, 204, 34, 255])
+            };
+
+            let height = count * max_height / highest;
+            let left = index as u32 * bar_width;
+
+            for x in left + 1..left + bar_width - 1 {
+                for y in HISTOGRAM_HEIGHT - 10 - height..HISTOGRAM_HEIGHT - 10 {
+                    image.put_pixel(x, y, colour);
+                }
+            }
+        }
+
+        // A line on the perfect timing.
+        for y in 0..HISTOGRAM_HEIGHT {
+            image.put_pixel(HISTOGRAM_WIDTH / 2, y, Rgba([255, 255, 255, 255]));
+        }
+
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut bytes, ImageOutputFormat::Png)?;
+
+        Ok(bytes)
+    }
+}
+
+/// Matches the key presses of an osu!std replay with the circles and sliders of its beatmap file.
+///
+/// Every press can only hit the first object under the cursor within the 50 hit window,
+/// which is close enough to the game to know the timing of the player.
+pub fn hit_errors(replay: &Replay, beatmap_file: &str) -> Option<HitErrors> {
+    if replay.mode != GameMode::Osu {
+        return None;
+    }
+
+    let mut od = 5.0;
+    let mut cs = 5.0;
+    let mut objects = Vec::new();
+    let mut section = "";
+
+    for line in beatmap_file.lines() {
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            section = line;
+            continue;
+        }
+
+        match section {
+            "[Difficulty]" => {
+                if let Some(x) = line.strip_prefix("OverallDifficulty:") {
+                    od = x.trim().parse().unwrap_or(od);
+                } else if let Some(x) = line.strip_prefix("CircleSize:") {
+                    cs = x.trim().parse().unwrap_or(cs);
+                }
+            }
+            "[HitObjects]" => {
+                let values = line.split(',').collect::<Vec<&str>>();
+                if values.len() < 4 {
+                    continue;
+                }
+
+                // Spinners don't need to be clicked on time.
+                if values[3].parse::<u32>().unwrap_or_default() & 8 != 0 {
+                    continue;
+                }
+
+                if let (Ok(x), Ok(y), Ok(time)) = (
+                    values[0].parse::<f64>(),
+                    values[1].parse::<f64>(),
+                    values[2].parse::<f64>(),
+                ) {
+                    objects.push((x, y, time));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let hard_rock = replay.mods.contains(ShortMods::HR);
+    if hard_rock {
+        od = (od * 1.4_f64).min(10.0);
+        cs = (cs * 1.3_f64).min(10.0);
+    } else if replay.mods.contains(ShortMods::EZ) {
+        od *= 0.5;
+        cs *= 0.5;
+    }
+
+    let hit_window_50 = 200.0 - 10.0 * od;
+    let radius = 54.4 - 4.48 * cs;
+
+    let mut presses = Vec::new();
+    let mut previous_keys = 0;
+
+    for frame in &replay.frames {
+        // K1 and K2 are always pressed along with M1 and M2.
+        let keys = frame.keys & 3;
+        if keys & !previous_keys != 0 {
+            presses.push(frame);
+        }
+        previous_keys = keys;
+    }
+
+    let mut errors = Vec::new();
+    let mut next_press = 0;
+
+    for (x, y, time) in objects {
+        // Hard Rock flips the beatmap vertically.
+        let y = if hard_rock { 384.0 - y } else { y };
+
+        while next_press < presses.len() && (presses[next_press].time as f64) < time - hit_window_50
+        {
+            next_press += 1;
+        }
+
+        for (index, press) in presses.iter().enumerate().skip(next_press) {
+            if press.time as f64 > time + hit_window_50 {
+                break;
+            }
+
+            let distance = ((press.x as f64 - x).powi(2) + (press.y as f64 - y).powi(2)).sqrt();
+            if distance <= radius {
+                errors.push(press.time as f64 - time);
+                next_press = index + 1;
+                break;
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        return None;
+    }
+
+    Some(HitErrors {
+        errors,
+        hit_window_300: 80.0 - 6.0 * od,
+        hit_window_100: 140.0 - 8.0 * od,
+        hit_window_50,
+        rate: replay.rate(),
+    })
+}